@@ -9,6 +9,7 @@ use nostr_kv::{
 };
 
 use std::{
+    collections::{HashSet, VecDeque},
     marker::PhantomData,
     ops::Bound,
     path::Path,
@@ -60,6 +61,10 @@ pub struct Db {
     t_expiration: Tree,
     // word time
     t_word: Tree,
+    // causal dag from parent and e tags, child id -> parent ids
+    t_parent: Tree,
+    // parent id -> child ids
+    t_child: Tree,
     seq: Arc<AtomicU64>,
 }
 
@@ -171,6 +176,41 @@ impl Db {
             writer.del(&self.t_expiration, IndexKey::encode_time(*t), Some(uid))?;
         }
 
+        // causal edges of the event, edges of its children stay with them
+        for parent in event.causal_parents() {
+            writer.del(&self.t_child, parent, Some(index_event.id()))?;
+        }
+        writer.del(&self.t_parent, index_event.id(), None)?;
+
+        Ok(())
+    }
+
+    fn put_dag(&self, writer: &mut Writer, event: &Event) -> Result<(), Error> {
+        for parent in event.causal_parents() {
+            writer.put(&self.t_parent, event.id(), parent)?;
+            writer.put(&self.t_child, parent, event.id())?;
+        }
+        Ok(())
+    }
+
+    // index the causal edges of events stored before the dag trees existed
+    fn index_dag(&self) -> Result<(), Error> {
+        let mut writer = self.inner.writer()?;
+        if writer.get(&self.t_meta, "dag")?.is_some() {
+            return Ok(());
+        }
+        let uids = writer
+            .iter(&self.t_data)
+            .map(|item| item.map(|(k, _)| k.to_vec()))
+            .collect::<Result<Vec<_>, _>>()?;
+        for uid in uids {
+            let event: Option<Event> = get_event_by_uid(&writer, &self.t_data, &self.t_index, &uid)?;
+            if let Some(event) = event {
+                self.put_dag(&mut writer, &event)?;
+            }
+        }
+        writer.put(&self.t_meta, "dag", "1")?;
+        writer.commit()?;
         Ok(())
     }
 
@@ -248,6 +288,8 @@ impl Db {
             writer.put(&self.t_expiration, IndexKey::encode_time(*t), uid)?;
         }
 
+        self.put_dag(writer, event)?;
+
         // word
         let words = &event.words;
         if !words.is_empty() {
@@ -368,7 +410,7 @@ impl Db {
         let t_data = inner.open_tree(Some("t_data"), integer_default_opts)?;
         let t_meta = inner.open_tree(Some("t_meta"), default_opts)?;
 
-        Ok(Self {
+        let db = Self {
             seq: Arc::new(AtomicU64::new(latest_seq(&inner, &t_data)?)),
             t_data,
             t_meta,
@@ -385,9 +427,13 @@ impl Db {
             t_tag: inner.open_tree(Some("t_tag"), ffi::MDB_DUPSORT | ffi::MDB_DUPFIXED)?,
             t_expiration: inner.open_tree(Some("t_expiration"), integer_index_opts)?,
             t_word: inner.open_tree(Some("t_word"), index_opts)?,
+            t_parent: inner.open_tree(Some("t_parent"), index_opts)?,
+            t_child: inner.open_tree(Some("t_child"), index_opts)?,
 
             inner,
-        })
+        };
+        db.index_dag()?;
+        Ok(db)
     }
 
     pub fn writer(&self) -> Result<Writer> {
@@ -526,6 +572,37 @@ impl Db {
         }
     }
 
+    /// Ids the event follows directly in the causal dag.
+    pub fn parents<K: AsRef<[u8]>, T: Transaction>(&self, txn: &T, event_id: K) -> Result<Vec<[u8; 32]>> {
+        dag_edges(txn, &self.t_parent, event_id.as_ref())
+    }
+
+    /// Ids of the events that follow the event directly in the causal dag.
+    pub fn children<K: AsRef<[u8]>, T: Transaction>(&self, txn: &T, event_id: K) -> Result<Vec<[u8; 32]>> {
+        dag_edges(txn, &self.t_child, event_id.as_ref())
+    }
+
+    /// Walk the ancestors of an event breadth first, at most `max_depth` edges away.
+    /// Ancestors do not have to be stored.
+    pub fn ancestors<'txn, T: Transaction>(
+        &self,
+        txn: &'txn T,
+        event_id: &[u8; 32],
+        max_depth: usize,
+    ) -> DagIter<'txn, T> {
+        DagIter::new(txn, self.t_parent.clone(), event_id, max_depth)
+    }
+
+    /// Walk the descendants of an event breadth first, at most `max_depth` edges away.
+    pub fn descendants<'txn, T: Transaction>(
+        &self,
+        txn: &'txn T,
+        event_id: &[u8; 32],
+        max_depth: usize,
+    ) -> DagIter<'txn, T> {
+        DagIter::new(txn, self.t_child.clone(), event_id, max_depth)
+    }
+
     pub fn batch_put<II, N>(&self, events: II) -> Result<usize>
     where
         II: IntoIterator<Item = N>,
@@ -1111,9 +1188,68 @@ where
     }
 }
 
+fn dag_edges<T: Transaction>(txn: &T, tree: &Tree, event_id: &[u8]) -> Result<Vec<[u8; 32]>> {
+    let mut edges = vec![];
+    for item in txn.iter_from(tree, Bound::Included(event_id), false) {
+        let (k, v) = item?;
+        if k != event_id {
+            break;
+        }
+        edges.push(v.try_into()?);
+    }
+    Ok(edges)
+}
+
+/// Breadth first walk of the causal dag, yields every event once with its depth.
+pub struct DagIter<'txn, T> {
+    txn: &'txn T,
+    tree: Tree,
+    max_depth: usize,
+    queue: VecDeque<([u8; 32], usize)>,
+    seen: HashSet<[u8; 32]>,
+}
+
+impl<'txn, T: Transaction> DagIter<'txn, T> {
+    fn new(txn: &'txn T, tree: Tree, event_id: &[u8; 32], max_depth: usize) -> Self {
+        Self {
+            txn,
+            tree,
+            max_depth,
+            queue: VecDeque::from([(*event_id, 0)]),
+            seen: HashSet::from([*event_id]),
+        }
+    }
+
+    fn next_inner(&mut self) -> Result<Option<([u8; 32], usize)>> {
+        while let Some((id, depth)) = self.queue.pop_front() {
+            if depth < self.max_depth {
+                for next in dag_edges(self.txn, &self.tree, &id)? {
+                    if self.seen.insert(next) {
+                        self.queue.push_back((next, depth + 1));
+                    }
+                }
+            }
+            // the start event is not its own ancestor
+            if depth > 0 {
+                return Ok(Some((id, depth)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<'txn, T: Transaction> Iterator for DagIter<'txn, T> {
+    type Item = Result<([u8; 32], usize), Error>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_inner().transpose()
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::upper;
+    use super::{upper, Db};
+    use crate::Event;
+    use anyhow::Result;
 
     #[test]
     pub fn test_upper_fn() {
@@ -1126,4 +1262,56 @@ mod tests {
         assert_eq!(upper(vec![1, 2, 3, 255, 5]), Some(vec![1, 2, 3, 255, 6]));
         assert_eq!(upper(vec![255, 2, 3, 4, 5]), Some(vec![255, 2, 3, 4, 6]));
     }
+
+    fn event(id: u8, tags: Vec<Vec<String>>) -> Result<Event> {
+        Ok(Event::new([id; 32], [1; 32], 10, 1, tags, "".to_owned(), [0; 64])?)
+    }
+
+    fn walk(it: super::DagIter<'_, impl nostr_kv::lmdb::Transaction>) -> Result<Vec<(u8, usize)>> {
+        let mut ids = it.map(|r| r.map(|(id, depth)| (id[0], depth))).collect::<Result<Vec<_>, _>>()?;
+        ids.sort();
+        Ok(ids)
+    }
+
+    #[test]
+    pub fn test_causal_dag() -> Result<()> {
+        let dir = tempfile::Builder::new().prefix("nostr-db-dag").tempdir()?;
+        let db = Db::open(dir.path())?;
+        let id = |b: u8| hex::encode([b; 32]);
+        // 1 <- 2 <- 3 <- 4, 1 <- 3, e tags name a parent by their first value only
+        let events = vec![
+            event(1, vec![])?,
+            event(2, vec![vec!["parent".to_owned(), id(1), "bad".to_owned(), id(2)]])?,
+            event(3, vec![vec!["parent".to_owned(), id(2)], vec!["e".to_owned(), id(1), id(9)]])?,
+            event(4, vec![vec!["e".to_owned(), id(3)]])?,
+        ];
+        assert_eq!(events[2].causal_parents(), vec![[1; 32], [2; 32]]);
+        assert_eq!(db.batch_put(&events)?, 4);
+
+        let reader = db.reader()?;
+        assert_eq!(db.parents(&reader, [3; 32])?, vec![[1; 32], [2; 32]]);
+        assert_eq!(db.children(&reader, [1; 32])?, vec![[2; 32], [3; 32]]);
+        assert!(db.children(&reader, [9; 32])?.is_empty());
+        assert_eq!(walk(db.ancestors(&reader, &[4; 32], 1))?, vec![(3, 1)]);
+        assert_eq!(walk(db.ancestors(&reader, &[4; 32], 10))?, vec![(1, 2), (2, 2), (3, 1)]);
+        assert_eq!(walk(db.descendants(&reader, &[1; 32], 10))?, vec![(2, 1), (3, 1), (4, 2)]);
+        assert!(walk(db.descendants(&reader, &[1; 32], 0))?.is_empty());
+        drop(reader);
+
+        let mut writer = db.writer()?;
+        assert!(db.del(&mut writer, [3; 32])?);
+        db.commit(writer)?;
+        let reader = db.reader()?;
+        assert!(db.parents(&reader, [3; 32])?.is_empty());
+        assert_eq!(db.children(&reader, [1; 32])?, vec![[2; 32]]);
+        assert_eq!(db.parents(&reader, [4; 32])?, vec![[3; 32]]);
+        drop(reader);
+        drop(db);
+
+        // reopening keeps the index
+        let db = Db::open(dir.path())?;
+        let reader = db.reader()?;
+        assert_eq!(walk(db.descendants(&reader, &[1; 32], 10))?, vec![(2, 1)]);
+        Ok(())
+    }
 }
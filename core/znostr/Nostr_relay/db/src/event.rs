@@ -3,10 +3,15 @@ use rkyv::{
     vec::ArchivedVec, AlignedVec, Archive, Archived, Deserialize as RkyvDeserialize,
     Serialize as RkyvSerialize,
 };
-use secp256k1::{schnorr::Signature, KeyPair, Message, XOnlyPublicKey, SECP256K1};
+use secp256k1::{
+    ecdsa::{RecoverableSignature, RecoveryId},
+    schnorr::Signature,
+    KeyPair, Message, SecretKey, XOnlyPublicKey, SECP256K1,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use sha2::{Digest, Sha256};
+use sha3::Keccak256;
 use std::{
     fmt::Display,
     str::FromStr,
@@ -314,6 +319,20 @@ impl Event {
             .as_ref();
         Self::new(id, pubkey, created_at, kind, tags, content, sig)
     }
+
+    /// Create an event signed with an ETH key, see [`SignScheme::Eth`].
+    pub fn create_eth(
+        secret_key: &SecretKey,
+        created_at: u64,
+        kind: u16,
+        tags: Vec<Vec<String>>,
+        content: String,
+    ) -> Result<Self, Error> {
+        let pubkey = eth_pubkey(&eth_address(secret_key));
+        let id = hash(&pubkey, created_at, kind, &tags, &content);
+        let sig = sign_eth(secret_key, &id)?;
+        Self::new(id, pubkey, created_at, kind, tags, content, sig)
+    }
 }
 
 impl AsRef<Event> for Event {
@@ -474,6 +493,28 @@ impl Event {
     pub fn sig(&self) -> &[u8; 64] {
         &self.sig
     }
+
+    /// Ids of the events this event follows in the causal dag, every id of
+    /// `parent` tags and the referenced id of `e` tags, without duplicates.
+    pub fn causal_parents(&self) -> Vec<[u8; 32]> {
+        let mut parents = self
+            .tags
+            .iter()
+            .flat_map(|tag| match tag.first().map(|name| name.as_str()) {
+                Some("parent") => &tag[1..],
+                Some("e") => &tag[1..tag.len().min(2)],
+                _ => &[],
+            })
+            .filter_map(|id| {
+                let mut parent = [0u8; 32];
+                hex::decode_to_slice(id, &mut parent).ok().map(|_| parent)
+            })
+            .filter(|parent| parent != self.id())
+            .collect::<Vec<_>>();
+        parents.sort();
+        parents.dedup();
+        parents
+    }
 }
 
 pub fn now() -> u64 {
@@ -515,8 +556,16 @@ impl Event {
         }
     }
 
+    pub fn sign_scheme(&self) -> SignScheme {
+        SignScheme::select(self.kind(), self.pubkey())
+    }
+
     pub fn verify_sign(&self) -> Result<(), Error> {
-        if verify_sign(&self.sig, self.pubkey(), self.id()).is_ok() {
+        let verified = match self.sign_scheme() {
+            SignScheme::Schnorr => verify_sign(&self.sig, self.pubkey(), self.id()),
+            SignScheme::Eth => verify_eth_sign(&self.sig, self.pubkey(), self.id()),
+        };
+        if verified.is_ok() {
             Ok(())
         } else {
             Err(Error::Invalid("signature is wrong".to_owned()))
@@ -618,6 +667,92 @@ fn verify_delegation(
     Ok(())
 }
 
+/// Kinds of the CausalityKey protocol, their authors are ETH identities:
+/// subspace creation, subspace join and the subspace ops. zchronod keeps the
+/// same list in `chronod::event`, its tests check they agree.
+pub const ETH_KINDS: [u32; 11] = [
+    30100, 30200, 30300, 30301, 30302, 30303, 30304, 30305, 30306, 30307, 30308,
+];
+
+/// Whether events of `kind` are always ETH signed.
+pub fn is_eth_kind(kind: u32) -> bool {
+    ETH_KINDS.contains(&kind)
+}
+
+/// How the signature of an event is checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignScheme {
+    /// BIP-340 Schnorr over the x-only pubkey, NIP-01.
+    Schnorr,
+    /// secp256k1 ECDSA by an ETH address. The pubkey is the 20 byte address
+    /// left padded with zeros, the sig is the EIP-2098 compact signature of the
+    /// EIP-191 personal message of the 32 id bytes.
+    Eth,
+}
+
+impl SignScheme {
+    /// CausalityKey kinds are always ETH signed, other events are ETH signed
+    /// when the pubkey is a padded address.
+    pub fn select(kind: u16, pubkey: &[u8; 32]) -> Self {
+        if is_eth_kind(kind.into()) || pubkey[..12] == [0u8; 12] {
+            SignScheme::Eth
+        } else {
+            SignScheme::Schnorr
+        }
+    }
+}
+
+/// EIP-191 hash of a personal message.
+fn eth_message(msg: &[u8]) -> Result<Message, Error> {
+    let mut hasher = Keccak256::new();
+    hasher.update(format!("\x19Ethereum Signed Message:\n{}", msg.len()));
+    hasher.update(msg);
+    Ok(Message::from_slice(&hasher.finalize())?)
+}
+
+fn eth_address(secret_key: &SecretKey) -> [u8; 20] {
+    let pk = secret_key.public_key(SECP256K1).serialize_uncompressed();
+    let hash = Keccak256::digest(&pk[1..]);
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&hash[12..]);
+    address
+}
+
+fn eth_pubkey(address: &[u8; 20]) -> [u8; 32] {
+    let mut pubkey = [0u8; 32];
+    pubkey[12..].copy_from_slice(address);
+    pubkey
+}
+
+fn sign_eth(secret_key: &SecretKey, msg: &[u8]) -> Result<[u8; 64], Error> {
+    let (recovery_id, compact) = SECP256K1
+        .sign_ecdsa_recoverable(&eth_message(msg)?, secret_key)
+        .serialize_compact();
+    let mut sig = compact;
+    // EIP-2098: the y parity is the top bit of s
+    if recovery_id.to_i32() == 1 {
+        sig[32] |= 0x80;
+    }
+    Ok(sig)
+}
+
+fn verify_eth_sign(sig: &[u8; 64], pk: &[u8; 32], msg: &[u8]) -> Result<(), Error> {
+    if pk[..12] != [0u8; 12] {
+        return Err(Error::Invalid("pubkey is not an eth address".to_owned()));
+    }
+    let mut compact = *sig;
+    let parity = (compact[32] >> 7) as i32;
+    compact[32] &= 0x7f;
+    let sig = RecoverableSignature::from_compact(&compact, RecoveryId::from_i32(parity)?)?;
+    let recovered = SECP256K1.recover_ecdsa(&eth_message(msg)?, &sig)?;
+    let hash = Keccak256::digest(&recovered.serialize_uncompressed()[1..]);
+    if hash[12..] == pk[12..] {
+        Ok(())
+    } else {
+        Err(Error::Invalid("eth signer does not match pubkey".to_owned()))
+    }
+}
+
 fn verify_sign(sig: &[u8], pk: &[u8], msg: &[u8]) -> Result<(), Error> {
     let sig = Signature::from_slice(sig)?;
     let pk = XOnlyPublicKey::from_slice(pk)?;
@@ -834,4 +969,52 @@ mod tests {
         assert!(event.verify_id().is_ok());
         Ok(())
     }
+
+    #[test]
+    fn eth_sign() -> Result<()> {
+        // web3.js accounts example key
+        let secret_key = SecretKey::from_str(
+            "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318",
+        )?;
+        assert_eq!(
+            hex::encode(eth_address(&secret_key)),
+            "2c7536e3605d9c16a7a3d7b1898e529396a65c23"
+        );
+
+        let event = Event::create_eth(&secret_key, 0, 30100, vec![], "".to_owned())?;
+        assert_eq!(event.sign_scheme(), SignScheme::Eth);
+        assert!(event.pubkey_str().ends_with("2c7536e3605d9c16a7a3d7b1898e529396a65c23"));
+        assert!(event.verify_sign().is_ok());
+        assert!(event.verify_id().is_ok());
+        // selected by pubkey for other kinds
+        let event = Event::create_eth(&secret_key, 0, 1, vec![], "hi".to_owned())?;
+        assert_eq!(event.sign_scheme(), SignScheme::Eth);
+        assert!(event.validate(0, 0, 0).is_ok());
+
+        let mut sig = *event.sig();
+        sig[32] ^= 0x80;
+        let forged = Event::new(
+            *event.id(),
+            *event.pubkey(),
+            0,
+            1,
+            vec![],
+            "hi".to_owned(),
+            sig,
+        )?;
+        assert!(forged.verify_sign().is_err());
+
+        // CausalityKey kinds do not take schnorr signatures
+        let key_pair = KeyPair::new_global(&mut thread_rng());
+        let event = Event::create(&key_pair, 0, 30100, vec![], "".to_owned())?;
+        assert_eq!(event.sign_scheme(), SignScheme::Eth);
+        assert!(event.verify_sign().is_err());
+        // other 303xx kinds, e.g. live events and the token kinds, stay schnorr signed
+        for kind in [30311, 30315, 30320] {
+            let event = Event::create(&key_pair, 0, kind, vec![], "".to_owned())?;
+            assert_eq!(event.sign_scheme(), SignScheme::Schnorr);
+            assert!(event.validate(0, 0, 0).is_ok());
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,241 @@
+//! Queries over the causal dag of stored events
+//!
+//! Edges come from the `parent` and `e` tags indexed by [`Db`], a parent is
+//! always causally before its children.
+
+use crate::{error::Error, Db};
+use nostr_kv::lmdb::Transaction;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+type Result<T, E = Error> = core::result::Result<T, E>;
+
+/// An event and its descendants up to some depth, with the edges between them
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Subgraph {
+    pub root: [u8; 32],
+    /// nodes in breadth first order, the root first
+    pub nodes: Vec<[u8; 32]>,
+    /// parent -> child
+    pub edges: Vec<([u8; 32], [u8; 32])>,
+}
+
+impl Subgraph {
+    /// Nodes ordered so every parent comes before its children,
+    /// ties are broken by the lower id. Nodes on a cycle are left out.
+    pub fn topological_order(&self) -> Vec<[u8; 32]> {
+        let mut indegree: HashMap<&[u8; 32], usize> = self.nodes.iter().map(|n| (n, 0)).collect();
+        let mut children: HashMap<&[u8; 32], Vec<&[u8; 32]>> = HashMap::new();
+        for (parent, child) in &self.edges {
+            *indegree.entry(child).or_default() += 1;
+            children.entry(parent).or_default().push(child);
+        }
+        let mut ready: BTreeSet<&[u8; 32]> = indegree
+            .iter()
+            .filter(|(_, d)| **d == 0)
+            .map(|(n, _)| *n)
+            .collect();
+        let mut order = Vec::with_capacity(self.nodes.len());
+        while let Some(node) = ready.pop_first() {
+            order.push(*node);
+            for child in children.get(node).into_iter().flatten() {
+                let d = indegree.get_mut(child).unwrap();
+                *d -= 1;
+                if *d == 0 {
+                    ready.insert(child);
+                }
+            }
+        }
+        order
+    }
+
+    /// The longest chain of causally dependent events, the first longest by
+    /// topological order when there are several.
+    pub fn critical_path(&self) -> Vec<[u8; 32]> {
+        let mut parents: HashMap<&[u8; 32], Vec<&[u8; 32]>> = HashMap::new();
+        for (parent, child) in &self.edges {
+            parents.entry(child).or_default().push(parent);
+        }
+        let order = self.topological_order();
+        // node -> (length of the longest chain ending at it, previous node)
+        let mut longest: HashMap<[u8; 32], (usize, Option<[u8; 32]>)> = HashMap::new();
+        let mut end: Option<([u8; 32], usize)> = None;
+        for node in &order {
+            let mut best = (1, None);
+            for parent in parents.get(node).into_iter().flatten() {
+                if let Some((len, _)) = longest.get(*parent) {
+                    if len + 1 > best.0 || (len + 1 == best.0 && best.1.is_some_and(|p| **parent < p)) {
+                        best = (len + 1, Some(**parent));
+                    }
+                }
+            }
+            if end.is_none_or(|(_, len)| best.0 > len) {
+                end = Some((*node, best.0));
+            }
+            longest.insert(*node, best);
+        }
+
+        let mut path = vec![];
+        let mut cur = end.map(|(node, _)| node);
+        while let Some(node) = cur {
+            path.push(node);
+            cur = longest[&node].1;
+        }
+        path.reverse();
+        path
+    }
+}
+
+impl Db {
+    /// The event and its descendants at most `max_depth` edges away.
+    pub fn subgraph<T: Transaction>(&self, txn: &T, root: &[u8; 32], max_depth: usize) -> Result<Subgraph> {
+        let mut nodes = vec![*root];
+        for item in self.descendants(txn, root, max_depth) {
+            nodes.push(item?.0);
+        }
+        let set: HashSet<&[u8; 32]> = nodes.iter().collect();
+        let mut edges = vec![];
+        for node in &nodes {
+            for child in self.children(txn, node)? {
+                if set.contains(&child) {
+                    edges.push((*node, child));
+                }
+            }
+        }
+        Ok(Subgraph {
+            root: *root,
+            nodes,
+            edges,
+        })
+    }
+
+    /// Shortest chain of events from `a` to `b` or from `b` to `a`, whichever is
+    /// an ancestor of the other, the ancestor first. None if they are not
+    /// causally related within `max_depth` edges.
+    pub fn causal_path<T: Transaction>(
+        &self,
+        txn: &T,
+        a: &[u8; 32],
+        b: &[u8; 32],
+        max_depth: usize,
+    ) -> Result<Option<Vec<[u8; 32]>>> {
+        if let Some(path) = self.shortest_path(txn, a, b, max_depth)? {
+            return Ok(Some(path));
+        }
+        self.shortest_path(txn, b, a, max_depth)
+    }
+
+    fn shortest_path<T: Transaction>(
+        &self,
+        txn: &T,
+        from: &[u8; 32],
+        to: &[u8; 32],
+        max_depth: usize,
+    ) -> Result<Option<Vec<[u8; 32]>>> {
+        let mut prev: HashMap<[u8; 32], [u8; 32]> = HashMap::new();
+        let mut queue = VecDeque::from([(*from, 0)]);
+        let mut found = from == to;
+        while let Some((node, depth)) = queue.pop_front() {
+            if found || depth >= max_depth {
+                break;
+            }
+            for child in self.children(txn, node)? {
+                if child == *from || prev.contains_key(&child) {
+                    continue;
+                }
+                prev.insert(child, node);
+                if child == *to {
+                    found = true;
+                    break;
+                }
+                queue.push_back((child, depth + 1));
+            }
+        }
+        if !found {
+            return Ok(None);
+        }
+        let mut path = vec![*to];
+        let mut cur = *to;
+        while cur != *from {
+            cur = prev[&cur];
+            path.push(cur);
+        }
+        path.reverse();
+        Ok(Some(path))
+    }
+
+    /// Number of distinct events depending on the event, at most `max_depth` edges away.
+    pub fn influence<T: Transaction>(&self, txn: &T, event_id: &[u8; 32], max_depth: usize) -> Result<usize> {
+        let mut count = 0;
+        for item in self.descendants(txn, event_id, max_depth) {
+            item?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Db, Event};
+    use anyhow::Result;
+
+    fn event(id: u8, parents: &[u8]) -> Result<Event> {
+        let mut tag = vec!["parent".to_owned()];
+        tag.extend(parents.iter().map(|p| hex::encode([*p; 32])));
+        Ok(Event::new([id; 32], [1; 32], 10, 1, vec![tag], "".to_owned(), [0; 64])?)
+    }
+
+    fn ids(nodes: Vec<[u8; 32]>) -> Vec<u8> {
+        nodes.iter().map(|n| n[0]).collect()
+    }
+
+    #[test]
+    pub fn graph_queries() -> Result<()> {
+        let dir = tempfile::Builder::new().prefix("nostr-db-graph").tempdir()?;
+        let db = Db::open(dir.path())?;
+        //   1
+        //  / \
+        // 3   2
+        // |   |
+        // 4   |
+        //  \ /
+        //   5 -- 6
+        db.batch_put(vec![
+            event(1, &[])?,
+            event(2, &[1])?,
+            event(3, &[1])?,
+            event(4, &[3])?,
+            event(5, &[2, 4])?,
+            event(6, &[5])?,
+            event(9, &[])?,
+        ])?;
+        let reader = db.reader()?;
+
+        let path = db.causal_path(&reader, &[1; 32], &[5; 32], 10)?;
+        assert_eq!(path.map(ids), Some(vec![1, 2, 5]));
+        let path = db.causal_path(&reader, &[6; 32], &[3; 32], 10)?;
+        assert_eq!(path.map(ids), Some(vec![3, 4, 5, 6]));
+        assert_eq!(db.causal_path(&reader, &[1; 32], &[6; 32], 2)?, None);
+        assert_eq!(db.causal_path(&reader, &[2; 32], &[3; 32], 10)?, None);
+        assert_eq!(db.causal_path(&reader, &[9; 32], &[9; 32], 10)?.map(ids), Some(vec![9]));
+
+        let graph = db.subgraph(&reader, &[1; 32], 10)?;
+        assert_eq!(graph.nodes.len(), 6);
+        assert_eq!(graph.edges.len(), 6);
+        assert_eq!(ids(graph.topological_order()), vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(ids(graph.critical_path()), vec![1, 3, 4, 5, 6]);
+
+        // 4 -> 5 is kept, both are two edges away
+        let graph = db.subgraph(&reader, &[1; 32], 2)?;
+        assert_eq!(ids(graph.topological_order()), vec![1, 2, 3, 4, 5]);
+        assert_eq!(ids(graph.critical_path()), vec![1, 3, 4, 5]);
+        let graph = db.subgraph(&reader, &[1; 32], 1)?;
+        assert_eq!(ids(graph.critical_path()), vec![1, 2]);
+        assert_eq!(ids(db.subgraph(&reader, &[9; 32], 10)?.critical_path()), vec![9]);
+
+        assert_eq!(db.influence(&reader, &[1; 32], 10)?, 5);
+        assert_eq!(db.influence(&reader, &[4; 32], 10)?, 2);
+        assert_eq!(db.influence(&reader, &[1; 32], 1)?, 2);
+        Ok(())
+    }
+}
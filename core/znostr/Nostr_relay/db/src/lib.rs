@@ -4,12 +4,14 @@ mod db;
 mod error;
 mod event;
 mod filter;
+mod graph;
 mod key;
 pub use secp256k1;
 
 pub use {
-    db::CheckEventResult, db::Db, db::Iter, error::Error, event::now, event::ArchivedEventIndex,
+    db::CheckEventResult, db::DagIter, db::Db, db::Iter, error::Error, event::now, event::ArchivedEventIndex,
     event::Event, event::EventIndex, event::FromEventData, filter::Filter, filter::SortList,
+    graph::Subgraph,
 };
 
 pub use nostr_kv as kv;
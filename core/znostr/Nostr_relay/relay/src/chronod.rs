@@ -0,0 +1,276 @@
+use crate::{
+    setting::{SettingWrapper, Zchronod},
+    zchronod::{
+        zchronod_client::ZchronodClient, Event as ZEvent, PollEventState, PollListRequest, PollListResponse,
+        QueryEventRequest, QueryPollEventRequest, TagArray, ZchronodRequest,
+    },
+};
+use actix::prelude::*;
+use nostr_db::Event;
+use serde_json::{json, Value};
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+use tonic::{transport::Channel, Code, Status};
+use tracing::info;
+
+/// Forward an event to zchronod
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "Result<(), Box<Status>>")]
+pub struct SendEvent(pub Event);
+
+/// Query an event by hex id
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "Result<Option<ZEvent>, Box<Status>>")]
+pub struct QueryEventMeta(pub String);
+
+/// Query a page of the nip-3041 poll list
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "Result<PollListResponse, Box<Status>>")]
+pub struct QueryPollList(pub PollListRequest);
+
+/// Query the state, end and status of a nip-3041 poll by hex id
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "Result<PollEventState, Box<Status>>")]
+pub struct QueryPollState(pub String);
+
+/// The only connection of the relay to zchronod.
+///
+/// Requests share one lazily connected channel, built from `setting.zchronod`
+/// and rebuilt when the setting changes. Every request is bounded by the
+/// timeout, after connection failures requests fail fast until the backoff
+/// delay has passed, so a dead zchronod costs callers nothing but an error.
+pub struct Chronod {
+    setting: SettingWrapper,
+    // setting the channel was built from
+    current: Option<Zchronod>,
+    channel: Result<Channel, String>,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl Chronod {
+    pub fn new(setting: SettingWrapper) -> Self {
+        Self {
+            setting,
+            current: None,
+            channel: Err("not connected".to_owned()),
+            failures: 0,
+            retry_at: None,
+        }
+    }
+
+    // grpc calls fail with a tonic::Status, boxed as it is large
+    fn client(&mut self) -> Result<ZchronodClient<Channel>, Box<Status>> {
+        let setting = self.setting.read().zchronod.clone();
+        if self.current.as_ref() != Some(&setting) {
+            info!("connect zchronod {}", setting.ip);
+            self.channel = Channel::from_shared(format!("http://{}", setting.ip))
+                .map(|endpoint| endpoint.connect_timeout(*setting.connect_timeout).connect_lazy())
+                .map_err(|e| format!("invalid zchronod address {}: {}", setting.ip, e));
+            self.current = Some(setting);
+            self.failures = 0;
+            self.retry_at = None;
+        }
+        if let Some(retry_at) = self.retry_at {
+            if Instant::now() < retry_at {
+                return Err(Status::unavailable("zchronod unavailable, waiting to reconnect").into());
+            }
+        }
+        self.channel
+            .clone()
+            .map(ZchronodClient::new)
+            .map_err(|e| Status::unavailable(e).into())
+    }
+
+    fn record(&mut self, result: &Result<impl Sized, Box<Status>>) {
+        let failed = matches!(
+            result,
+            Err(status) if matches!(
+                status.code(),
+                Code::Unavailable | Code::DeadlineExceeded | Code::Cancelled | Code::Unknown
+            )
+        );
+        if !failed {
+            self.failures = 0;
+            self.retry_at = None;
+            return;
+        }
+        let Some(setting) = &self.current else {
+            return;
+        };
+        let backoff = setting
+            .backoff
+            .saturating_mul(1 << self.failures.min(16))
+            .min(*setting.max_backoff);
+        self.failures += 1;
+        self.retry_at = Some(Instant::now() + backoff);
+        info!("zchronod request failed {} times, retry in {:?}", self.failures, backoff);
+    }
+
+    /// Run a request against zchronod with the timeout and backoff applied.
+    fn call<T, F, Fut>(&mut self, f: F) -> ResponseActFuture<Self, Result<T, Box<Status>>>
+    where
+        T: 'static,
+        F: FnOnce(ZchronodClient<Channel>) -> Fut,
+        Fut: Future<Output = Result<T, Status>> + 'static,
+    {
+        let client = match self.client() {
+            Ok(client) => client,
+            Err(status) => return Box::pin(fut::ready(Err(status))),
+        };
+        let timeout: Duration = self
+            .current
+            .as_ref()
+            .map(|setting| *setting.timeout)
+            .unwrap_or_default();
+        let fut = f(client);
+        Box::pin(
+            async move {
+                match tokio::time::timeout(timeout, fut).await {
+                    Ok(result) => result.map_err(Box::new),
+                    Err(_) => Err(Box::new(Status::deadline_exceeded("zchronod timeout"))),
+                }
+            }
+            .into_actor(self)
+            .map(|result, act, _| {
+                act.record(&result);
+                result
+            }),
+        )
+    }
+}
+
+/// The zchronod event in nip-01 json form
+pub fn event_json(event: &ZEvent) -> Value {
+    json!({
+        "id": hex::encode(&event.id),
+        "pubkey": hex::encode(&event.pubkey),
+        "created_at": event.created_at,
+        "kind": event.kind,
+        "tags": event.tags.iter().map(|tag| &tag.values).collect::<Vec<_>>(),
+        "content": event.content,
+        "sig": hex::encode(&event.sig),
+    })
+}
+
+/// Options and vote counts of a nip-3041 poll state `[option, count, option, count, ..]`
+pub fn poll_options(state: &[String]) -> Vec<(String, u64)> {
+    state
+        .chunks(2)
+        .map(|pair| {
+            let votes = pair.get(1).and_then(|n| n.parse().ok()).unwrap_or_default();
+            (pair[0].clone(), votes)
+        })
+        .collect()
+}
+
+/// The nip-3041 poll state in the json form of query results
+pub fn poll_state_json(poll_id: &str, state: &[String]) -> Value {
+    let options = poll_options(state)
+        .into_iter()
+        .map(|(option, votes)| json!({ "option": option, "votes": votes }))
+        .collect::<Vec<_>>();
+    json!({ "id": poll_id, "options": options })
+}
+
+/// The reason of a `CLOSED` message for a failed zchronod request
+pub fn closed_reason(status: &Status) -> String {
+    match status.code() {
+        Code::InvalidArgument => format!("invalid: {}", status.message()),
+        Code::NotFound => format!("not found: {}", status.message()),
+        _ => format!("error: zchronod: {}", status.message()),
+    }
+}
+
+impl Actor for Chronod {
+    type Context = Context<Self>;
+}
+
+impl Handler<SendEvent> for Chronod {
+    type Result = ResponseActFuture<Self, Result<(), Box<Status>>>;
+    fn handle(&mut self, msg: SendEvent, _: &mut Self::Context) -> Self::Result {
+        let event = msg.0;
+        let request = ZchronodRequest {
+            msg: Some(ZEvent {
+                id: event.id().to_vec(),
+                pubkey: event.pubkey().to_vec(),
+                // the id covers created_at, zchronod checks it
+                created_at: event.created_at() as i64,
+                kind: event.kind() as u32,
+                tags: event
+                    .tags()
+                    .iter()
+                    .map(|values| TagArray {
+                        values: values.clone(),
+                    })
+                    .collect(),
+                content: event.content().clone(),
+                sig: event.sig().to_vec(),
+            }),
+        };
+        self.call(|mut client| async move { client.send(request).await.map(|_| ()) })
+    }
+}
+
+impl Handler<QueryEventMeta> for Chronod {
+    type Result = ResponseActFuture<Self, Result<Option<ZEvent>, Box<Status>>>;
+    fn handle(&mut self, msg: QueryEventMeta, _: &mut Self::Context) -> Self::Result {
+        let request = QueryEventRequest { eventid: msg.0 };
+        self.call(|mut client| async move {
+            Ok(client.query_by_event_id(request).await?.into_inner().event)
+        })
+    }
+}
+
+impl Handler<QueryPollList> for Chronod {
+    type Result = ResponseActFuture<Self, Result<PollListResponse, Box<Status>>>;
+    fn handle(&mut self, msg: QueryPollList, _: &mut Self::Context) -> Self::Result {
+        self.call(|mut client| async move { Ok(client.query_poll_list(msg.0).await?.into_inner()) })
+    }
+}
+
+impl Handler<QueryPollState> for Chronod {
+    type Result = ResponseActFuture<Self, Result<PollEventState, Box<Status>>>;
+    fn handle(&mut self, msg: QueryPollState, _: &mut Self::Context) -> Self::Result {
+        let request = QueryPollEventRequest { eventid: msg.0 };
+        self.call(|mut client| async move { Ok(client.query_poll_event_state(request).await?.into_inner()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Setting;
+    use anyhow::Result;
+
+    #[actix_rt::test]
+    async fn dead_zchronod() -> Result<()> {
+        let mut setting = Setting::default();
+        // nothing listens on port 1
+        setting.zchronod.ip = "127.0.0.1:1".to_owned();
+        setting.zchronod.backoff = Duration::from_secs(60).try_into().unwrap();
+        let setting: SettingWrapper = setting.into();
+        let chronod = Chronod::new(setting.clone()).start();
+
+        let start = Instant::now();
+        let status = chronod.send(QueryPollList(Default::default())).await?.unwrap_err();
+        assert_ne!(status.message(), "zchronod unavailable, waiting to reconnect");
+        // fail fast while backing off
+        let status = chronod.send(QueryPollState("01".to_owned())).await?.unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(status.message(), "zchronod unavailable, waiting to reconnect");
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // a new address connects again at once
+        setting.write().zchronod.ip = "127.0.0.1:2".to_owned();
+        let status = chronod.send(QueryPollList(Default::default())).await?.unwrap_err();
+        assert_ne!(status.message(), "zchronod unavailable, waiting to reconnect");
+
+        setting.write().zchronod.ip = "not an address".to_owned();
+        let status = chronod.send(QueryPollList(Default::default())).await?.unwrap_err();
+        assert!(status.message().starts_with("invalid zchronod address"));
+        Ok(())
+    }
+}
@@ -23,6 +23,7 @@ impl actix_web::ResponseError for Error {}
 pub type Result<T, E = Error> = core::result::Result<T, E>;
 
 mod app;
+pub mod chronod;
 pub mod duration;
 mod extension;
 mod hash;
@@ -39,7 +40,7 @@ pub mod zchronod;
 pub use metrics;
 pub use nostr_db as db;
 pub use {
-    app::*, extension::*, list::List, reader::Reader, server::Server, server::*, session::Session,
+    app::*, chronod::Chronod, extension::*, list::List, reader::Reader, server::Server, server::*, session::Session,
     setting::Setting, subscriber::Subscriber, writer::Writer,
 };
 
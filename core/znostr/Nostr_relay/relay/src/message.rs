@@ -103,10 +103,15 @@ pub enum IncomingMessage {
     Event(Event),
     Close(String),
     Req(Subscription),
-    /// nip-3041
-    Query(QueryPollState),
-    QueryPollList(String),
-    QueryEventMeta(String),
+    /// nip-3041, `["QUERY", <request id>, {"id": <poll id>}]`,
+    /// the request id is None in the legacy form without it
+    Query(Option<String>, QueryPollState),
+    QueryPollList(Option<String>, PollListQuery),
+    QueryEventMeta(Option<String>, EventMetaQuery),
+    /// live nip-3041 poll results, `["SUBPOLL", <sub id>, {"id": <poll id>}]`
+    SubscribePoll(String, QueryPollState),
+    /// causal graph queries
+    Graph(GraphCommand, GraphQuery),
     /// nip-42
     Auth(Event),
     /// nip-45
@@ -123,9 +128,11 @@ impl IncomingMessage {
             IncomingMessage::Auth(_) => "AUTH",
             IncomingMessage::Count(_) => "COUNT",
             IncomingMessage::Unknown(cmd, _) => cmd,
-            IncomingMessage::Query(_) => "QUERY",
-            IncomingMessage::QueryPollList(_) => "QUERYPOLLLIST",
-            IncomingMessage::QueryEventMeta(_) => "QUERYEVENTMETA",
+            IncomingMessage::Query(_, _) => "QUERY",
+            IncomingMessage::QueryPollList(_, _) => "QUERYPOLLLIST",
+            IncomingMessage::QueryEventMeta(_, _) => "QUERYEVENTMETA",
+            IncomingMessage::SubscribePoll(_, _) => "SUBPOLL",
+            IncomingMessage::Graph(cmd, _) => cmd.as_str(),
         }
     }
 
@@ -137,9 +144,11 @@ impl IncomingMessage {
             IncomingMessage::Auth(_) => Some("AUTH"),
             IncomingMessage::Count(_) => Some("COUNT"),
             IncomingMessage::Unknown(_, _) => None,
-            IncomingMessage::Query(_) => Some("QUERY"),
-            IncomingMessage::QueryPollList(_) => Some("QUERYPOLLLIST"),
-            IncomingMessage::QueryEventMeta(_) => Some("QUERYEVENTMETA"),
+            IncomingMessage::Query(_, _) => Some("QUERY"),
+            IncomingMessage::QueryPollList(_, _) => Some("QUERYPOLLLIST"),
+            IncomingMessage::QueryEventMeta(_, _) => Some("QUERYEVENTMETA"),
+            IncomingMessage::SubscribePoll(_, _) => Some("SUBPOLL"),
+            IncomingMessage::Graph(cmd, _) => Some(cmd.as_str()),
         }
     }
 }
@@ -189,18 +198,57 @@ impl<'de> Visitor<'de> for MessageVisitor {
                 let r = Vec::<Filter>::deserialize(de::value::SeqAccessDeserializer::new(seq))?;
                 Ok(IncomingMessage::Count(Subscription { id: t, filters: r }))
             }
-            "QUERY" => Ok(IncomingMessage::Query(
-                seq.next_element()?
-                    .ok_or_else(|| de::Error::invalid_length(0, &self))?,
-            )),
-            "QUERYPOLLLIST" => Ok(IncomingMessage::QueryPollList(
+            "QUERY" | "QUERYPOLLLIST" | "QUERYEVENTMETA" => {
+                let first: Value = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                // legacy clients send the params without a request id
+                let (req_id, params) = match seq.next_element::<Value>()? {
+                    Some(params) => (Some(String::deserialize(first).map_err(de::Error::custom)?), params),
+                    None => (None, first),
+                };
+                Ok(match (t, req_id) {
+                    ("QUERY", req_id) => IncomingMessage::Query(
+                        req_id,
+                        QueryPollState::deserialize(params).map_err(de::Error::custom)?,
+                    ),
+                    ("QUERYPOLLLIST", None) => IncomingMessage::QueryPollList(None, PollListQuery::default()),
+                    ("QUERYPOLLLIST", req_id) => IncomingMessage::QueryPollList(
+                        req_id,
+                        PollListQuery::deserialize(params).map_err(de::Error::custom)?,
+                    ),
+                    (_, None) => IncomingMessage::QueryEventMeta(
+                        None,
+                        EventMetaQuery {
+                            id: String::deserialize(params).map_err(de::Error::custom)?,
+                        },
+                    ),
+                    (_, req_id) => IncomingMessage::QueryEventMeta(
+                        req_id,
+                        EventMetaQuery::deserialize(params).map_err(de::Error::custom)?,
+                    ),
+                })
+            }
+            "SUBPOLL" => Ok(IncomingMessage::SubscribePoll(
                 seq.next_element()?
                     .ok_or_else(|| de::Error::invalid_length(0, &self))?,
-            )),
-            "QUERYEVENTMETA" => Ok(IncomingMessage::QueryEventMeta(
                 seq.next_element()?
-                    .ok_or_else(|| de::Error::invalid_length(0, &self))?,
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?,
             )),
+            "CAUSALPATH" | "TOPOSORT" | "CRITICALPATH" | "INFLUENCE" | "SUBGRAPH" => {
+                let cmd = match t {
+                    "CAUSALPATH" => GraphCommand::CausalPath,
+                    "TOPOSORT" => GraphCommand::TopoSort,
+                    "CRITICALPATH" => GraphCommand::CriticalPath,
+                    "INFLUENCE" => GraphCommand::Influence,
+                    _ => GraphCommand::Subgraph,
+                };
+                Ok(IncomingMessage::Graph(
+                    cmd,
+                    seq.next_element()?
+                        .ok_or_else(|| de::Error::invalid_length(0, &self))?,
+                ))
+            }
             _ => Ok(IncomingMessage::Unknown(
                 t.to_string(),
                 Vec::<Value>::deserialize(de::value::SeqAccessDeserializer::new(seq))?,
@@ -234,6 +282,74 @@ pub struct QueryPollState {
     pub id: String,
 }
 
+/// Poll list params, `["QUERYPOLLLIST", <request id>, {"cursor", "limit", "author", "status"}]`,
+/// every param is optional
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PollListQuery {
+    /// cursor of the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+    /// hex pubkey
+    pub author: Option<String>,
+    pub status: Option<PollStatus>,
+}
+
+/// Status filter of the poll list
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PollStatus {
+    Open,
+    Closed,
+}
+
+/// Event meta params, `["QUERYEVENTMETA", <request id>, {"id": <event id>}]`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EventMetaQuery {
+    /// hex event id
+    pub id: String,
+}
+
+/// Query over the causal dag of stored events
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphCommand {
+    /// shortest path between `id` and `to`
+    CausalPath,
+    /// topological order of the subgraph below `id`
+    TopoSort,
+    /// longest path in the subgraph below `id`
+    CriticalPath,
+    /// number of descendants of `id`
+    Influence,
+    /// nodes and edges of the subgraph below `id`
+    Subgraph,
+}
+
+impl GraphCommand {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GraphCommand::CausalPath => "CAUSALPATH",
+            GraphCommand::TopoSort => "TOPOSORT",
+            GraphCommand::CriticalPath => "CRITICALPATH",
+            GraphCommand::Influence => "INFLUENCE",
+            GraphCommand::Subgraph => "SUBGRAPH",
+        }
+    }
+}
+
+/// Graph query params, `["CAUSALPATH", {"id": "..", "to": "..", "depth": 10}]`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GraphQuery {
+    /// hex event id
+    pub id: String,
+    /// hex event id, the other end of a causal path
+    #[serde(default)]
+    pub to: Option<String>,
+    /// max edges to walk, clamped to the max_graph_depth limitation
+    #[serde(default)]
+    pub depth: Option<usize>,
+}
+
 /// Subscription
 #[derive(Clone, Debug)]
 pub struct Subscription {
@@ -294,6 +410,20 @@ impl OutgoingMessage {
     pub fn ok(event_id: &str, saved: bool, message: &str) -> Self {
         Self(json!(["OK", event_id, saved, message]).to_string())
     }
+
+    /// nip-3041 query result
+    pub fn result(req_id: &str, result: Value) -> Self {
+        Self(json!(["RESULT", req_id, result]).to_string())
+    }
+
+    /// nip-3041 query failed, the reason is prefixed like nip-20 messages
+    pub fn closed(req_id: &str, reason: &str) -> Self {
+        Self(json!(["CLOSED", req_id, reason]).to_string())
+    }
+
+    pub fn graph(cmd: GraphCommand, event_id: &str, result: Value) -> Self {
+        Self(json!([cmd.as_str(), event_id, result]).to_string())
+    }
 }
 
 impl Display for OutgoingMessage {
@@ -357,6 +487,14 @@ pub struct ReadEventResult {
     pub msg: OutgoingMessage,
 }
 
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct ReadGraph {
+    pub id: usize,
+    pub cmd: GraphCommand,
+    pub query: GraphQuery,
+}
+
 #[derive(MessageResponse, Clone, Debug, PartialEq, Eq)]
 pub enum Subscribed {
     Ok,
@@ -371,6 +509,15 @@ pub struct Subscribe {
     pub subscription: Subscription,
 }
 
+/// Subscribe to the results of a nip-3041 poll
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "Subscribed")]
+pub struct SubscribePoll {
+    pub id: usize,
+    pub sub_id: String,
+    pub poll_id: [u8; 32],
+}
+
 #[derive(Message, Clone, Debug)]
 #[rtype(result = "()")]
 pub struct Unsubscribe {
@@ -455,6 +602,47 @@ mod tests {
         let msg: IncomingMessage = serde_json::from_str(r#"["COUNT", "sub_id1", {}]"#)?;
         assert!(matches!(msg, IncomingMessage::Count(sub) if sub.id == "sub_id1"));
 
+        // graph
+        let msg: IncomingMessage =
+            serde_json::from_str(r#"["CAUSALPATH", {"id": "01", "to": "02", "depth": 3}]"#)?;
+        assert!(matches!(msg, IncomingMessage::Graph(GraphCommand::CausalPath, ref q)
+            if q.id == "01" && q.to.as_deref() == Some("02") && q.depth == Some(3)));
+        assert_eq!(msg.command(), "CAUSALPATH");
+        let msg: IncomingMessage = serde_json::from_str(r#"["INFLUENCE", {"id": "01"}]"#)?;
+        assert!(matches!(msg, IncomingMessage::Graph(GraphCommand::Influence, ref q) if q.depth.is_none()));
+        assert!(serde_json::from_str::<IncomingMessage>(r#"["SUBGRAPH"]"#).is_err());
+
+        // nip-3041
+        let msg: IncomingMessage = serde_json::from_str(r#"["QUERY", "q1", {"id": "01"}]"#)?;
+        assert!(matches!(msg, IncomingMessage::Query(Some(ref req_id), ref q) if req_id == "q1" && q.id == "01"));
+        let msg: IncomingMessage = serde_json::from_str(r#"["QUERYPOLLLIST", "q2", {}]"#)?;
+        assert!(matches!(msg, IncomingMessage::QueryPollList(Some(ref req_id), _) if req_id == "q2"));
+        let msg: IncomingMessage =
+            serde_json::from_str(r#"["QUERYPOLLLIST", "q2", {"cursor": "c1", "limit": 10, "status": "open"}]"#)?;
+        assert!(matches!(
+            msg,
+            IncomingMessage::QueryPollList(_, ref q)
+                if q.cursor.as_deref() == Some("c1") && q.limit == Some(10) && q.author.is_none()
+                    && q.status == Some(PollStatus::Open)
+        ));
+        assert!(serde_json::from_str::<IncomingMessage>(r#"["QUERYPOLLLIST", "q2", {"status": "ended"}]"#).is_err());
+        let msg: IncomingMessage = serde_json::from_str(r#"["QUERYEVENTMETA", "q3", {"id": "01"}]"#)?;
+        assert!(matches!(msg, IncomingMessage::QueryEventMeta(Some(ref req_id), ref q) if req_id == "q3" && q.id == "01"));
+        assert!(serde_json::from_str::<IncomingMessage>(r#"["QUERY", {"id": "01"}, {"id": "01"}]"#).is_err());
+        assert!(serde_json::from_str::<IncomingMessage>(r#"["QUERYEVENTMETA", "q3", "01"]"#).is_err());
+        assert!(serde_json::from_str::<IncomingMessage>(r#"["QUERY", "q1", {"id": "01"}, {}]"#).is_err());
+        // legacy
+        let msg: IncomingMessage = serde_json::from_str(r#"["QUERY", {"id": "01"}]"#)?;
+        assert!(matches!(msg, IncomingMessage::Query(None, ref q) if q.id == "01"));
+        let msg: IncomingMessage = serde_json::from_str(r#"["QUERYPOLLLIST", ""]"#)?;
+        assert!(matches!(msg, IncomingMessage::QueryPollList(None, _)));
+        let msg: IncomingMessage = serde_json::from_str(r#"["QUERYEVENTMETA", "01"]"#)?;
+        assert!(matches!(msg, IncomingMessage::QueryEventMeta(None, ref q) if q.id == "01"));
+        assert!(serde_json::from_str::<IncomingMessage>(r#"["QUERY"]"#).is_err());
+        let msg: IncomingMessage = serde_json::from_str(r#"["SUBPOLL", "s1", {"id": "01"}]"#)?;
+        assert!(matches!(msg, IncomingMessage::SubscribePoll(ref sub_id, ref q) if sub_id == "s1" && q.id == "01"));
+        assert!(serde_json::from_str::<IncomingMessage>(r#"["SUBPOLL", "s1"]"#).is_err());
+
         Ok(())
     }
 
@@ -469,6 +657,10 @@ mod tests {
         let msg = OutgoingMessage::eose("hello");
         let json = msg.to_string();
         assert_eq!(json, r#"["EOSE","hello"]"#);
+        let msg = OutgoingMessage::result("q1", json!({ "polls": [] }));
+        assert_eq!(msg.to_string(), r#"["RESULT","q1",{"polls":[]}]"#);
+        let msg = OutgoingMessage::closed("q1", "error: timeout");
+        assert_eq!(msg.to_string(), r#"["CLOSED","q1","error: timeout"]"#);
         // let event = Event::default();
         // let msg = OutgoingMessage("id".to_owned(), Some(event));
         // let json = msg.to_string();
@@ -1,7 +1,8 @@
-use crate::{message::*, setting::SettingWrapper, Result};
+use crate::{message::*, setting::SettingWrapper, Error, Result};
 use actix::prelude::*;
 use metrics::histogram;
 use nostr_db::Db;
+use serde_json::{json, Value};
 use std::{sync::Arc, time::Instant};
 
 /// Requst by filter
@@ -46,6 +47,62 @@ impl Reader {
     }
 }
 
+fn parse_event_id(id: &str) -> Result<[u8; 32]> {
+    let mut event_id = [0u8; 32];
+    hex::decode_to_slice(id, &mut event_id)
+        .map_err(|_| Error::Invalid(format!("event id {}", id)))?;
+    Ok(event_id)
+}
+
+fn hex_ids(ids: Vec<[u8; 32]>) -> Vec<String> {
+    ids.iter().map(hex::encode).collect()
+}
+
+impl Reader {
+    pub fn read_graph(&self, msg: &ReadGraph) -> Result<Value> {
+        let max_depth = self.setting.read().limitation.max_graph_depth;
+        let depth = msg.query.depth.unwrap_or(max_depth).min(max_depth);
+        let event_id = parse_event_id(&msg.query.id)?;
+        let reader = self.db.reader()?;
+        let start = Instant::now();
+        let result = match msg.cmd {
+            GraphCommand::CausalPath => {
+                let to = msg
+                    .query
+                    .to
+                    .as_deref()
+                    .ok_or_else(|| Error::Invalid("missing to".to_owned()))?;
+                let path = self
+                    .db
+                    .causal_path(&reader, &event_id, &parse_event_id(to)?, depth)?;
+                json!({ "path": path.map(hex_ids) })
+            }
+            GraphCommand::TopoSort => {
+                let graph = self.db.subgraph(&reader, &event_id, depth)?;
+                json!({ "order": hex_ids(graph.topological_order()) })
+            }
+            GraphCommand::CriticalPath => {
+                let path = self.db.subgraph(&reader, &event_id, depth)?.critical_path();
+                json!({ "length": path.len() - 1, "path": hex_ids(path) })
+            }
+            GraphCommand::Influence => {
+                json!({ "influence": self.db.influence(&reader, &event_id, depth)? })
+            }
+            GraphCommand::Subgraph => {
+                let graph = self.db.subgraph(&reader, &event_id, depth)?;
+                let edges = graph
+                    .edges
+                    .iter()
+                    .map(|(parent, child)| [hex::encode(parent), hex::encode(child)])
+                    .collect::<Vec<_>>();
+                json!({ "nodes": hex_ids(graph.nodes), "edges": edges })
+            }
+        };
+        histogram!("nostr_relay_db_graph", start.elapsed());
+        Ok(result)
+    }
+}
+
 impl Actor for Reader {
     type Context = SyncContext<Self>;
     fn started(&mut self, _ctx: &mut Self::Context) {}
@@ -64,6 +121,21 @@ impl Handler<ReadEvent> for Reader {
     }
 }
 
+impl Handler<ReadGraph> for Reader {
+    type Result = ();
+    fn handle(&mut self, msg: ReadGraph, _: &mut Self::Context) {
+        let out = match self.read_graph(&msg) {
+            Ok(result) => OutgoingMessage::graph(msg.cmd, &msg.query.id, result),
+            Err(err) => OutgoingMessage::notice(&format!("graph query error: {}", err)),
+        };
+        self.addr.do_send(ReadEventResult {
+            id: msg.id,
+            sub_id: msg.query.id,
+            msg: out,
+        });
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -132,4 +204,66 @@ mod tests {
         assert_eq!(r.len(), 8);
         Ok(())
     }
+
+    #[actix_rt::test]
+    async fn read_graph() -> Result<()> {
+        let db = Arc::new(Db::open(temp_data_path("reader_graph")?)?);
+        let id = |b: u8| hex::encode([b; 32]);
+        // 1 <- 2 <- 3
+        let events = [(1, vec![]), (2, vec![id(1)]), (3, vec![id(2)])]
+            .into_iter()
+            .map(|(b, parents)| {
+                let mut tag = vec!["parent".to_owned()];
+                tag.extend(parents);
+                Event::new([b; 32], [1; 32], 10, 1, vec![tag], "".to_owned(), [0; 64])
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        db.batch_put(events)?;
+
+        let receiver = Receiver::default();
+        let messages = receiver.0.clone();
+        let addr = receiver.start().recipient();
+        let mut setting = Setting::default();
+        setting.limitation.max_graph_depth = 1;
+        let setting: SettingWrapper = setting.into();
+        let reader = SyncArbiter::start(1, move || {
+            Reader::new(Arc::clone(&db), addr.clone(), setting.clone())
+        });
+
+        let queries = [
+            (GraphCommand::CausalPath, id(2), Some(id(1)), None),
+            (GraphCommand::CriticalPath, id(1), None, Some(5)),
+            (GraphCommand::Influence, id(2), None, None),
+            (GraphCommand::CausalPath, "zz".to_owned(), Some(id(1)), None),
+        ];
+        for (i, (cmd, event_id, to, depth)) in queries.into_iter().enumerate() {
+            reader
+                .send(ReadGraph {
+                    id: i,
+                    cmd,
+                    query: GraphQuery {
+                        id: event_id,
+                        to,
+                        depth,
+                    },
+                })
+                .await?;
+        }
+
+        sleep(Duration::from_millis(100)).await;
+        let r = messages.read();
+        assert_eq!(r.len(), 4);
+        assert_eq!(
+            r[0].msg.0,
+            json!(["CAUSALPATH", id(2), { "path": [id(1), id(2)] }]).to_string()
+        );
+        // depth is clamped to max_graph_depth
+        assert_eq!(
+            r[1].msg.0,
+            json!(["CRITICALPATH", id(1), { "length": 1, "path": [id(1), id(2)] }]).to_string()
+        );
+        assert_eq!(r[2].msg.0, json!(["INFLUENCE", id(2), { "influence": 1 }]).to_string());
+        assert!(r[3].msg.0.starts_with(r#"["NOTICE","graph query error"#));
+        Ok(())
+    }
 }
@@ -1,21 +1,17 @@
-use crate::{message::*, setting::SettingWrapper, Reader, Subscriber, Writer};
+use crate::{
+    chronod,
+    message::*,
+    zchronod,
+    setting::{SettingWrapper, ZchronodPolicy},
+    Chronod, Reader, Subscriber, Writer,
+};
 use actix::prelude::*;
 use nostr_db::{CheckEventResult, Db, Event};
-use std::{collections::HashMap, env, sync::Arc, thread};
-use std::fmt::Error;
-use std::future::Future;
-use std::sync::mpsc;
-use std::sync::mpsc::Sender;
-use tonic::Status;
-use tonic::transport::Channel;
+use std::{collections::HashMap, sync::Arc};
+use tonic::{Code, Status};
 use tracing::info;
-use crate::zchronod::zchronod_client::ZchronodClient;
-use crate::zchronod::{Empty, QueryEventRequest, QueryPollEventRequest, TagArray, ZchronodRequest, ZchronodResp};
-use crate::zchronod::Event as c_Event;
-use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::message::IncomingMessage::{Query, QueryEventMeta, QueryPollList};
-use tokio::runtime::Runtime;
 use serde_json::{json, Value};
 
 /// Server
@@ -26,7 +22,10 @@ pub struct Server {
     reader: Addr<Reader>,
     subscriber: Addr<Subscriber>,
     sessions: HashMap<usize, Recipient<OutgoingMessage>>,
-    zchronod_ip: String,
+    chronod: Addr<Chronod>,
+    setting: SettingWrapper,
+    /// zchronod outcome of events being written, (session id, event id) -> note for the OK message
+    chronod_notes: HashMap<(usize, [u8; 32]), String>,
 }
 
 impl Server {
@@ -41,12 +40,14 @@ impl Server {
 
         Server::create(|ctx| {
             let writer = Writer::new(Arc::clone(&db), ctx.address().recipient()).start();
-            let subscriber = Subscriber::new(ctx.address().recipient(), setting.clone()).start();
+            let chronod = Chronod::new(setting.clone()).start();
+            let subscriber =
+                Subscriber::new(ctx.address().recipient(), chronod.clone(), setting.clone()).start();
             let addr = ctx.address().recipient();
             info!("starting {} reader workers", num);
-            let zchronod_ip = setting.clone().read().zchronod.ip.clone();
+            let c_setting = setting.clone();
             let reader = SyncArbiter::start(num, move || {
-                Reader::new(Arc::clone(&db), addr.clone(), setting.clone())
+                Reader::new(Arc::clone(&db), addr.clone(), c_setting.clone())
             });
 
             Server {
@@ -55,7 +56,9 @@ impl Server {
                 reader,
                 subscriber,
                 sessions: HashMap::new(),
-                zchronod_ip: zchronod_ip,
+                chronod,
+                setting,
+                chronod_notes: HashMap::new(),
             }
         })
     }
@@ -65,6 +68,68 @@ impl Server {
             addr.do_send(msg);
         }
     }
+
+    fn write_event(&mut self, id: usize, event: Event, chronod_note: Option<String>) {
+        if let Some(note) = chronod_note {
+            self.chronod_notes.insert((id, *event.id()), note);
+        }
+        self.writer.do_send(WriteEvent { id, event });
+    }
+
+    /// Answer a nip-3041 query with the zchronod response as `["RESULT", req_id, result]`,
+    /// or `["CLOSED", req_id, reason]` when it fails. Legacy queries without a request id
+    /// get the `legacy` json or a notice.
+    fn query_chronod<M, T>(
+        &mut self,
+        ctx: &mut Context<Self>,
+        session_id: usize,
+        req_id: Option<String>,
+        msg: M,
+        result: impl FnOnce(T) -> Result<Value, String> + 'static,
+        legacy: impl FnOnce(T) -> Value + 'static,
+    ) where
+        M: Message<Result = Result<T, Box<Status>>> + Send + 'static,
+        T: Send + 'static,
+        Chronod: Handler<M>,
+        <Chronod as Actor>::Context: dev::ToEnvelope<Chronod, M>,
+    {
+        if req_id.is_none() && !self.setting.read().zchronod.legacy_query {
+            self.send_to_client(
+                session_id,
+                OutgoingMessage::notice("invalid: request id required, use [<command>, <request id>, <params>]"),
+            );
+            return;
+        }
+        self.chronod
+            .send(msg)
+            .into_actor(self)
+            .then(move |res, act, _ctx| {
+                let res = match res {
+                    Ok(res) => res,
+                    Err(err) => Err(Status::unavailable(err.to_string()).into()),
+                };
+                let out = match (req_id, res) {
+                    (Some(req_id), Ok(value)) => match result(value) {
+                        Ok(value) => OutgoingMessage::result(&req_id, value),
+                        Err(reason) => OutgoingMessage::closed(&req_id, &reason),
+                    },
+                    (Some(req_id), Err(status)) => OutgoingMessage::closed(&req_id, &chronod::closed_reason(&status)),
+                    (None, Ok(value)) => OutgoingMessage(legacy(value).to_string()),
+                    (None, Err(status)) => chronod_notice(status.message()),
+                };
+                act.send_to_client(session_id, out);
+                fut::ready(())
+            })
+            .spawn(ctx);
+    }
+}
+
+fn with_note(message: &str, note: Option<String>) -> String {
+    match note {
+        Some(note) if message.is_empty() => note,
+        Some(note) => format!("{}; {}", message, note),
+        None => message.to_owned(),
+    }
 }
 
 /// Make actor from `Server`
@@ -111,234 +176,6 @@ impl Handler<Disconnect> for Server {
     }
 }
 
-fn get_current_system_time() -> i64 {
-    let current_time = SystemTime::now();
-    let duration = current_time.duration_since(UNIX_EPOCH).expect("Time went backwards");
-    let timestamp = duration.as_secs() as i64;
-    timestamp
-}
-
-impl Server {
-    fn query_event_meta(&self, event_id: String) -> String {
-        println!("query event meta here");
-
-        let (sender, receiver) = mpsc::channel();
-        thread::spawn(move || {
-            let mut rt = Runtime::new().unwrap();
-            //let zi = self.zchronod_ip.clone();
-            let zi = "127.0.0.1:10020".to_string();
-
-
-            // let sender = Arc::new(sender);
-            rt.block_on(async move {
-                let grpc_server_addr = format!("http://{}", zi);
-
-                let mut client = ZchronodClient::connect(grpc_server_addr).await.unwrap();
-
-
-                let request = tonic::Request::new(QueryEventRequest {
-                    eventid: event_id,
-                });
-
-                match client.query_by_event_id(request).await {
-                    Ok(response) => {
-                        let pl = response.into_inner().event;
-                        println!("Received response: {:?}", &pl);
-                        sender.send(pl).unwrap();
-                    }
-                    Err(_) => {
-                        println!("failed to send")
-                    }
-                }
-            });
-        });
-
-        let received = receiver.recv().unwrap().unwrap();
-        serde_json::to_string(&received).unwrap()
-    }
-
-    fn query_poll_list(&self) -> Vec<Vec<String>> {
-        println!("query poll list here");
-
-        let (sender, receiver) = mpsc::channel();
-        thread::spawn(move || {
-            let mut rt = Runtime::new().unwrap();
-            //let zi = self.zchronod_ip.clone();
-            let zi = "127.0.0.1:10020".to_string();
-
-
-            // let sender = Arc::new(sender);
-            rt.block_on(async move {
-                let grpc_server_addr = format!("http://{}", zi);
-
-                let mut client = ZchronodClient::connect(grpc_server_addr).await.unwrap();
-
-
-                let request = tonic::Request::new(Empty {});
-
-                match client.query_poll_list(request).await {
-                    Ok(response) => {
-                        let pl = response.into_inner().item;
-                        println!("Received response: {:?}", &pl);
-                        sender.send(pl).unwrap();
-                    }
-                    Err(_) => {
-                        println!("failed to send")
-                    }
-                }
-            });
-        });
-
-        let received_vec = receiver.recv().unwrap();
-        let mut vec_string = vec![];
-        for i in received_vec {
-            vec_string.push(i.poll_item);
-        }
-        // drop(rt);
-        vec_string
-    }
-    async fn send_to_chronod_event(&self, e: Event) {
-        println!("send to chronod_event here");
-        let mut rt = Runtime::new().unwrap();
-        //let zi = self.zchronod_ip.clone();
-        let zi = "127.0.0.1:10020".to_string();
-
-        println!("in send to chronod future");
-        let e_c = e.clone();
-        let grpc_server_addr = format!("http://{}", zi);
-
-        let mut client = ZchronodClient::connect(grpc_server_addr).await.unwrap();
-
-        let t: Vec<TagArray> = e_c.tags().into_iter()
-            .map(|inner_vec| TagArray { values: inner_vec.clone() })
-            .collect();
-        let request = tonic::Request::new(ZchronodRequest {
-            msg: Some(c_Event {
-                id: vec![1],
-                pubkey: vec![6, 7],
-                created_at: 2,
-                kind: 2,
-                tags: vec![],
-                content: "hello12333".to_string(),
-                sig: vec![7, 8, 9, 10],
-            })
-        });
-        info!("should send");
-        // let request = tonic::Request::new(ZchronodRequest {
-        //     msg: Some(c_Event {
-        //         id: Vec::from(e_c.id()),
-        //         pubkey: Vec::from(e_c.pubkey()),
-        //         created_at: get_current_system_time(),
-        //         kind: e_c.kind() as u32,
-        //         tags: t,
-        //         content: e_c.content().clone(),
-        //         sig: Vec::from(e_c.sig()),
-        //     })
-        // });
-        let response = client.send(request).await.unwrap().into_inner();
-        println!("Received response: {:?}", response);
-        // match client.send(request).await {
-        //     Ok(response) => {
-        //         println!("Received response: {:?}", response.into_inner());
-        //     }
-        //     Err(_) => {
-        //         println!("failed to send")
-        //     }
-        // }
-
-        //drop(rt);
-    }
-}
-
-async fn query_poll_state(tx: Sender<Vec<String>>, eventid: String) {
-    //  let mut rt = Runtime::new().unwrap();
-    //let zi = self.zchronod_ip.clone();
-    let zi = "127.0.0.1:10020".to_string();
-
-    // let sender = Arc::new(sender);
-
-    let grpc_server_addr = format!("http://{}", zi);
-
-    let mut client = ZchronodClient::connect(grpc_server_addr).await.unwrap();
-
-
-    let request = tonic::Request::new(QueryPollEventRequest {
-        eventid: eventid,
-    });
-
-    let resp = client.query_poll_event_state(request).await.unwrap().into_inner().state;
-
-    // let resp= client.query_poll_list(request).await.unwrap().into_inner().poll_list;
-    // let json_string = serde_json::to_string(&resp).unwrap();
-
-    tx.send(resp).expect("failed to send");
-    //     match client.query_poll_list(request).await {
-    //         Ok(response) => {
-    //             let pl = response.into_inner().poll_list;
-    //             println!("Received response: {:?}", &pl);
-    //             sender.send(pl).unwrap();
-    //         }
-    //         Err(_) => {
-    //             println!("failed to send")
-    //         }
-    // });
-    // let received_vec = receiver.recv().unwrap();
-    // drop(rt);
-    // received_vec
-}
-
-async fn send_to_chronod_event(e: Event) {
-    println!("send to chronod_event here");
-    //let zi = self.zchronod_ip.clone();
-    let zi = "127.0.0.1:10020".to_string();
-
-    println!("in send to chronod future");
-    let e_c = e.clone();
-    let grpc_server_addr = format!("http://{}", zi);
-    println!("{:?}", e_c.id());
-    let mut client = ZchronodClient::connect(grpc_server_addr).await.unwrap();
-
-    let t: Vec<TagArray> = e_c.tags().into_iter()
-        .map(|inner_vec| TagArray { values: inner_vec.clone() })
-        .collect();
-    // let request = tonic::Request::new(ZchronodRequest {
-    //     msg: Some(c_Event {
-    //         id: vec![1],
-    //         pubkey: vec![6, 7],
-    //         created_at: 2,
-    //         kind: 2,
-    //         tags: vec![],
-    //         content: "hello12333".to_string(),
-    //         sig: vec![7, 8, 9, 10],
-    //     })
-    // });
-    info!("should send");
-    println!("{:?}", e_c.id());
-    let hex_string_back: String = hex::encode(e_c.id());
-    println!("here string id back {:?}", hex_string_back);
-    let request = tonic::Request::new(ZchronodRequest {
-        msg: Some(c_Event {
-            id: Vec::from(e_c.id()),
-            pubkey: Vec::from(e_c.pubkey()),
-            created_at: get_current_system_time(),
-            kind: e_c.kind() as u32,
-            tags: t,
-            content: e_c.content().clone(),
-            sig: Vec::from(e_c.sig()),
-        })
-    });
-    let response = client.send(request).await.unwrap().into_inner();
-    println!("Received response: {:?}", response);
-    // match client.send(request).await {
-    //     Ok(response) => {
-    //         println!("Received response: {:?}", response.into_inner());
-    //     }
-    //     Err(_) => {
-    //         println!("failed to send")
-    //     }
-    // }
-}
-
 fn transfer_query(json_str: String) -> String {
     let json_value: Value = serde_json::from_str(&*json_str).unwrap();
     let mut event_id = "".to_string();
@@ -363,118 +200,168 @@ fn transfer_query(json_str: String) -> String {
     event_id
 }
 
+fn chronod_notice(message: &str) -> OutgoingMessage {
+    OutgoingMessage::notice(&format!("zchronod: {}", message))
+}
+
 /// Handler for Message message.
 impl Handler<ClientMessage> for Server {
     type Result = ();
     fn handle(&mut self, msg: ClientMessage, ctx: &mut Self::Context) {
-        println!("sglk: receive here {:?}", msg);
-        let msg_c = msg.clone();
-        // let event_id = transfer_query(msg_c.text);
-        // if event_id !="" {
-        //     info!("receive query poll here");
-        //     println!("receive query poll here");
-        //     let (tx, recv) = mpsc::channel();
-        //     let cs = event_id.clone();
-        //     thread::spawn(move || {
-        //         let mut rt = Runtime::new().unwrap();
-        //         rt.block_on(async { query_poll_state(tx, cs).await; })
-        //     });
-        //
-        //     let poll_list = recv.recv().unwrap();
-        //     let json_string = json!(poll_list);
-        //     //  let json_string = serde_json::to_string(&poll_list).unwrap();
-        //     self.send_to_client(msg.id, OutgoingMessage {
-        //         0: json_string.to_string(),
-        //     });
-        // }
+        let session_id = msg.id;
         match msg.msg {
-            QueryEventMeta(s) => {
+            QueryEventMeta(req_id, query) => {
                 info!("receive query event meta here");
-                println!("receive query event meta here");
-                let e = self.query_event_meta(s);
-                println!("query event meta[{:?}]", e);
-                self.send_to_client(msg.id, OutgoingMessage {
-                    0: e,
-                });
+                self.query_chronod(
+                    ctx,
+                    session_id,
+                    req_id,
+                    chronod::QueryEventMeta(query.id),
+                    |event| match event {
+                        Some(event) => Ok(json!({ "event": chronod::event_json(&event) })),
+                        None => Err("not found: event".to_owned()),
+                    },
+                    |event| json!(event),
+                );
             }
-            QueryPollList(_) => {
+            QueryPollList(req_id, query) => {
                 info!("receive query poll list here");
-                println!("receive query poll list here");
-                let poll_list = self.query_poll_list();
-                // key is 3041_event-id_state
-                println!("receive poll_list");
-                //  let mut list = vec!();
-                // for element in poll_list {
-                //     let hex_string_back: String = hex::encode(element);
-                //     println!("{:?}", &hex_string_back);
-                //     list.push(hex_string_back);
-                // }
-                let json_string = json!(poll_list);
-                self.send_to_client(msg.id, OutgoingMessage {
-                    0: json_string.to_string(),
-                });
+                let request = zchronod::PollListRequest {
+                    cursor: query.cursor.unwrap_or_default(),
+                    limit: query.limit.unwrap_or_default(),
+                    author: query.author.unwrap_or_default(),
+                    status: match query.status {
+                        None => zchronod::PollStatus::Any,
+                        Some(PollStatus::Open) => zchronod::PollStatus::Open,
+                        Some(PollStatus::Closed) => zchronod::PollStatus::Closed,
+                    } as i32,
+                };
+                self.query_chronod(
+                    ctx,
+                    session_id,
+                    req_id,
+                    chronod::QueryPollList(request),
+                    |page| {
+                        // item is [poll id, title, info]
+                        let polls = page
+                            .item
+                            .iter()
+                            .map(|item| {
+                                json!({
+                                    "id": item.poll_item.first(),
+                                    "title": item.poll_item.get(1),
+                                    "info": item.poll_item.get(2),
+                                    "author": item.author,
+                                    "created_at": item.created_at,
+                                    "closed": item.closed,
+                                })
+                            })
+                            .collect::<Vec<_>>();
+                        let cursor = Some(page.cursor).filter(|cursor| !cursor.is_empty());
+                        Ok(json!({ "polls": polls, "cursor": cursor }))
+                    },
+                    |page| json!(page.item.into_iter().map(|item| item.poll_item).collect::<Vec<_>>()),
+                );
             }
-            Query(s) => {
+            Query(req_id, query) => {
                 info!("receive query poll here");
-                println!("receive query poll here");
-                let (tx, recv) = mpsc::channel();
-                let cs = s.clone();
-                thread::spawn(move || {
-                    let mut rt = Runtime::new().unwrap();
-                    rt.block_on(async { query_poll_state(tx, cs.id).await; })
-                });
-
-                let poll_state = recv.recv().unwrap();
-                println!("query poll state, result is [{:?}]", poll_state);
-                let json_string = json!(poll_state);
-                //  let json_string = serde_json::to_string(&poll_list).unwrap();
-                self.send_to_client(msg.id, OutgoingMessage {
-                    0: json_string.to_string(),
-                });
+                let poll_id = query.id.clone();
+                self.query_chronod(
+                    ctx,
+                    session_id,
+                    req_id,
+                    chronod::QueryPollState(query.id),
+                    move |poll_state| Ok(chronod::poll_state_json(&poll_id, &poll_state.state)),
+                    |poll_state| json!(poll_state.state),
+                );
+            }
+            IncomingMessage::SubscribePoll(sub_id, query) => {
+                let poll_id = match hex::decode(&query.id).ok().and_then(|id| <[u8; 32]>::try_from(id).ok()) {
+                    Some(poll_id) => poll_id,
+                    None => {
+                        self.send_to_client(session_id, OutgoingMessage::closed(&sub_id, "invalid: poll id"));
+                        return;
+                    }
+                };
+                self.subscriber
+                    .send(SubscribePoll {
+                        id: session_id,
+                        sub_id: sub_id.clone(),
+                        poll_id,
+                    })
+                    .into_actor(self)
+                    .then(move |res, act, _ctx| {
+                        let reason = match res {
+                            Ok(Subscribed::Ok) => return fut::ready(()),
+                            Ok(Subscribed::Overlimit) => "error: number of subscriptions exceeds limit",
+                            Ok(Subscribed::InvalidIdLength) => {
+                                "invalid: subscription id should be non-empty string of max length 64 chars"
+                            }
+                            Err(_err) => "error: something is wrong",
+                        };
+                        act.send_to_client(session_id, OutgoingMessage::closed(&sub_id, reason));
+                        fut::ready(())
+                    })
+                    .spawn(ctx);
             }
             IncomingMessage::Event(event) => {
-                println!("receive event here");
                 // save all event
                 // save ephemeral for check duplicate, disconnection recovery, will be deleted
-                let cp = event.clone();
-                let handle1 = thread::spawn(move || {
-                    let cpp = cp.clone();
-                    let mut rt = Runtime::new().unwrap();
-                    rt.block_on(send_to_chronod_event(cpp))
-                });
-                handle1.join().unwrap();
-                self.send_to_client(msg.id, OutgoingMessage {
-                    0: "zchronod has received".to_string(),
-                });
-                println!("should send back [{:?}]", msg.id);
-                //self.send_to_chronod_event(event.clone());
-                self.writer.do_send(WriteEvent { id: msg.id, event });
-                // test query
-                // let poll_list = self.query_poll_list();
-                // // key is 3041_event-id_state
-                // println!("receive poll_list");
-                // for element in poll_list {
-                //     //    let hex_string_back: String = hex::encode(element);
-                //     println!("{:?}", &element);
-                // }
-
-                // test poll state
-                // let (tx, recv) = mpsc::channel();
-                // thread::spawn(move || {
-                //     let mut rt = Runtime::new().unwrap();
-                //     rt.block_on(async { query_poll_state(tx, "082155c14942cbe52fcc188711cdce699c812da4532d55af34cc557ae6728b98".to_string()).await; })
-                // });
-                //
-                // let poll_state = recv.recv().unwrap();
-                // println!("receive poll_state");
-                // println!("{:?}", poll_state);
+                let policy = self.setting.read().zchronod.policy;
+                if policy == ZchronodPolicy::LocalOnly {
+                    self.write_event(session_id, event, None);
+                } else {
+                    self.chronod
+                        .send(chronod::SendEvent(event.clone()))
+                        .into_actor(self)
+                        .then(move |res, act, _ctx| {
+                            let res = match res {
+                                Ok(res) => res,
+                                Err(err) => Err(Status::unavailable(err.to_string()).into()),
+                            };
+                            match res {
+                                Ok(()) => {
+                                    act.write_event(session_id, event, Some("zchronod: accepted".to_owned()));
+                                }
+                                // zchronod refused the event: bad id or signature, or no permission
+                                Err(status) if matches!(
+                                    status.code(),
+                                    Code::InvalidArgument | Code::Unauthenticated | Code::PermissionDenied
+                                ) => {
+                                    info!("zchronod rejected event {}: {}", event.id_str(), status.message());
+                                    act.send_to_client(
+                                        session_id,
+                                        OutgoingMessage::ok(&event.id_str(), false, status.message()),
+                                    );
+                                }
+                                Err(status) => {
+                                    info!("failed to send event {} to zchronod: {}", event.id_str(), status);
+                                    let note = format!("zchronod unavailable: {}", status.message());
+                                    if policy == ZchronodPolicy::Require {
+                                        act.send_to_client(
+                                            session_id,
+                                            OutgoingMessage::ok(&event.id_str(), false, &format!("error: {}", note)),
+                                        );
+                                    } else {
+                                        act.write_event(session_id, event, Some(note));
+                                    }
+                                }
+                            }
+                            fut::ready(())
+                        })
+                        .spawn(ctx);
+                }
             }
+            IncomingMessage::Graph(cmd, query) => self.reader.do_send(ReadGraph {
+                id: msg.id,
+                cmd,
+                query,
+            }),
             IncomingMessage::Close(id) => self.subscriber.do_send(Unsubscribe {
                 id: msg.id,
                 sub_id: Some(id),
             }),
             IncomingMessage::Req(subscription) => {
-                let session_id = msg.id;
                 let read_event = ReadEvent {
                     id: msg.id,
                     subscription: subscription.clone(),
@@ -530,28 +417,27 @@ impl Handler<WriteEventResult> for Server {
         match msg {
             WriteEventResult::Write { id, event, result } => {
                 let event_id = event.id_str();
-                let out_msg = match &result {
-                    CheckEventResult::Ok(_num) => OutgoingMessage::ok(&event_id, true, ""),
-                    CheckEventResult::Duplicate => {
-                        OutgoingMessage::ok(&event_id, true, "duplicate: event exists")
-                    }
-                    CheckEventResult::Invald(msg) => {
-                        OutgoingMessage::ok(&event_id, false, &format!("invalid: {}", msg))
-                    }
+                let note = self.chronod_notes.remove(&(id, *event.id()));
+                let (saved, message) = match &result {
+                    CheckEventResult::Ok(_num) => (true, "".to_owned()),
+                    CheckEventResult::Duplicate => (true, "duplicate: event exists".to_owned()),
+                    CheckEventResult::Invald(msg) => (false, format!("invalid: {}", msg)),
                     CheckEventResult::Deleted => {
-                        OutgoingMessage::ok(&event_id, false, "deleted: user requested deletion")
+                        (false, "deleted: user requested deletion".to_owned())
                     }
                     CheckEventResult::ReplaceIgnored => {
-                        OutgoingMessage::ok(&event_id, false, "replaced: have newer event")
+                        (false, "replaced: have newer event".to_owned())
                     }
                 };
+                let out_msg = OutgoingMessage::ok(&event_id, saved, &with_note(&message, note));
                 self.send_to_client(id, out_msg);
                 // dispatch event to subscriber
                 if let CheckEventResult::Ok(_num) = result {
                     self.subscriber.do_send(Dispatch { id, event });
                 }
             }
-            WriteEventResult::Message { id, event: _, msg } => {
+            WriteEventResult::Message { id, event, msg } => {
+                self.chronod_notes.remove(&(id, *event.id()));
                 self.send_to_client(id, msg);
             }
         }
@@ -575,6 +461,11 @@ impl Handler<SubscribeResult> for Server {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::zchronod::{
+        zchronod_server::{Zchronod, ZchronodServer},
+        Event as ZEvent, EventMeta, PollEventState, PollItem, PollListRequest, PollListResponse,
+        QueryEventRequest, QueryPollEventRequest, TagArray, ZchronodRequest, ZchronodResp,
+    };
     use crate::{temp_data_path, Setting};
     use actix_rt::time::sleep;
     use anyhow::Result;
@@ -746,4 +637,358 @@ mod tests {
 
         Ok(())
     }
+
+    /// Zchronod rejecting events with the content "reject", knowing the event 01.. and the
+    /// polls 03.. closed after 2 votes by its clock limit, 04.. ending in a second,
+    /// 05.. ended and 07.. ended at a datetime
+    #[derive(Default)]
+    struct MockZchronod {
+        /// poll id -> votes
+        votes: std::sync::Mutex<HashMap<String, u64>>,
+    }
+
+    /// The poll `end` as zchronod parses it
+    fn poll_end(id: u8) -> i64 {
+        match id {
+            3 => 253402300799,
+            4 => nostr_db::now() as i64 + 1,
+            7 => 1708504620,
+            _ => 1,
+        }
+    }
+
+    fn poll(id: u8, clock: u64, end: &str) -> ZEvent {
+        let tag = ["poll", "single", &clock.to_string(), "0", end, "lunch", "pick one", "pizza", "salad"];
+        ZEvent {
+            id: vec![id; 32],
+            kind: 301,
+            tags: vec![TagArray {
+                values: tag.map(str::to_owned).to_vec(),
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[tonic::async_trait]
+    impl Zchronod for MockZchronod {
+        async fn send(
+            &self,
+            request: tonic::Request<ZchronodRequest>,
+        ) -> Result<tonic::Response<ZchronodResp>, Status> {
+            match request.into_inner().msg {
+                Some(event) if event.content == "reject" => {
+                    Err(Status::permission_denied("restricted: no permission"))
+                }
+                Some(event) if event.kind == 309 => {
+                    let poll_id = event.tags[0].values[1].clone();
+                    *self.votes.lock().unwrap().entry(poll_id).or_default() += 1;
+                    Ok(tonic::Response::new(ZchronodResp { resp: None }))
+                }
+                _ => Ok(tonic::Response::new(ZchronodResp { resp: None })),
+            }
+        }
+        async fn query_poll_list(
+            &self,
+            request: tonic::Request<PollListRequest>,
+        ) -> Result<tonic::Response<PollListResponse>, Status> {
+            // two polls, a page of one poll is cut after 01
+            let request = request.into_inner();
+            let items = [("01", "lunch", "pick one", false), ("02", "dinner", "", true)]
+                .into_iter()
+                .skip(if request.cursor == "c1" { 1 } else { 0 })
+                .filter(|(_, _, _, closed)| request.status == 0 || (request.status == 2) == *closed)
+                .take(if request.limit == 0 { 100 } else { request.limit as usize })
+                .map(|(id, title, info, closed)| PollItem {
+                    poll_item: vec![id.to_owned(), title.to_owned(), info.to_owned()],
+                    author: "02".repeat(32),
+                    created_at: 10,
+                    closed,
+                })
+                .collect::<Vec<_>>();
+            let cursor = if request.limit == 1 && items.first().is_some_and(|item| item.poll_item[0] == "01") {
+                "c1"
+            } else {
+                ""
+            };
+            Ok(tonic::Response::new(PollListResponse {
+                item: items,
+                cursor: cursor.to_owned(),
+            }))
+        }
+        async fn query_poll_event_state(
+            &self,
+            request: tonic::Request<QueryPollEventRequest>,
+        ) -> Result<tonic::Response<PollEventState>, Status> {
+            let poll_id = request.into_inner().eventid;
+            let id = match poll_id.get(..4) {
+                _ if poll_id == "01" => 1,
+                Some("0303") => 3,
+                Some("0404") => 4,
+                Some("0505") => 5,
+                Some("0707") => 7,
+                _ => return Err(Status::not_found("poll")),
+            };
+            let added = self.votes.lock().unwrap().get(&poll_id).copied().unwrap_or_default();
+            let votes = 2 + added;
+            let state = vec!["pizza".to_owned(), votes.to_string(), "salad".to_owned(), "0".to_owned()];
+            let end = poll_end(id);
+            // every vote ticks the clock of the mock
+            let closed = end <= nostr_db::now() as i64 || (id == 3 && added >= 2);
+            Ok(tonic::Response::new(PollEventState {
+                state,
+                end: Some(end),
+                closed,
+            }))
+        }
+        async fn query_by_event_id(
+            &self,
+            request: tonic::Request<QueryEventRequest>,
+        ) -> Result<tonic::Response<EventMeta>, Status> {
+            let event = match request.into_inner().eventid.get(..2) {
+                Some("01") => ZEvent {
+                    id: vec![1; 32],
+                    pubkey: vec![2; 32],
+                    created_at: 10,
+                    kind: 1,
+                    tags: vec![TagArray {
+                        values: vec!["t".to_owned(), "nostr".to_owned()],
+                    }],
+                    content: "gm".to_owned(),
+                    sig: vec![0; 64],
+                },
+                Some("03") => poll(3, 4, "9999-12-31T23:59:59"),
+                Some("04") => poll(4, 0, &poll_end(4).to_string()),
+                Some("05") => poll(5, 0, "1"),
+                Some("07") => poll(7, 0, "2024-02-21T08:37"),
+                _ => return Err(Status::invalid_argument("event id is invalid")),
+            };
+            Ok(tonic::Response::new(EventMeta { event: Some(event) }))
+        }
+    }
+
+    async fn mock_zchronod() -> Result<String> {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let mock = format!("127.0.0.1:{}", port);
+        let serve = tonic::transport::Server::builder()
+            .add_service(ZchronodServer::new(MockZchronod::default()))
+            .serve(mock.parse()?);
+        actix_rt::spawn(serve);
+        sleep(Duration::from_millis(100)).await;
+        Ok(mock)
+    }
+
+    #[actix_rt::test]
+    async fn zchronod_policy() -> Result<()> {
+        let mock = mock_zchronod().await?;
+
+        let db = Arc::new(Db::open(temp_data_path("server_zchronod")?)?);
+        let mut setting = Setting::default();
+        setting.zchronod.ip = mock.clone();
+        setting.zchronod.policy = ZchronodPolicy::Require;
+        let setting: SettingWrapper = setting.into();
+
+        let receiver = Receiver::default();
+        let messages = receiver.0.clone();
+        let addr = receiver.start().recipient();
+        let server = Server::create_with(Arc::clone(&db), setting.clone());
+        let id = server.send(Connect { addr }).await?;
+
+        let cases = [
+            (ZchronodPolicy::Require, mock.as_str(), "", true, "zchronod: accepted"),
+            (ZchronodPolicy::Require, mock.as_str(), "reject", false, "restricted: no permission"),
+            (ZchronodPolicy::BestEffort, mock.as_str(), "reject", false, "restricted: no permission"),
+            (ZchronodPolicy::LocalOnly, mock.as_str(), "reject", true, ""),
+            // nothing listens on port 1
+            (ZchronodPolicy::Require, "127.0.0.1:1", "", false, "error: zchronod unavailable"),
+            (ZchronodPolicy::BestEffort, "127.0.0.1:1", "", true, "zchronod unavailable"),
+        ];
+        for (i, (policy, ip, content, saved, message)) in cases.into_iter().enumerate() {
+            {
+                let mut w = setting.write();
+                w.zchronod.policy = policy;
+                w.zchronod.ip = ip.to_owned();
+            }
+            let event = Event::new([i as u8; 32], [1; 32], 10, 1, vec![], content.to_owned(), [0; 64])?;
+            let text = format!(r#"["EVENT", {}]"#, event.to_json()?);
+            let msg = IncomingMessage::Event(event.clone());
+            server.send(ClientMessage { id, text, msg }).await?;
+            sleep(Duration::from_millis(300)).await;
+
+            let mut w = messages.write();
+            assert_eq!(w.len(), 1, "case {}", i);
+            let ok: Value = serde_json::from_str(&w[0].0)?;
+            assert_eq!(ok[0], "OK");
+            assert_eq!(ok[1], event.id_str());
+            assert_eq!(ok[2], saved, "case {}", i);
+            assert!(ok[3].as_str().unwrap().starts_with(message), "case {}: {}", i, ok[3]);
+            w.clear();
+
+            let reader = db.reader()?;
+            let stored = db.get::<String, _, _>(&reader, event.id())?;
+            assert_eq!(stored.is_some(), saved, "case {}", i);
+        }
+        Ok(())
+    }
+    #[actix_rt::test]
+    async fn zchronod_query() -> Result<()> {
+        let mut setting = Setting::default();
+        setting.zchronod.ip = mock_zchronod().await?;
+        let setting: SettingWrapper = setting.into();
+
+        let receiver = Receiver::default();
+        let messages = receiver.0.clone();
+        let addr = receiver.start().recipient();
+        let db = Arc::new(Db::open(temp_data_path("server_zchronod_query")?)?);
+        let server = Server::create_with(db, setting.clone());
+        let id = server.send(Connect { addr }).await?;
+
+        let cases = [
+            (
+                r#"["QUERY", "q1", {"id": "01"}]"#,
+                json!(["RESULT", "q1", {"id": "01", "options": [
+                    {"option": "pizza", "votes": 2}, {"option": "salad", "votes": 0}
+                ]}]),
+            ),
+            (r#"["QUERY", "q2", {"id": "02"}]"#, json!(["CLOSED", "q2", "not found: poll"])),
+            (
+                r#"["QUERYPOLLLIST", "q3", {"limit": 1}]"#,
+                json!(["RESULT", "q3", {"polls": [{
+                    "id": "01", "title": "lunch", "info": "pick one",
+                    "author": "02".repeat(32), "created_at": 10, "closed": false,
+                }], "cursor": "c1"}]),
+            ),
+            (
+                r#"["QUERYPOLLLIST", "q3", {"limit": 1, "cursor": "c1"}]"#,
+                json!(["RESULT", "q3", {"polls": [{
+                    "id": "02", "title": "dinner", "info": "",
+                    "author": "02".repeat(32), "created_at": 10, "closed": true,
+                }], "cursor": null}]),
+            ),
+            (
+                r#"["QUERYPOLLLIST", "q3", {"status": "open"}]"#,
+                json!(["RESULT", "q3", {"polls": [{
+                    "id": "01", "title": "lunch", "info": "pick one",
+                    "author": "02".repeat(32), "created_at": 10, "closed": false,
+                }], "cursor": null}]),
+            ),
+            (
+                r#"["QUERYEVENTMETA", "q4", {"id": "01"}]"#,
+                json!(["RESULT", "q4", {"event": {
+                    "id": "01".repeat(32),
+                    "pubkey": "02".repeat(32),
+                    "created_at": 10,
+                    "kind": 1,
+                    "tags": [["t", "nostr"]],
+                    "content": "gm",
+                    "sig": "00".repeat(64),
+                }}]),
+            ),
+            (
+                r#"["QUERYEVENTMETA", "q5", {"id": "02"}]"#,
+                json!(["CLOSED", "q5", "invalid: event id is invalid"]),
+            ),
+            // legacy
+            (r#"["QUERY", {"id": "01"}]"#, json!(["pizza", "2", "salad", "0"])),
+            (r#"["QUERYPOLLLIST", ""]"#, json!([["01", "lunch", "pick one"], ["02", "dinner", ""]])),
+        ];
+        for (text, expected) in cases {
+            let msg = serde_json::from_str::<IncomingMessage>(text)?;
+            server.send(ClientMessage { id, text: text.to_owned(), msg }).await?;
+            sleep(Duration::from_millis(200)).await;
+            let mut w = messages.write();
+            assert_eq!(w.len(), 1, "{}", text);
+            assert_eq!(serde_json::from_str::<Value>(&w[0].0)?, expected, "{}", text);
+            w.clear();
+        }
+
+        setting.write().zchronod.legacy_query = false;
+        let text = r#"["QUERYPOLLLIST", ""]"#;
+        let msg = serde_json::from_str::<IncomingMessage>(text)?;
+        server.send(ClientMessage { id, text: text.to_owned(), msg }).await?;
+        sleep(Duration::from_millis(200)).await;
+        let w = messages.read();
+        assert_eq!(w.len(), 1);
+        assert!(w[0].0.contains("request id required"));
+        Ok(())
+    }
+
+    #[actix_rt::test]
+    async fn poll_subscription() -> Result<()> {
+        let mut setting = Setting::default();
+        setting.zchronod.ip = mock_zchronod().await?;
+        let receiver = Receiver::default();
+        let messages = receiver.0.clone();
+        let addr = receiver.start().recipient();
+        let db = Arc::new(Db::open(temp_data_path("server_poll_subscription")?)?);
+        let server = Server::create_with(db, setting.into());
+        let id = server.send(Connect { addr }).await?;
+
+        let send = |text: String| {
+            let server = server.clone();
+            let messages = messages.clone();
+            async move {
+                let msg = serde_json::from_str::<IncomingMessage>(&text)?;
+                server.send(ClientMessage { id, text, msg }).await?;
+                sleep(Duration::from_millis(300)).await;
+                let mut w = messages.write();
+                let received = w
+                    .drain(..)
+                    .map(|msg| serde_json::from_str::<Value>(&msg.0))
+                    .collect::<Result<Vec<_>, _>>()?;
+                anyhow::Ok(received)
+            }
+        };
+        let result = |sub_id: &str, poll: &str, votes: u64| {
+            json!(["RESULT", sub_id, {"id": poll, "options": [
+                {"option": "pizza", "votes": votes}, {"option": "salad", "votes": 0}
+            ]}])
+        };
+        let vote = |i: u8, poll: &str| -> Result<String> {
+            let tags = vec![vec!["e".to_owned(), poll.to_owned()], vec!["poll_r".to_owned(), "0".to_owned()]];
+            let event = Event::new([i; 32], [1; 32], 10, 309, tags, "".to_owned(), [0; 64])?;
+            Ok(format!(r#"["EVENT", {}]"#, event.to_json()?))
+        };
+
+        // closed when the clock limit is reached, open until its datetime end
+        let poll = "03".repeat(32);
+        let received = send(format!(r#"["SUBPOLL", "s1", {{"id": "{}"}}]"#, poll)).await?;
+        assert_eq!(received, vec![result("s1", &poll, 2)]);
+        let received = send(vote(1, &poll)?).await?;
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0][0], "OK");
+        assert_eq!(received[1], result("s1", &poll, 3));
+        let received = send(vote(2, &poll)?).await?;
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[1], result("s1", &poll, 4));
+        assert_eq!(received[2], json!(["CLOSED", "s1", "closed: poll clock limit reached"]));
+        let received = send(vote(3, &poll)?).await?;
+        assert_eq!(received.len(), 1);
+
+        // closed at the end of the poll
+        let poll = "04".repeat(32);
+        let received = send(format!(r#"["SUBPOLL", "s2", {{"id": "{}"}}]"#, poll)).await?;
+        assert_eq!(received, vec![result("s2", &poll, 2)]);
+        sleep(Duration::from_millis(2000)).await;
+        let received = send(vote(4, &poll)?).await?;
+        assert_eq!(received[0], json!(["CLOSED", "s2", "closed: poll ended"]));
+        assert_eq!(received.len(), 2);
+
+        // closed by the client
+        let poll = "03".repeat(32);
+        send(format!(r#"["SUBPOLL", "s3", {{"id": "{}"}}]"#, poll)).await?;
+        send(r#"["CLOSE", "s3"]"#.to_owned()).await?;
+        assert_eq!(send(vote(5, &poll)?).await?.len(), 1);
+
+        let cases = [
+            (format!(r#"["SUBPOLL", "s4", {{"id": "{}"}}]"#, "05".repeat(32)), "closed: poll ended"),
+            (format!(r#"["SUBPOLL", "s4", {{"id": "{}"}}]"#, "07".repeat(32)), "closed: poll ended"),
+            (format!(r#"["SUBPOLL", "s4", {{"id": "{}"}}]"#, "06".repeat(32)), "invalid: event id is invalid"),
+            (format!(r#"["SUBPOLL", "s4", {{"id": "{}"}}]"#, "01".repeat(32)), "invalid: not a poll"),
+            (r#"["SUBPOLL", "s4", {"id": "01"}]"#.to_owned(), "invalid: poll id"),
+        ];
+        for (text, reason) in cases {
+            assert_eq!(send(text.clone()).await?, vec![json!(["CLOSED", "s4", reason])], "{}", text);
+        }
+        Ok(())
+    }
 }
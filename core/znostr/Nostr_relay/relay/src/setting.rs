@@ -139,6 +139,8 @@ pub struct Limitation {
     pub max_event_time_older_than_now: u64,
     /// Events newer than this will be rejected. default 15 minutes, 0 ignore
     pub max_event_time_newer_than_now: u64,
+    /// maximum number of edges a causal graph query walks from its event. default 64
+    pub max_graph_depth: usize,
 }
 
 impl Default for Limitation {
@@ -153,6 +155,7 @@ impl Default for Limitation {
             max_event_tags: 5000,
             max_event_time_older_than_now: 94608000,
             max_event_time_newer_than_now: 900,
+            max_graph_depth: 64,
         }
     }
 }
@@ -186,10 +189,51 @@ pub struct Setting {
 }
 
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+/// zchronod connection config
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 #[serde(default)]
 pub struct Zchronod {
+    /// grpc address, host:port
     pub ip: String,
+    /// connect timeout, default 3 seconds
+    pub connect_timeout: NonZeroDuration,
+    /// request timeout, default 5 seconds
+    pub timeout: NonZeroDuration,
+    /// delay before reconnecting after a failed request, doubles up to max_backoff. default 500ms
+    pub backoff: NonZeroDuration,
+    /// default 30 seconds
+    pub max_backoff: NonZeroDuration,
+    /// whether events are stored without zchronod accepting them
+    pub policy: ZchronodPolicy,
+    /// answer nip-3041 queries without a request id in the old format, default true
+    pub legacy_query: bool,
+}
+
+/// What the relay does with an event zchronod does not confirm
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ZchronodPolicy {
+    /// only store events zchronod accepted
+    Require,
+    /// store events zchronod did not reject, also when it is unavailable
+    #[default]
+    BestEffort,
+    /// store events without sending them to zchronod
+    LocalOnly,
+}
+
+impl Default for Zchronod {
+    fn default() -> Self {
+        Self {
+            ip: "127.0.0.1:10020".to_string(),
+            connect_timeout: Duration::from_secs(3).try_into().unwrap(),
+            timeout: Duration::from_secs(5).try_into().unwrap(),
+            backoff: Duration::from_millis(500).try_into().unwrap(),
+            max_backoff: Duration::from_secs(30).try_into().unwrap(),
+            policy: ZchronodPolicy::default(),
+            legacy_query: true,
+        }
+    }
 }
 
 impl PartialEq for Setting {
@@ -200,6 +244,7 @@ impl PartialEq for Setting {
             && self.network == other.network
             && self.limitation == other.limitation
             && self.extra == other.extra
+            && self.zchronod == other.zchronod
     }
 }
 
@@ -3,9 +3,19 @@ use std::{
     rc::{Rc, Weak},
 };
 
-use crate::{message::*, setting::SettingWrapper};
+use crate::{
+    chronod::{self, Chronod},
+    message::*,
+    setting::SettingWrapper,
+    zchronod::Event as ZEvent,
+};
 use actix::prelude::*;
-use nostr_db::{EventIndex, Filter};
+use nostr_db::{now, EventIndex, Filter};
+use std::time::Duration;
+use tracing::info;
+
+/// nip-3041 vote
+const VOTE_KIND: u16 = 309;
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 struct Key {
@@ -250,23 +260,126 @@ impl SubscriberIndex {
     }
 }
 
+/// A live nip-3041 poll result subscription
+#[derive(Debug)]
+struct PollWatch {
+    poll_id: String,
+    /// closes the subscription at the poll end
+    timer: Option<SpawnHandle>,
+}
+
+/// A kind 301 event with a `poll` tag, zchronod parses the tag
+fn is_poll(event: &ZEvent) -> bool {
+    event.kind == 301
+        && event
+            .tags
+            .iter()
+            .any(|tag| tag.values.first().map(String::as_str) == Some("poll"))
+}
+
 pub struct Subscriber {
     pub addr: Recipient<SubscribeResult>,
     /// map session_id -> subscription_id -> filters
     pub subscriptions: HashMap<usize, HashMap<String, Vec<Filter>>>,
     pub index: SubscriberIndex,
     pub setting: SettingWrapper,
+    chronod: Addr<Chronod>,
+    /// (session_id, subscription_id) -> poll, votes of these subscriptions are sent as poll results
+    polls: HashMap<(usize, String), PollWatch>,
 }
 
 impl Subscriber {
-    pub fn new(addr: Recipient<SubscribeResult>, setting: SettingWrapper) -> Self {
+    pub fn new(addr: Recipient<SubscribeResult>, chronod: Addr<Chronod>, setting: SettingWrapper) -> Self {
         Self {
             addr,
             subscriptions: HashMap::new(),
             setting,
             index: SubscriberIndex::default(),
+            chronod,
+            polls: HashMap::new(),
+        }
+    }
+
+    fn send(&self, id: usize, sub_id: &str, msg: OutgoingMessage) {
+        self.addr.do_send(SubscribeResult {
+            id,
+            sub_id: sub_id.to_owned(),
+            msg,
+        });
+    }
+
+    fn remove_polls(&mut self, ctx: &mut Context<Self>, id: usize, sub_id: Option<&String>) {
+        let keys = self
+            .polls
+            .keys()
+            .filter(|(session_id, poll_sub_id)| *session_id == id && sub_id.is_none_or(|s| s == poll_sub_id))
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in keys {
+            if let Some(timer) = self.polls.remove(&key).and_then(|watch| watch.timer) {
+                ctx.cancel_future(timer);
+            }
+        }
+    }
+
+    /// End a poll subscription, telling the client why
+    fn close_poll(&mut self, ctx: &mut Context<Self>, key: &(usize, String), reason: &str) {
+        if self.polls.contains_key(key) {
+            self.remove_polls(ctx, key.0, Some(&key.1));
+            self.index.remove(key.0, Some(&key.1));
+            self.send(key.0, &key.1, OutgoingMessage::closed(&key.1, reason));
         }
     }
+
+    /// Send the current state of the poll to `only` or all its subscriptions,
+    /// closing them once zchronod reports the poll closed. `only` is a new
+    /// subscription, it gets the end timer and no result for a closed poll.
+    fn update_poll(&mut self, ctx: &mut Context<Self>, poll_id: String, only: Option<(usize, String)>) {
+        self.chronod
+            .send(chronod::QueryPollState(poll_id.clone()))
+            .into_actor(self)
+            .then(move |res, act, ctx| {
+                let state = match res {
+                    Ok(Ok(state)) => state,
+                    Ok(Err(status)) => {
+                        info!("failed to query poll {}: {}", poll_id, status.message());
+                        return fut::ready(());
+                    }
+                    Err(err) => {
+                        info!("failed to query poll {}: {}", poll_id, err);
+                        return fut::ready(());
+                    }
+                };
+                let result = chronod::poll_state_json(&poll_id, &state.state);
+                let ended = state.end.is_some_and(|end| end <= now() as i64);
+                let keys = act
+                    .polls
+                    .iter()
+                    .filter(|(key, watch)| watch.poll_id == poll_id && only.as_ref().is_none_or(|only| only == *key))
+                    .map(|(key, _)| key.clone())
+                    .collect::<Vec<_>>();
+                for key in keys {
+                    if !(state.closed && only.is_some()) {
+                        act.send(key.0, &key.1, OutgoingMessage::result(&key.1, result.clone()));
+                    }
+                    if ended {
+                        act.close_poll(ctx, &key, "closed: poll ended");
+                    } else if state.closed {
+                        act.close_poll(ctx, &key, "closed: poll clock limit reached");
+                    } else if let (Some(end), Some(_)) = (state.end, &only) {
+                        let timer_key = key.clone();
+                        let timer = ctx.run_later(Duration::from_secs(end as u64 - now()), move |act, ctx| {
+                            act.close_poll(ctx, &timer_key, "closed: poll ended");
+                        });
+                        if let Some(watch) = act.polls.get_mut(&key) {
+                            watch.timer = Some(timer);
+                        }
+                    }
+                }
+                fut::ready(())
+            })
+            .spawn(ctx);
+    }
 }
 
 impl Actor for Subscriber {
@@ -288,26 +401,99 @@ impl Handler<Subscribe> for Subscriber {
     }
 }
 
+/// Poll results are sent when subscribed and after every vote for the poll,
+/// the subscription closes at the end of the poll or when its clock limit is reached.
+impl Handler<SubscribePoll> for Subscriber {
+    type Result = Subscribed;
+    fn handle(&mut self, msg: SubscribePoll, ctx: &mut Self::Context) -> Subscribed {
+        let votes = Filter {
+            kinds: vec![VOTE_KIND].into(),
+            tags: HashMap::from([(b"e".to_vec(), vec![msg.poll_id.to_vec()].into())]),
+            ..Default::default()
+        };
+        let res = self.index.add(
+            msg.id,
+            msg.sub_id.clone(),
+            vec![votes],
+            self.setting.read().limitation.max_subscriptions,
+        );
+        if res != Subscribed::Ok {
+            return res;
+        }
+        let key = (msg.id, msg.sub_id);
+        let poll_id = hex::encode(msg.poll_id);
+        self.remove_polls(ctx, key.0, Some(&key.1));
+        self.polls.insert(
+            key.clone(),
+            PollWatch {
+                poll_id: poll_id.clone(),
+                timer: None,
+            },
+        );
+
+        self.chronod
+            .send(chronod::QueryEventMeta(poll_id.clone()))
+            .into_actor(self)
+            .then(move |res, act, ctx| {
+                // unsubscribed meanwhile
+                if act.polls.get(&key).is_none_or(|watch| watch.poll_id != poll_id) {
+                    return fut::ready(());
+                }
+                let event = match res {
+                    Ok(Ok(event)) => event,
+                    Ok(Err(status)) => {
+                        act.close_poll(ctx, &key, &chronod::closed_reason(&status));
+                        return fut::ready(());
+                    }
+                    Err(err) => {
+                        act.close_poll(ctx, &key, &format!("error: zchronod: {}", err));
+                        return fut::ready(());
+                    }
+                };
+                if !event.as_ref().is_some_and(is_poll) {
+                    act.close_poll(ctx, &key, "invalid: not a poll");
+                    return fut::ready(());
+                }
+                act.update_poll(ctx, poll_id, Some(key));
+                fut::ready(())
+            })
+            .spawn(ctx);
+        Subscribed::Ok
+    }
+}
+
 impl Handler<Unsubscribe> for Subscriber {
     type Result = ();
-    fn handle(&mut self, msg: Unsubscribe, _: &mut Self::Context) {
+    fn handle(&mut self, msg: Unsubscribe, ctx: &mut Self::Context) {
+        self.remove_polls(ctx, msg.id, msg.sub_id.as_ref());
         self.index.remove(msg.id, msg.sub_id.as_ref());
     }
 }
 
 impl Handler<Dispatch> for Subscriber {
     type Result = ();
-    fn handle(&mut self, msg: Dispatch, _: &mut Self::Context) {
+    fn handle(&mut self, msg: Dispatch, ctx: &mut Self::Context) {
         let event = &msg.event;
         let index = event.index();
         let event_str = event.to_string();
+        let mut voted = vec![];
         self.index.lookup(index, |session_id, sub_id| {
+            // votes for a poll update its results instead
+            if let Some(watch) = self.polls.get(&(*session_id, sub_id.clone())) {
+                if !voted.contains(&watch.poll_id) {
+                    voted.push(watch.poll_id.clone());
+                }
+                return;
+            }
             self.addr.do_send(SubscribeResult {
                 id: *session_id,
                 msg: OutgoingMessage::event(sub_id, &event_str),
                 sub_id: sub_id.clone(),
             });
         });
+        for poll_id in voted {
+            self.update_poll(ctx, poll_id, None);
+        }
     }
 }
 
@@ -356,7 +542,9 @@ mod tests {
         let receiver = receiver.start();
         let addr = receiver.recipient();
 
-        let subscriber = Subscriber::new(addr.clone(), Setting::default().into()).start();
+        let setting: SettingWrapper = Setting::default().into();
+        let chronod = Chronod::new(setting.clone()).start();
+        let subscriber = Subscriber::new(addr.clone(), chronod, setting).start();
 
         subscriber
             .send(Dispatch {
@@ -25,21 +25,81 @@ pub struct PollEventState {
     /// option - string - option - string
     #[prost(string, repeated, tag = "1")]
     pub state: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// unix seconds of the poll end
+    #[prost(int64, optional, tag = "2")]
+    pub end: ::core::option::Option<i64>,
+    /// ended or reached its clock limit
+    #[prost(bool, tag = "3")]
+    pub closed: bool,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Empty {}
+/// newest polls first, an empty request gets the first page of every poll
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PollListRequest {
+    /// cursor of the previous page
+    #[prost(string, tag = "1")]
+    pub cursor: ::prost::alloc::string::String,
+    /// 0 for the default page size
+    #[prost(uint32, tag = "2")]
+    pub limit: u32,
+    /// hex pubkey
+    #[prost(string, tag = "3")]
+    pub author: ::prost::alloc::string::String,
+    #[prost(enumeration = "PollStatus", tag = "4")]
+    pub status: i32,
+}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct PollListResponse {
     #[prost(message, repeated, tag = "1")]
     pub item: ::prost::alloc::vec::Vec<PollItem>,
+    /// empty on the last page
+    #[prost(string, tag = "2")]
+    pub cursor: ::prost::alloc::string::String,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct PollItem {
+    /// event id - title - info
     #[prost(string, repeated, tag = "1")]
     pub poll_item: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(string, tag = "2")]
+    pub author: ::prost::alloc::string::String,
+    #[prost(int64, tag = "3")]
+    pub created_at: i64,
+    #[prost(bool, tag = "4")]
+    pub closed: bool,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum PollStatus {
+    Any = 0,
+    /// still counts votes
+    Open = 1,
+    /// ended or reached its clock limit
+    Closed = 2,
+}
+impl PollStatus {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            PollStatus::Any => "ANY",
+            PollStatus::Open => "OPEN",
+            PollStatus::Closed => "CLOSED",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "ANY" => Some(Self::Any),
+            "OPEN" => Some(Self::Open),
+            "CLOSED" => Some(Self::Closed),
+            _ => None,
+        }
+    }
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -209,7 +269,7 @@ pub mod zchronod_client {
         }
         pub async fn query_poll_list(
             &mut self,
-            request: impl tonic::IntoRequest<super::Empty>,
+            request: impl tonic::IntoRequest<super::PollListRequest>,
         ) -> std::result::Result<
             tonic::Response<super::PollListResponse>,
             tonic::Status,
@@ -291,7 +351,7 @@ pub mod zchronod_server {
         ) -> std::result::Result<tonic::Response<super::ZchronodResp>, tonic::Status>;
         async fn query_poll_list(
             &self,
-            request: tonic::Request<super::Empty>,
+            request: tonic::Request<super::PollListRequest>,
         ) -> std::result::Result<
             tonic::Response<super::PollListResponse>,
             tonic::Status,
@@ -431,7 +491,7 @@ pub mod zchronod_server {
                 "/zchronod.zchronod/query_poll_list" => {
                     #[allow(non_camel_case_types)]
                     struct query_poll_listSvc<T: Zchronod>(pub Arc<T>);
-                    impl<T: Zchronod> tonic::server::UnaryService<super::Empty>
+                    impl<T: Zchronod> tonic::server::UnaryService<super::PollListRequest>
                     for query_poll_listSvc<T> {
                         type Response = super::PollListResponse;
                         type Future = BoxFuture<
@@ -440,7 +500,7 @@ pub mod zchronod_server {
                         >;
                         fn call(
                             &mut self,
-                            request: tonic::Request<super::Empty>,
+                            request: tonic::Request<super::PollListRequest>,
                         ) -> Self::Future {
                             let inner = Arc::clone(&self.0);
                             let fut = async move {
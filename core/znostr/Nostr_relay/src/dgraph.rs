@@ -0,0 +1,273 @@
+//! Export the event graph as Dgraph upserts
+//!
+//! Nodes and predicates follow the schema in `dgraph/sschema.py`: every event
+//! is a `Post` with an `author` `User`, `e` tags become `reply` / `root` edges
+//! and `p` and `t` tags `mention_p` and `tags` edges. Nodes are matched by
+//! `id`, `pubkey` and `tag_content` in an upsert block instead of blank nodes,
+//! so loading the same events again does not duplicate anything.
+//!
+//! Post every line to `/mutate?commitNow=true`, RDF with
+//! `Content-Type: application/rdf`, JSON with `Content-Type: application/json`.
+
+use clap::ValueEnum;
+use nostr_db::Event;
+use serde_json::{json, Map, Value};
+
+/// export format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ExportFormat {
+    /// nostr event json lines
+    #[default]
+    Jsonl,
+    /// dgraph RDF N-Quads upsert blocks
+    Rdf,
+    /// dgraph JSON upsert mutations, one per line
+    Json,
+}
+
+enum Object {
+    Node(String),
+    Str(String),
+}
+
+/// The upsert of one event
+struct Upsert {
+    // variable, predicate, value
+    vars: Vec<(String, &'static str, String)>,
+    // subject variable, predicate, object
+    set: Vec<(String, &'static str, Object)>,
+}
+
+impl Upsert {
+    // the node matching `predicate` = `value`, created when missing
+    fn node(&mut self, predicate: &'static str, value: &str, dtype: &str) -> String {
+        if let Some((var, _, _)) = self
+            .vars
+            .iter()
+            .find(|(_, p, v)| *p == predicate && v == value)
+        {
+            return var.clone();
+        }
+        let var = format!("v{}", self.vars.len());
+        self.vars.push((var.clone(), predicate, value.to_owned()));
+        self.str(&var, "dgraph.type", dtype);
+        self.str(&var, predicate, value);
+        var
+    }
+
+    fn str(&mut self, subject: &str, predicate: &'static str, value: &str) {
+        self.set
+            .push((subject.to_owned(), predicate, Object::Str(value.to_owned())));
+    }
+
+    fn edge(&mut self, subject: &str, predicate: &'static str, object: &str) {
+        self.set
+            .push((subject.to_owned(), predicate, Object::Node(object.to_owned())));
+    }
+
+    fn new(event: &Event) -> Self {
+        let mut upsert = Upsert {
+            vars: vec![],
+            set: vec![],
+        };
+        let author = upsert.node("pubkey", &event.pubkey_str(), "User");
+        // metadata only names the user
+        if event.kind() == 0 {
+            return upsert;
+        }
+
+        let post = upsert.node("id", &event.id_str(), "Post");
+        upsert.str(&post, "content", event.content());
+        upsert.str(&post, "created_at", &rfc3339(event.created_at()));
+        upsert.str(&post, "kind", &event.kind().to_string());
+        upsert.str(&post, "sig", &hex::encode(event.sig()));
+        upsert.edge(&post, "author", &author);
+        upsert.edge(&author, "posts", &post);
+
+        // nip-10, unmarked e tags are root first and reply last
+        let refs = event
+            .tags()
+            .iter()
+            .filter(|tag| tag.len() > 1 && tag[0] == "e")
+            .collect::<Vec<_>>();
+        let positional = refs.iter().all(|tag| tag.len() < 4);
+        for (i, tag) in refs.iter().enumerate() {
+            let marker = if positional {
+                if i + 1 == refs.len() {
+                    "reply"
+                } else if i == 0 {
+                    "root"
+                } else {
+                    "mention"
+                }
+            } else {
+                tag.get(3).map(|m| m.as_str()).unwrap_or_default()
+            };
+            let (forward, backward) = match marker {
+                "reply" => ("reply", "replyed_by"),
+                "root" => ("root", "child"),
+                _ => continue,
+            };
+            let parent = upsert.node("id", &tag[1], "Post");
+            upsert.edge(&post, forward, &parent);
+            upsert.edge(&parent, backward, &post);
+        }
+
+        for tag in event.tags().iter().filter(|tag| tag.len() > 1) {
+            match tag[0].as_str() {
+                "p" => {
+                    let user = upsert.node("pubkey", &tag[1], "User");
+                    upsert.edge(&post, "mention_p", &user);
+                    upsert.edge(&user, "mentioned_by", &post);
+                }
+                "t" => {
+                    let t = upsert.node("tag_content", &tag[1], "Tag");
+                    upsert.edge(&post, "tags", &t);
+                    upsert.edge(&t, "posts", &post);
+                }
+                _ => {}
+            }
+        }
+        upsert
+    }
+
+    fn query(&self) -> String {
+        let vars = self
+            .vars
+            .iter()
+            .map(|(var, predicate, value)| format!("{} as var(func: eq({}, {}))", var, predicate, quote(value)))
+            .collect::<Vec<_>>();
+        format!("{{ {} }}", vars.join(" "))
+    }
+
+    fn to_rdf(&self) -> String {
+        let set = self
+            .set
+            .iter()
+            .map(|(subject, predicate, object)| {
+                let object = match object {
+                    Object::Node(var) => format!("uid({})", var),
+                    Object::Str(s) => quote(s),
+                };
+                format!("uid({}) <{}> {} .", subject, predicate, object)
+            })
+            .collect::<Vec<_>>();
+        format!(
+            "upsert {{ query {} mutation {{ set {{ {} }} }} }}",
+            self.query(),
+            set.join(" ")
+        )
+    }
+
+    fn to_json(&self) -> Value {
+        let set = self
+            .set
+            .iter()
+            .map(|(subject, predicate, object)| {
+                let mut node = Map::new();
+                node.insert("uid".to_owned(), json!(format!("uid({})", subject)));
+                let object = match object {
+                    Object::Node(var) => json!({ "uid": format!("uid({})", var) }),
+                    Object::Str(s) => json!(s),
+                };
+                node.insert(predicate.to_string(), object);
+                Value::Object(node)
+            })
+            .collect::<Vec<_>>();
+        json!({ "query": self.query(), "set": set })
+    }
+}
+
+// dql and n-quads accept json string escapes
+fn quote(s: &str) -> String {
+    Value::String(s.to_owned()).to_string()
+}
+
+fn rfc3339(secs: u64) -> String {
+    // civil from days, http://howardhinnant.github.io/date_algorithms.html
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    let rem = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// One line loading the event into dgraph, None for the jsonl format.
+pub fn dgraph_upsert(event: &Event, format: ExportFormat) -> Option<String> {
+    match format {
+        ExportFormat::Jsonl => None,
+        ExportFormat::Rdf => Some(Upsert::new(event).to_rdf()),
+        ExportFormat::Json => Some(Upsert::new(event).to_json().to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn upsert() -> anyhow::Result<()> {
+        let event = Event::from_str(&format!(
+            r#"{{"id":"{}","pubkey":"{}","created_at":1680690006,"kind":1,"content":"gm \"all\"",
+            "sig":"{}","tags":[["e","{}","","root"],["e","{}","","reply"],["p","{}"],["t","nostr"],["t","nostr"]]}}"#,
+            "01".repeat(32),
+            "0a".repeat(32),
+            "00".repeat(64),
+            "02".repeat(32),
+            "03".repeat(32),
+            "0b".repeat(32),
+        ))?;
+        assert_eq!(rfc3339(1680690006), "2023-04-05T10:20:06Z");
+        assert_eq!(rfc3339(951782400), "2000-02-29T00:00:00Z");
+        assert_eq!(dgraph_upsert(&event, ExportFormat::Jsonl), None);
+
+        let rdf = dgraph_upsert(&event, ExportFormat::Rdf).unwrap();
+        assert!(rdf.starts_with(&format!(
+            r#"upsert {{ query {{ v0 as var(func: eq(pubkey, "{}")) v1 as var(func: eq(id, "{}"))"#,
+            "0a".repeat(32),
+            "01".repeat(32)
+        )));
+        assert!(rdf.contains(r#"uid(v1) <content> "gm \"all\"" ."#));
+        assert!(rdf.contains(r#"uid(v1) <created_at> "2023-04-05T10:20:06Z" ."#));
+        assert!(rdf.contains("uid(v1) <root> uid(v2) . uid(v2) <child> uid(v1) ."));
+        assert!(rdf.contains("uid(v1) <reply> uid(v3) . uid(v3) <replyed_by> uid(v1) ."));
+        assert!(rdf.contains("uid(v1) <mention_p> uid(v4) . uid(v4) <mentioned_by> uid(v1) ."));
+        // repeated tags share a node
+        assert_eq!(rdf.matches("eq(tag_content").count(), 1);
+
+        let json: Value = serde_json::from_str(&dgraph_upsert(&event, ExportFormat::Json).unwrap())?;
+        assert!(json["query"].as_str().unwrap().contains(r#"v5 as var(func: eq(tag_content, "nostr"))"#));
+        assert!(json["set"]
+            .as_array()
+            .unwrap()
+            .contains(&json!({ "uid": "uid(v0)", "posts": { "uid": "uid(v1)" } })));
+
+        // positional e tags
+        let event = Event::from_str(&format!(
+            r#"{{"id":"{}","pubkey":"{}","created_at":0,"kind":1,"content":"","sig":"{}","tags":[["e","{}"]]}}"#,
+            "01".repeat(32),
+            "0a".repeat(32),
+            "00".repeat(64),
+            "02".repeat(32),
+        ))?;
+        let rdf = dgraph_upsert(&event, ExportFormat::Rdf).unwrap();
+        assert!(rdf.contains("uid(v1) <reply> uid(v2) ."));
+        assert!(!rdf.contains("<root>"));
+        Ok(())
+    }
+}
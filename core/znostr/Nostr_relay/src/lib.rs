@@ -10,9 +10,11 @@ use std::{
 };
 
 mod bench;
+mod dgraph;
 mod relay;
 
 pub use bench::*;
+pub use dgraph::*;
 pub use relay::*;
 
 #[derive(thiserror::Error, Debug)]
@@ -64,7 +66,11 @@ pub struct ExportOpts {
     #[arg(long, value_name = "BOOL")]
     pub desc: Option<bool>,
 
-    /// output jsonl data file, use '-' for stdout
+    /// output format, rdf and json write idempotent dgraph upserts of the event graph
+    #[arg(long, value_enum, default_value_t)]
+    pub format: ExportFormat,
+
+    /// output data file, use '-' for stdout
     #[clap(value_parser, default_value = "-")]
     pub output: Output,
 }
@@ -189,7 +195,7 @@ pub fn export_opts(opts: ExportOpts) -> anyhow::Result<usize> {
         if let Some(desc) = opts.desc {
             opts.filter.desc = desc;
         }
-        let count = export(&opts.path, opts.output, &opts.filter, f)?;
+        let count = export(&opts.path, opts.output, &opts.filter, opts.format, f)?;
         Ok(count)
     }
 
@@ -219,18 +225,29 @@ pub fn export<F: Fn(usize)>(
     path: &PathBuf,
     mut output: Output,
     filter: &Filter,
+    format: ExportFormat,
     f: F,
 ) -> Result<usize> {
     let db = Db::open(path)?;
     let reader = db.reader()?;
-    let iter = db.iter::<String, _>(&reader, filter)?;
     let mut count = 0;
-    for event in iter {
-        count += 1;
-        let mut json: String = event?;
-        json.push('\n');
-        output.write_all(json.as_bytes())?;
-        f(count);
+    if format == ExportFormat::Jsonl {
+        for event in db.iter::<String, _>(&reader, filter)? {
+            count += 1;
+            let mut json: String = event?;
+            json.push('\n');
+            output.write_all(json.as_bytes())?;
+            f(count);
+        }
+    } else {
+        for event in db.iter::<Event, _>(&reader, filter)? {
+            count += 1;
+            if let Some(mut line) = dgraph_upsert(&event?, format) {
+                line.push('\n');
+                output.write_all(line.as_bytes())?;
+            }
+            f(count);
+        }
     }
     output.finish()?;
     Ok(count)
@@ -5,7 +5,9 @@
 //! network can verify the correctness of the clock.
 
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::cmp;
+use std::collections::BTreeMap;
 use prost::Message;
 
 
@@ -37,8 +39,15 @@ pub struct VlcMeta {
     pub clock_state: ::core::option::Option<Clock>,
     #[prost(bytes = "vec", tag = "2")]
     pub event_meta: ::prost::alloc::vec::Vec<u8>,
+    /// schnorr signature of the clock owner over `signing_digest`
+    #[prost(bytes = "vec", tag = "3")]
+    pub signature: ::prost::alloc::vec::Vec<u8>,
 }
 
+/// Vector clock: `values` maps every node id to the counter of that node.
+///
+/// `value` always mirrors `values[id]`. `ancestors` is the recursive format
+/// older nodes send; it is only read, see `Clock::migrate`.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Clock {
@@ -48,7 +57,10 @@ pub struct Clock {
     pub value: u64,
     #[prost(message, repeated, tag = "3")]
     pub ancestors: ::prost::alloc::vec::Vec<Clock>,
+    #[prost(btree_map = "string, uint64", tag = "4")]
+    pub values: ::prost::alloc::collections::BTreeMap<::prost::alloc::string::String, u64>,
 }
+
 impl Into<Vec<u8>> for ZMessage {
     fn into(self) -> Vec<u8> {
         let mut buf = Vec::new();
@@ -64,53 +76,87 @@ impl Into<Vec<u8>> for Clock {
     }
 }
 
+impl VlcMeta {
+    /// Digest the owner of the clock signs: sha256(encoded clock_state || event_meta).
+    /// Covering the event bytes stops a valid clock from being re-attached to another event.
+    pub fn signing_digest(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        if let Some(clock) = &self.clock_state {
+            hasher.update(clock.encode_to_vec());
+        }
+        hasher.update(&self.event_meta);
+        hasher.finalize().into()
+    }
+}
+
 impl PartialOrd for Clock {
+    /// Pointwise comparison of the two vectors, a missing entry counts as 0.
+    /// Both maps are sorted so this is a single merge walk.
     fn partial_cmp(&self, other: &Clock) -> Option<cmp::Ordering> {
-        println!("in compare");
-        if self.id == other.id {
-            return self.value.partial_cmp(&other.value);
-        } else {
-            // If current clock is <= to any of the other clock's ancestors,
-            // the clock is ordered before the other clock.
-            for anc in &other.ancestors {
-                match self.partial_cmp(anc) {
-                    Some(cmp::Ordering::Less) | Some(cmp::Ordering::Equal) => {
-                        return Some(cmp::Ordering::Less);
-                    }
-                    _ => (),
+        let mut less = false;
+        let mut greater = false;
+        let mut a = self.values.iter().peekable();
+        let mut b = other.values.iter().peekable();
+        loop {
+            let (x, y) = match (a.peek(), b.peek()) {
+                (None, None) => break,
+                (Some((_, &x)), None) => {
+                    a.next();
+                    (x, 0)
                 }
-            }
-            // Do the same check with the reverse direction
-            for anc in &self.ancestors {
-                match other.partial_cmp(anc) {
-                    Some(cmp::Ordering::Less) | Some(cmp::Ordering::Equal) => {
-                        return Some(cmp::Ordering::Greater);
-                    }
-                    _ => (),
+                (None, Some((_, &y))) => {
+                    b.next();
+                    (0, y)
                 }
+                (Some((ka, &x)), Some((kb, &y))) => match ka.cmp(kb) {
+                    cmp::Ordering::Less => {
+                        a.next();
+                        (x, 0)
+                    }
+                    cmp::Ordering::Greater => {
+                        b.next();
+                        (0, y)
+                    }
+                    cmp::Ordering::Equal => {
+                        a.next();
+                        b.next();
+                        (x, y)
+                    }
+                },
+            };
+            less |= x < y;
+            greater |= x > y;
+            if less && greater {
+                return None;
             }
         }
-        None
+        match (less, greater) {
+            (false, false) => Some(cmp::Ordering::Equal),
+            (true, false) => Some(cmp::Ordering::Less),
+            (false, true) => Some(cmp::Ordering::Greater),
+            (true, true) => None,
+        }
     }
 }
 
 impl Clock {
     /// Create a new clock.
     pub fn new(id: String) -> Self {
+        let mut values = BTreeMap::new();
+        values.insert(id.clone(), 0);
         Self {
             id,
             value: 0,
             ancestors: Vec::new(),
+            values,
         }
     }
 
     /// Create a new clock that extends other clocks.
     pub fn create(id: String, ancestors: &Vec<Clock>) -> Self {
-        Self {
-            id,
-            value: 0,
-            ancestors: ancestors.clone(),
-        }
+        let mut clock = Clock::new(id);
+        clock.merge(&ancestors.iter().collect());
+        clock
     }
 
     /// Increment the clock
@@ -118,29 +164,39 @@ impl Clock {
         // If clock value overflows, panic
         assert_ne!(self.value.checked_add(1), None);
         self.value += 1;
+        self.values.insert(self.id.clone(), self.value);
     }
 
     /// Reset the clock.
     pub fn clear(&mut self) {
         self.value = 0;
         self.ancestors.clear();
+        self.values.clear();
+        self.values.insert(self.id.clone(), 0);
     }
 
-    /// Merge the clock with other clocks.
+    /// Merge the clock with other clocks, taking the max of every entry.
     pub fn merge(&mut self, others: &Vec<&Clock>) {
         for &clock in others {
-            match self.clone().partial_cmp(clock) {
-                Some(cmp::Ordering::Less) => {
-                    self.value = clock.value;
-                    self.ancestors = clock.ancestors.clone();
-                }
-                Some(cmp::Ordering::Equal) => (),
-                Some(cmp::Ordering::Greater) => (),
-                None => {
-                    self.ancestors.push(clock.clone());
-                }
+            for (id, &value) in &clock.values {
+                let entry = self.values.entry(id.clone()).or_insert(0);
+                *entry = cmp::max(*entry, value);
             }
         }
+        self.value = self.values.get(&self.id).copied().unwrap_or(0);
+    }
+
+    /// Fold a clock in the legacy recursive format into `values`.
+    ///
+    /// Nodes before the vector clock only fill `id`, `value` and `ancestors`,
+    /// every ancestor stands for "happened after `ancestor.id` reached
+    /// `ancestor.value`". Clocks already in the new format are left unchanged.
+    pub fn migrate(&mut self) {
+        let mut values = std::mem::take(&mut self.values);
+        fold_legacy(self, &mut values);
+        self.values = values;
+        self.ancestors.clear();
+        self.value = self.values.get(&self.id).copied().unwrap_or(0);
     }
 
     pub fn get_value(&self) -> u64 {
@@ -148,13 +204,21 @@ impl Clock {
     }
 }
 
+fn fold_legacy(clock: &Clock, values: &mut BTreeMap<String, u64>) {
+    let entry = values.entry(clock.id.clone()).or_insert(0);
+    *entry = cmp::max(*entry, clock.value);
+    for anc in &clock.ancestors {
+        fold_legacy(anc, values);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn clock_inc() {
-        let mut c = Clock::new(1);
+        let mut c = Clock::new("1".to_string());
         c.inc();
         c.inc();
         assert_eq!(c.value, 2);
@@ -162,24 +226,25 @@ mod tests {
 
     #[test]
     fn clock_cmp() {
-        let mut c1 = Clock::new(1);
+        let mut c1 = Clock::new("1".to_string());
         let c2 = c1.clone();
-        let c3 = Clock::new(2);
+        let mut c3 = Clock::new("2".to_string());
 
         assert_eq!(c1, c2);
+        c1.inc();
+        c3.inc();
         assert_eq!(c1.partial_cmp(&c3), None);
-        assert_eq!(c2.partial_cmp(&c3), None);
+        assert_eq!(c2.partial_cmp(&c3), Some(cmp::Ordering::Less));
 
-        c1.inc();
         assert_eq!(c2.partial_cmp(&c1), Some(cmp::Ordering::Less));
         assert_eq!(c3.partial_cmp(&c1), None);
     }
 
     #[test]
     fn clock_merge() {
-        let mut c1 = Clock::new(1);
-        let mut c2 = Clock::new(2);
-        let mut c3 = Clock::new(3);
+        let mut c1 = Clock::new("1".to_string());
+        let mut c2 = Clock::new("2".to_string());
+        let mut c3 = Clock::new("3".to_string());
 
         c1.inc();
         c2.inc();
@@ -195,4 +260,58 @@ mod tests {
         assert_eq!(c3.partial_cmp(&c1), Some(cmp::Ordering::Less));
         assert_eq!(c1.partial_cmp(&c3), Some(cmp::Ordering::Greater));
     }
+
+    #[test]
+    fn clock_proto_round_trip() {
+        let mut c1 = Clock::new("1".to_string());
+        let mut c2 = Clock::new("2".to_string());
+        c1.inc();
+        c2.inc();
+        c2.inc();
+        c1.merge(&vec![&c2]);
+
+        let bytes: Vec<u8> = c1.clone().into();
+        let decoded = Clock::decode(bytes.as_slice()).unwrap();
+        assert_eq!(decoded, c1);
+        assert_eq!(decoded.values.get("2"), Some(&2));
+        assert_eq!(decoded.partial_cmp(&c1), Some(cmp::Ordering::Equal));
+    }
+
+    #[test]
+    fn clock_migrate_legacy() {
+        // the format handle_rpc_msg used to send: own clock wrapped as ancestor
+        let legacy = Clock {
+            id: "1".to_string(),
+            value: 3,
+            ancestors: vec![Clock {
+                id: "1".to_string(),
+                value: 3,
+                ancestors: vec![Clock {
+                    id: "2".to_string(),
+                    value: 5,
+                    ancestors: vec![],
+                    values: Default::default(),
+                }],
+                values: Default::default(),
+            }],
+            values: Default::default(),
+        };
+        let mut migrated = legacy.clone();
+        migrated.migrate();
+        assert!(migrated.ancestors.is_empty());
+        assert_eq!(migrated.value, 3);
+        assert_eq!(migrated.values.get("1"), Some(&3));
+        assert_eq!(migrated.values.get("2"), Some(&5));
+
+        let mut c2 = Clock::new("2".to_string());
+        for _ in 0..5 {
+            c2.inc();
+        }
+        assert_eq!(c2.partial_cmp(&migrated), Some(cmp::Ordering::Less));
+
+        // new format passes through unchanged
+        let mut again = migrated.clone();
+        again.migrate();
+        assert_eq!(again, migrated);
+    }
 }
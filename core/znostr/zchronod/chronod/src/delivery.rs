@@ -0,0 +1,268 @@
+//! Causal delivery of accepted events.
+//!
+//! Every event bumps the counter of its origin (`Clock.id`) by one, so the
+//! clock of an event names all of its causal predecessors: the events of
+//! node `j` with counter `1..=values[j]`. An event is delivered once all of
+//! them are, which turns the order events arrive in over gossip into a
+//! topological order. A rejected event still takes its counter, its place
+//! is filled by a placeholder that is never delivered.
+//!
+//! A waiting event is indexed by one place it waits for, a delivery checks
+//! only the events waiting for its place again.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use crate::clock::Clock;
+
+// a clock entry, origin id and counter
+type Place = (String, u64);
+
+#[derive(Default)]
+pub struct CausalLog {
+    // origin id -> highest contiguous counter delivered
+    delivered: BTreeMap<String, u64>,
+    // events waiting for a predecessor, by event id
+    pending: BTreeMap<String, Clock>,
+    // waiting event ids by the place whose delivery they wait for, an event
+    // is only checked again once that place is delivered
+    waiting: BTreeMap<Place, BTreeSet<String>>,
+    // waiting event ids by their own place
+    places: BTreeMap<Place, BTreeSet<String>>,
+    // placeholders of rejected events, by "origin:value"
+    skipped: BTreeSet<String>,
+}
+
+impl CausalLog {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Offer an accepted event, returns the event ids that became deliverable
+    /// in delivery order. Events offered twice are delivered once.
+    pub fn offer(&mut self, event_id: String, clock: Clock) -> Vec<String> {
+        if self.is_delivered(&clock) || self.pending.contains_key(&event_id) {
+            return vec![];
+        }
+        let mut ready = VecDeque::new();
+        self.enqueue(event_id, clock, &mut ready);
+
+        let mut result = vec![];
+        while let Some((id, clock)) = ready.pop_front() {
+            // a duplicate queued along with the event it repeats
+            if self.is_delivered(&clock) {
+                self.skipped.remove(&id);
+                continue;
+            }
+            self.mark_delivered(&clock);
+            let place = Self::place(&clock);
+            // a duplicate of a delivered event can still be waiting under another id
+            for duplicate in self.places.remove(&place).unwrap_or_default() {
+                self.pending.remove(&duplicate);
+                self.skipped.remove(&duplicate);
+            }
+            if !self.skipped.remove(&id) {
+                result.push(id);
+            }
+            for id in self.waiting.remove(&place).unwrap_or_default() {
+                if let Some(clock) = self.pending.remove(&id) {
+                    self.unplace(&id, &clock);
+                    self.enqueue(id, clock, &mut ready);
+                }
+            }
+        }
+        result
+    }
+
+    // queue an event that can be delivered, or index it by the first place it waits for
+    fn enqueue(&mut self, event_id: String, clock: Clock, ready: &mut VecDeque<(String, Clock)>) {
+        match self.waits_for(&clock) {
+            None => ready.push_back((event_id, clock)),
+            Some(place) => {
+                self.waiting.entry(place).or_default().insert(event_id.clone());
+                self.places.entry(Self::place(&clock)).or_default().insert(event_id.clone());
+                self.pending.insert(event_id, clock);
+            }
+        }
+    }
+
+    fn unplace(&mut self, event_id: &str, clock: &Clock) {
+        let place = Self::place(clock);
+        if let Some(ids) = self.places.get_mut(&place) {
+            ids.remove(event_id);
+            if ids.is_empty() {
+                self.places.remove(&place);
+            }
+        }
+    }
+
+    /// Fill the place of a rejected event so the events after it are delivered,
+    /// returns the event ids that became deliverable.
+    pub fn skip(&mut self, clock: Clock) -> Vec<String> {
+        if self.holds(&clock) {
+            return vec![];
+        }
+        let slot = Self::slot(&clock);
+        self.skipped.insert(slot.clone());
+        self.offer(slot, clock)
+    }
+
+    /// Whether the place of `clock` is delivered or taken by a waiting event.
+    pub fn holds(&self, clock: &Clock) -> bool {
+        self.is_delivered(clock) || self.places.contains_key(&Self::place(clock))
+    }
+
+    /// Key a rejected event is stored under, an event id is never a valid one.
+    pub fn slot(clock: &Clock) -> String {
+        format!("{}:{}", clock.id, Self::origin_value(clock))
+    }
+
+    /// Record an event delivered in an earlier run, before any event is offered.
+    pub fn restore(&mut self, clock: &Clock) {
+        self.mark_delivered(clock);
+    }
+
+    /// Clock of everything delivered so far, no gaps below any entry.
+    pub fn delivered(&self) -> &BTreeMap<String, u64> {
+        &self.delivered
+    }
+
+    /// Number of events waiting for a predecessor.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn origin_value(clock: &Clock) -> u64 {
+        clock.values.get(&clock.id).copied().unwrap_or(0)
+    }
+
+    fn is_delivered(&self, clock: &Clock) -> bool {
+        Self::origin_value(clock) <= self.delivered.get(&clock.id).copied().unwrap_or(0)
+    }
+
+    fn place(clock: &Clock) -> Place {
+        (clock.id.clone(), Self::origin_value(clock))
+    }
+
+    // a place not delivered yet that `clock` follows, none if it can be delivered:
+    // the previous event of its origin, or the last event it merged of another
+    // node. Places of a node are delivered one by one, so the event is checked
+    // again exactly when that place is.
+    fn waits_for(&self, clock: &Clock) -> Option<Place> {
+        clock.values.iter().find_map(|(id, &value)| {
+            let delivered = self.delivered.get(id).copied().unwrap_or(0);
+            if *id == clock.id {
+                (value > delivered + 1).then(|| (id.clone(), value - 1))
+            } else {
+                (value > delivered).then(|| (id.clone(), value))
+            }
+        })
+    }
+
+    fn mark_delivered(&mut self, clock: &Clock) {
+        let value = Self::origin_value(clock);
+        let entry = self.delivered.entry(clock.id.clone()).or_insert(0);
+        *entry = (*entry).max(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deliver_in_causal_order() {
+        let mut n1 = Clock::new("1".to_string());
+        let mut n2 = Clock::new("2".to_string());
+        n1.inc();
+        let a = n1.clone();
+        n2.merge(&vec![&a]);
+        n2.inc();
+        let b = n2.clone();
+        n1.inc();
+        let c = n1.clone();
+
+        let mut log = CausalLog::new();
+        // b depends on a, c on a
+        assert!(log.offer("b".to_string(), b.clone()).is_empty());
+        assert!(log.offer("c".to_string(), c.clone()).is_empty());
+        assert_eq!(log.pending(), 2);
+        let delivered = log.offer("a".to_string(), a.clone());
+        assert_eq!(delivered[0], "a");
+        assert_eq!(delivered.len(), 3);
+        assert_eq!(log.pending(), 0);
+
+        // duplicates are dropped
+        assert!(log.offer("a".to_string(), a).is_empty());
+        assert!(log.offer("b".to_string(), b).is_empty());
+        assert_eq!(log.delivered().get("1"), Some(&2));
+        assert_eq!(log.delivered().get("2"), Some(&1));
+    }
+
+    #[test]
+    fn skip_rejected() {
+        let mut n1 = Clock::new("1".to_string());
+        n1.inc();
+        let a = n1.clone();
+        n1.inc();
+        let b = n1.clone();
+        n1.inc();
+        let c = n1.clone();
+
+        let mut log = CausalLog::new();
+        assert!(log.offer("c".to_string(), c).is_empty());
+        assert!(log.offer("b".to_string(), b).is_empty());
+        // a is rejected, its placeholder is not delivered
+        assert_eq!(log.skip(a.clone()), vec!["b".to_string(), "c".to_string()]);
+        assert!(log.skip(a).is_empty());
+        assert_eq!(log.delivered().get("1"), Some(&3));
+
+        // a duplicate of a waiting event does not take its place
+        n1.inc();
+        let d = n1.clone();
+        n1.inc();
+        let e = n1.clone();
+        assert!(log.offer("e".to_string(), e.clone()).is_empty());
+        assert!(log.skip(e).is_empty());
+        assert_eq!(log.offer("d".to_string(), d), vec!["d".to_string(), "e".to_string()]);
+        assert_eq!(log.pending(), 0);
+    }
+
+    #[test]
+    fn restore_delivered() {
+        let mut n1 = Clock::new("1".to_string());
+        n1.inc();
+        let a = n1.clone();
+        n1.inc();
+        let b = n1.clone();
+
+        let mut log = CausalLog::new();
+        log.restore(&a);
+        assert!(log.offer("a".to_string(), a).is_empty());
+        assert_eq!(log.offer("b".to_string(), b), vec!["b".to_string()]);
+    }
+
+    #[test]
+    fn deliver_long_backlog() {
+        // two nodes that see each other every event, offered last first
+        let (mut n1, mut n2) = (Clock::new("1".to_string()), Clock::new("2".to_string()));
+        let mut events = vec![];
+        for i in 0..500 {
+            let node = if i % 2 == 0 { &mut n1 } else { &mut n2 };
+            if let Some((_, last)) = events.last() {
+                node.merge(&vec![last]);
+            }
+            node.inc();
+            events.push((i.to_string(), node.clone()));
+        }
+
+        let mut log = CausalLog::new();
+        for (id, clock) in events[1..].iter().rev() {
+            assert!(log.offer(id.clone(), clock.clone()).is_empty());
+        }
+        assert_eq!(log.pending(), 499);
+        let delivered = log.offer(events[0].0.clone(), events[0].1.clone());
+        assert_eq!(delivered, events.iter().map(|(id, _)| id.clone()).collect::<Vec<_>>());
+        assert_eq!(log.pending(), 0);
+        assert!(log.holds(&events[499].1));
+    }
+}
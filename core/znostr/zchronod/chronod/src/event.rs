@@ -0,0 +1,287 @@
+//! Nostr event verification.
+//!
+//! zchronod only orders events the author actually signed: the NIP-01 id is
+//! recomputed from the event fields and the signature is checked against the
+//! author key, both for events submitted over RPC and for events that arrive
+//! over gossip. Authors are nostr keys (Schnorr) or, for CausalityKey events,
+//! ETH addresses (ECDSA), the same rules the relay applies in
+//! `nostr_db::Event::validate`.
+
+use std::fmt;
+
+use proto::zchronod::Event;
+
+use secp256k1::{
+    ecdsa::{RecoverableSignature, RecoveryId},
+    schnorr::Signature,
+    KeyPair, Message, SecretKey, XOnlyPublicKey, SECP256K1,
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sha3::Keccak256;
+
+/// Kinds of the CausalityKey protocol, their authors are ETH identities:
+/// subspace creation, subspace join and the subspace ops. The relay keeps the
+/// same list in `nostr_db::event`, `eth_kinds_match_relay` checks they agree.
+pub const ETH_KINDS: [u32; 11] = [
+    30100, 30200, 30300, 30301, 30302, 30303, 30304, 30305, 30306, 30307, 30308,
+];
+
+/// Whether events of `kind` are always ETH signed.
+pub fn is_eth_kind(kind: u32) -> bool {
+    ETH_KINDS.contains(&kind)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventError {
+    BadId { expected: String, got: String },
+    InvalidPubkey(String),
+    BadSignature,
+}
+
+impl fmt::Display for EventError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventError::BadId { expected, got } => {
+                write!(f, "bad event id: expected [{}], got [{}]", expected, got)
+            }
+            EventError::InvalidPubkey(msg) => write!(f, "invalid pubkey: {}", msg),
+            EventError::BadSignature => write!(f, "signature is wrong"),
+        }
+    }
+}
+
+impl std::error::Error for EventError {}
+
+/// How the signature of an event is checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignScheme {
+    /// BIP-340 Schnorr over the x-only pubkey, NIP-01.
+    Schnorr,
+    /// secp256k1 ECDSA by an ETH address. The pubkey is the 20 byte address
+    /// left padded to 32 bytes, the sig is the EIP-2098 compact signature of
+    /// the EIP-191 personal message of the 32 id bytes.
+    Eth,
+}
+
+impl SignScheme {
+    /// CausalityKey kinds are always ETH signed, other events are ETH signed
+    /// when the pubkey is a padded address.
+    pub fn select(kind: u32, pubkey: &[u8]) -> Self {
+        if is_eth_kind(kind) || is_eth_pubkey(pubkey) {
+            SignScheme::Eth
+        } else {
+            SignScheme::Schnorr
+        }
+    }
+}
+
+fn is_eth_pubkey(pubkey: &[u8]) -> bool {
+    pubkey.len() == 32 && pubkey[..12] == [0u8; 12]
+}
+
+/// NIP-01 id: sha256 of `[0, pubkey, created_at, kind, tags, content]`.
+pub fn event_hash(e: &Event) -> [u8; 32] {
+    let tags: Vec<&Vec<String>> = e.tags.iter().map(|t| &t.values).collect();
+    let json = json!([0, hex::encode(&e.pubkey), e.created_at, e.kind, tags, e.content]);
+    let mut hasher = Sha256::new();
+    hasher.update(json.to_string());
+    hasher.finalize().into()
+}
+
+/// Check the id and the signature of `e`.
+pub fn verify_event(e: &Event) -> Result<(), EventError> {
+    let hash = event_hash(e);
+    if e.id != hash {
+        return Err(EventError::BadId {
+            expected: hex::encode(hash),
+            got: hex::encode(&e.id),
+        });
+    }
+    match SignScheme::select(e.kind, &e.pubkey) {
+        SignScheme::Schnorr => verify_schnorr(e, &hash),
+        SignScheme::Eth => verify_eth(e, &hash),
+    }
+}
+
+fn verify_schnorr(e: &Event, hash: &[u8; 32]) -> Result<(), EventError> {
+    let pubkey = XOnlyPublicKey::from_slice(&e.pubkey)
+        .map_err(|err| EventError::InvalidPubkey(err.to_string()))?;
+    let sig = Signature::from_slice(&e.sig).map_err(|_| EventError::BadSignature)?;
+    let msg = Message::from_slice(hash).expect("hash is 32 bytes");
+    SECP256K1
+        .verify_schnorr(&sig, &msg, &pubkey)
+        .map_err(|_| EventError::BadSignature)
+}
+
+fn verify_eth(e: &Event, hash: &[u8; 32]) -> Result<(), EventError> {
+    if !is_eth_pubkey(&e.pubkey) {
+        return Err(EventError::InvalidPubkey("not an eth address".to_string()));
+    }
+    if e.sig.len() != 64 {
+        return Err(EventError::BadSignature);
+    }
+    let mut compact = [0u8; 64];
+    compact.copy_from_slice(&e.sig);
+    let parity = (compact[32] >> 7) as i32;
+    compact[32] &= 0x7f;
+    let recovery_id = RecoveryId::from_i32(parity).expect("parity is 0 or 1");
+    let sig = RecoverableSignature::from_compact(&compact, recovery_id)
+        .map_err(|_| EventError::BadSignature)?;
+    let recovered = SECP256K1
+        .recover_ecdsa(&eth_message(hash), &sig)
+        .map_err(|_| EventError::BadSignature)?;
+    if eth_address(&recovered.serialize_uncompressed()) == e.pubkey[12..] {
+        Ok(())
+    } else {
+        Err(EventError::BadSignature)
+    }
+}
+
+/// EIP-191 hash of a personal message.
+fn eth_message(msg: &[u8]) -> Message {
+    let mut hasher = Keccak256::new();
+    hasher.update(format!("\x19Ethereum Signed Message:\n{}", msg.len()));
+    hasher.update(msg);
+    Message::from_slice(&hasher.finalize()).expect("keccak is 32 bytes")
+}
+
+// address of an uncompressed public key
+fn eth_address(pubkey: &[u8; 65]) -> [u8; 20] {
+    let hash = Keccak256::digest(&pubkey[1..]);
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&hash[12..]);
+    address
+}
+
+/// Fill pubkey, id and sig of `e` for `key_pair`.
+pub fn sign_event(e: &mut Event, key_pair: &KeyPair) {
+    e.pubkey = key_pair.x_only_public_key().0.serialize().to_vec();
+    let hash = event_hash(e);
+    e.id = hash.to_vec();
+    let msg = Message::from_slice(&hash).expect("hash is 32 bytes");
+    e.sig = SECP256K1.sign_schnorr(&msg, key_pair).as_ref().to_vec();
+}
+
+/// Fill pubkey, id and sig of `e` for an ETH key, see [`SignScheme::Eth`].
+pub fn sign_eth_event(e: &mut Event, secret_key: &SecretKey) {
+    let mut pubkey = vec![0u8; 12];
+    pubkey.extend(eth_address(&secret_key.public_key(SECP256K1).serialize_uncompressed()));
+    e.pubkey = pubkey;
+    let hash = event_hash(e);
+    e.id = hash.to_vec();
+    let (recovery_id, mut sig) = SECP256K1
+        .sign_ecdsa_recoverable(&eth_message(&hash), secret_key)
+        .serialize_compact();
+    // EIP-2098: the y parity is the top bit of s
+    if recovery_id.to_i32() == 1 {
+        sig[32] |= 0x80;
+    }
+    e.sig = sig.to_vec();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::zchronod::TagArray;
+
+    fn signed() -> Event {
+        let key_pair = KeyPair::new_global(&mut secp256k1::rand::thread_rng());
+        let mut e = Event {
+            created_at: 1700000000,
+            kind: 1,
+            tags: vec![TagArray { values: vec!["t".to_string(), "poll".to_string()] }],
+            content: "hello \"nostr\"\n".to_string(),
+            ..Default::default()
+        };
+        sign_event(&mut e, &key_pair);
+        e
+    }
+
+    // the relay picks the same signature scheme for every kind, skipped when the
+    // relay sources are not checked out next to zchronod
+    #[test]
+    fn eth_kinds_match_relay() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../Nostr_relay/db/src/event.rs");
+        let Ok(source) = std::fs::read_to_string(path) else {
+            return;
+        };
+        let list = source
+            .split("pub const ETH_KINDS")
+            .nth(1)
+            .and_then(|rest| rest.split('=').nth(1))
+            .and_then(|rest| rest.split(['[', ']']).nth(1))
+            .expect("ETH_KINDS in nostr_db::event");
+        let kinds: Vec<u32> = list
+            .split(',')
+            .map(str::trim)
+            .filter(|kind| !kind.is_empty())
+            .map(|kind| kind.parse().unwrap())
+            .collect();
+        assert_eq!(kinds, ETH_KINDS);
+    }
+
+    #[test]
+    fn verify_signed_event() {
+        let e = signed();
+        assert_eq!(verify_event(&e), Ok(()));
+
+        let mut tampered = e.clone();
+        tampered.content = "bye".to_string();
+        assert!(matches!(verify_event(&tampered), Err(EventError::BadId { .. })));
+
+        let mut tampered = e.clone();
+        tampered.id = vec![1];
+        assert!(matches!(verify_event(&tampered), Err(EventError::BadId { .. })));
+
+        let mut tampered = e.clone();
+        tampered.sig[0] ^= 1;
+        assert_eq!(verify_event(&tampered), Err(EventError::BadSignature));
+
+        // signed by another key
+        let mut tampered = e.clone();
+        tampered.sig = signed().sig;
+        assert_eq!(verify_event(&tampered), Err(EventError::BadSignature));
+
+        let mut tampered = e;
+        tampered.pubkey = vec![0; 32];
+        tampered.id = event_hash(&tampered).to_vec();
+        assert!(matches!(verify_event(&tampered), Err(EventError::InvalidPubkey(_)) | Err(EventError::BadSignature)));
+    }
+
+    #[test]
+    fn verify_eth_event() {
+        // web3.js accounts example key
+        let secret_key = SecretKey::from_slice(
+            &hex::decode("4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318").unwrap(),
+        )
+        .unwrap();
+        let mut e = Event { kind: 30100, content: "subspace".to_string(), ..Default::default() };
+        sign_eth_event(&mut e, &secret_key);
+        assert_eq!(hex::encode(&e.pubkey[12..]), "2c7536e3605d9c16a7a3d7b1898e529396a65c23");
+        assert_eq!(verify_event(&e), Ok(()));
+
+        // selected by the pubkey for other kinds
+        let mut note = Event { kind: 1, content: "hi".to_string(), ..Default::default() };
+        sign_eth_event(&mut note, &secret_key);
+        assert_eq!(SignScheme::select(note.kind, &note.pubkey), SignScheme::Eth);
+        assert_eq!(verify_event(&note), Ok(()));
+
+        let mut tampered = e.clone();
+        tampered.sig[32] ^= 0x80;
+        assert_eq!(verify_event(&tampered), Err(EventError::BadSignature));
+
+        // CausalityKey kinds do not take schnorr signatures
+        let key_pair = KeyPair::new_global(&mut secp256k1::rand::thread_rng());
+        let mut schnorr = Event { kind: 30301, ..Default::default() };
+        sign_event(&mut schnorr, &key_pair);
+        assert!(verify_event(&schnorr).is_err());
+        // other 303xx kinds, e.g. live events and the token kinds, stay schnorr signed
+        for kind in [30311, 30315, 30320] {
+            let mut e = Event { kind, ..Default::default() };
+            sign_event(&mut e, &key_pair);
+            assert_eq!(SignScheme::select(e.kind, &e.pubkey), SignScheme::Schnorr);
+            assert_eq!(verify_event(&e), Ok(()));
+        }
+    }
+}
@@ -1,6 +1,11 @@
 pub mod clock;
+pub mod delivery;
+pub mod event;
+pub mod sync;
+pub mod verify;
 
 pub use clock::Clock;
+pub use verify::{ClockSigner, ClockVerifier};
 
 use api::*;
 use std::sync::{Arc, mpsc};
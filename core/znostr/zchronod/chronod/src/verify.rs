@@ -0,0 +1,286 @@
+//! Signed clock updates.
+//!
+//! Every increment a node makes to its own clock is signed with the node key
+//! that owns `Clock.id`. Receivers check the signature against the key they
+//! have registered for that id before the clock is merged, and drop clock
+//! states they have already accepted.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use secp256k1::{schnorr::Signature, KeyPair, Message, SecretKey, XOnlyPublicKey, SECP256K1};
+
+use crate::clock::VlcMeta;
+
+/// How far below the highest accepted value of an id a clock value is still
+/// remembered. Values older than this window are treated as replays.
+const REPLAY_WINDOW: u64 = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    MissingClock,
+    UnknownId(String),
+    BadSignature(String),
+    Replayed { id: String, value: u64 },
+    InvalidKey(String),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::MissingClock => write!(f, "vlc meta has no clock state"),
+            VerifyError::UnknownId(id) => write!(f, "no key registered for clock id [{}]", id),
+            VerifyError::BadSignature(id) => write!(f, "bad clock signature for id [{}]", id),
+            VerifyError::Replayed { id, value } => {
+                write!(f, "clock [{}] value {} has been seen before", id, value)
+            }
+            VerifyError::InvalidKey(msg) => write!(f, "invalid key: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Signs the clock states this node sends out.
+pub struct ClockSigner {
+    key_pair: KeyPair,
+}
+
+impl ClockSigner {
+    pub fn new(key_pair: KeyPair) -> Self {
+        ClockSigner { key_pair }
+    }
+
+    /// Load the signer from a hex encoded secret key.
+    pub fn from_hex(secret: &str) -> Result<Self, VerifyError> {
+        let secret_key =
+            SecretKey::from_str(secret).map_err(|e| VerifyError::InvalidKey(e.to_string()))?;
+        Ok(ClockSigner::new(KeyPair::from_secret_key(SECP256K1, &secret_key)))
+    }
+
+    /// Create a signer with a fresh random key.
+    pub fn generate() -> Self {
+        ClockSigner::new(KeyPair::new_global(&mut secp256k1::rand::thread_rng()))
+    }
+
+    pub fn public_key(&self) -> XOnlyPublicKey {
+        self.key_pair.x_only_public_key().0
+    }
+
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.public_key().serialize())
+    }
+
+    /// Fill `meta.signature` for the current clock state and event bytes.
+    pub fn sign(&self, meta: &mut VlcMeta) {
+        let msg = Message::from_slice(&meta.signing_digest()).expect("digest is 32 bytes");
+        meta.signature = SECP256K1.sign_schnorr(&msg, &self.key_pair).as_ref().to_vec();
+    }
+}
+
+/// Checks clock states received from peers and keeps rejection counters.
+#[derive(Default)]
+pub struct ClockVerifier {
+    // clock id -> owner key
+    keys: HashMap<String, XOnlyPublicKey>,
+    // clock id -> accepted values within the replay window
+    seen: HashMap<String, BTreeSet<u64>>,
+    forged: u64,
+    replayed: u64,
+}
+
+impl ClockVerifier {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Register the public key (hex, x-only) that owns a clock id.
+    pub fn register(&mut self, id: String, pubkey: &str) -> Result<(), VerifyError> {
+        let key =
+            XOnlyPublicKey::from_str(pubkey).map_err(|e| VerifyError::InvalidKey(e.to_string()))?;
+        self.keys.insert(id, key);
+        Ok(())
+    }
+
+    /// Verify the signature of a clock state and that it was not accepted before.
+    /// Rejections are counted as forged or replayed.
+    pub fn verify(&mut self, meta: &VlcMeta) -> Result<(), VerifyError> {
+        let result = self.check(meta);
+        match &result {
+            Err(VerifyError::Replayed { .. }) => self.replayed += 1,
+            Err(_) => self.forged += 1,
+            Ok(_) => {}
+        }
+        result
+    }
+
+    /// Verify only the signature of a clock state.
+    ///
+    /// Used for clock states a peer sends again on sync, those are deduplicated
+    /// by event id and may be far behind the replay window.
+    pub fn verify_signature(&mut self, meta: &VlcMeta) -> Result<(), VerifyError> {
+        let result = self.check_signature(meta);
+        if result.is_err() {
+            self.forged += 1;
+        }
+        result
+    }
+
+    fn check_signature(&self, meta: &VlcMeta) -> Result<(), VerifyError> {
+        let clock = meta.clock_state.as_ref().ok_or(VerifyError::MissingClock)?;
+        let key = self
+            .keys
+            .get(&clock.id)
+            .ok_or_else(|| VerifyError::UnknownId(clock.id.clone()))?;
+        let bad_sig = || VerifyError::BadSignature(clock.id.clone());
+        let sig = Signature::from_slice(&meta.signature).map_err(|_| bad_sig())?;
+        let msg = Message::from_slice(&meta.signing_digest()).map_err(|_| bad_sig())?;
+        SECP256K1.verify_schnorr(&sig, &msg, key).map_err(|_| bad_sig())
+    }
+
+    fn check(&mut self, meta: &VlcMeta) -> Result<(), VerifyError> {
+        self.check_signature(meta)?;
+        let clock = meta.clock_state.as_ref().ok_or(VerifyError::MissingClock)?;
+
+        let seen = self.seen.entry(clock.id.clone()).or_default();
+        let highest = seen.last().copied().unwrap_or(0);
+        if seen.contains(&clock.value) || clock.value + REPLAY_WINDOW < highest {
+            return Err(VerifyError::Replayed {
+                id: clock.id.clone(),
+                value: clock.value,
+            });
+        }
+        seen.insert(clock.value);
+        let floor = seen.last().copied().unwrap_or(0).saturating_sub(REPLAY_WINDOW);
+        *seen = seen.split_off(&floor);
+        Ok(())
+    }
+
+    /// Number of clock states rejected for a missing or bad signature.
+    pub fn forged(&self) -> u64 {
+        self.forged
+    }
+
+    /// Number of clock states rejected because they were already accepted.
+    pub fn replayed(&self) -> u64 {
+        self.replayed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Clock;
+
+    fn signed_meta(signer: &ClockSigner, value: u64) -> VlcMeta {
+        let mut meta = VlcMeta {
+            clock_state: Some(Clock {
+                id: "node1".to_string(),
+                value,
+                ancestors: vec![],
+                values: [("node1".to_string(), value), ("node2".to_string(), 3)].into(),
+            }),
+            event_meta: b"event".to_vec(),
+            signature: vec![],
+        };
+        signer.sign(&mut meta);
+        meta
+    }
+
+    fn verifier_for(signer: &ClockSigner) -> ClockVerifier {
+        let mut verifier = ClockVerifier::new();
+        verifier
+            .register("node1".to_string(), &signer.public_key_hex())
+            .unwrap();
+        verifier
+    }
+
+    #[test]
+    fn verify_signed() {
+        let signer = ClockSigner::generate();
+        let mut verifier = verifier_for(&signer);
+        assert_eq!(verifier.verify(&signed_meta(&signer, 1)), Ok(()));
+        assert_eq!(verifier.verify(&signed_meta(&signer, 2)), Ok(()));
+        assert_eq!(verifier.forged(), 0);
+        assert_eq!(verifier.replayed(), 0);
+    }
+
+    #[test]
+    fn reject_tampered_value() {
+        let signer = ClockSigner::generate();
+        let mut verifier = verifier_for(&signer);
+        let mut meta = signed_meta(&signer, 1);
+        meta.clock_state.as_mut().unwrap().value = 100;
+        assert!(matches!(verifier.verify(&meta), Err(VerifyError::BadSignature(_))));
+        assert_eq!(verifier.forged(), 1);
+    }
+
+    #[test]
+    fn reject_tampered_vector() {
+        let signer = ClockSigner::generate();
+        let mut verifier = verifier_for(&signer);
+        let mut meta = signed_meta(&signer, 1);
+        meta.clock_state.as_mut().unwrap().values.insert("node2".to_string(), 100);
+        assert!(matches!(verifier.verify(&meta), Err(VerifyError::BadSignature(_))));
+
+        let mut meta = signed_meta(&signer, 1);
+        meta.clock_state.as_mut().unwrap().values.remove("node2");
+        assert!(matches!(verifier.verify(&meta), Err(VerifyError::BadSignature(_))));
+
+        let mut meta = signed_meta(&signer, 1);
+        meta.event_meta = b"other event".to_vec();
+        assert!(matches!(verifier.verify(&meta), Err(VerifyError::BadSignature(_))));
+        assert_eq!(verifier.forged(), 3);
+    }
+
+    #[test]
+    fn reject_wrong_key() {
+        let signer = ClockSigner::generate();
+        let mut verifier = verifier_for(&signer);
+        let other = ClockSigner::generate();
+        assert!(matches!(
+            verifier.verify(&signed_meta(&other, 1)),
+            Err(VerifyError::BadSignature(_))
+        ));
+
+        let mut meta = signed_meta(&signer, 1);
+        meta.clock_state.as_mut().unwrap().id = "node3".to_string();
+        assert!(matches!(verifier.verify(&meta), Err(VerifyError::UnknownId(_))));
+        assert_eq!(verifier.forged(), 2);
+    }
+
+    #[test]
+    fn reject_replayed() {
+        let signer = ClockSigner::generate();
+        let mut verifier = verifier_for(&signer);
+        let meta = signed_meta(&signer, 5);
+        assert_eq!(verifier.verify(&meta), Ok(()));
+        assert!(matches!(verifier.verify(&meta), Err(VerifyError::Replayed { .. })));
+        // out of order delivery inside the window is accepted
+        assert_eq!(verifier.verify(&signed_meta(&signer, 4)), Ok(()));
+        assert_eq!(verifier.verify(&signed_meta(&signer, 5 + REPLAY_WINDOW + 1)), Ok(()));
+        assert!(matches!(
+            verifier.verify(&signed_meta(&signer, 4)),
+            Err(VerifyError::Replayed { .. })
+        ));
+        assert_eq!(verifier.replayed(), 2);
+        assert_eq!(verifier.forged(), 0);
+    }
+
+    #[test]
+    fn verify_signature_allows_resend() {
+        let signer = ClockSigner::generate();
+        let mut verifier = verifier_for(&signer);
+        let meta = signed_meta(&signer, 5);
+        assert_eq!(verifier.verify(&meta), Ok(()));
+        assert_eq!(verifier.verify_signature(&meta), Ok(()));
+        assert_eq!(verifier.verify_signature(&signed_meta(&signer, 1)), Ok(()));
+
+        let mut tampered = signed_meta(&signer, 1);
+        tampered.clock_state.as_mut().unwrap().value = 2;
+        assert!(matches!(verifier.verify_signature(&tampered), Err(VerifyError::BadSignature(_))));
+        assert_eq!(verifier.replayed(), 0);
+        assert_eq!(verifier.forged(), 1);
+    }
+}
@@ -1,18 +1,21 @@
 use std::{
     collections::hash_map::DefaultHasher,
     hash::{Hash, Hasher},
+    iter,
     time::Duration,
 };
 use tokio::task::yield_now;
 
 use {
-    futures::stream::StreamExt,
+    async_trait::async_trait,
+    futures::{stream::StreamExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     libp2p::{
         gossipsub,
-        gossipsub::Message,
+        gossipsub::{Message, TopicHash},
         mdns, noise,
+        request_response::{self, ProtocolSupport},
         swarm::{NetworkBehaviour, SwarmEvent},
-        tcp, yamux, Multiaddr, PeerId, Swarm,
+        tcp, yamux, Multiaddr, PeerId, StreamProtocol, Swarm,
     },
     tokio::{
         io, select,
@@ -20,10 +23,60 @@ use {
     },
 };
 
+/// Topic of the messages a single peer sent to this node, they are handed to
+/// the distributor like gossip messages.
+pub const DIRECT_TOPIC: &str = "direct";
+const DIRECT_PROTOCOL: StreamProtocol = StreamProtocol::new("/gossipd/direct/1");
+/// Largest direct message read from a peer.
+const MAX_DIRECT_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Raw bytes to one peer, answered with an empty ack.
+#[derive(Clone, Default)]
+struct DirectCodec;
+
+#[async_trait]
+impl request_response::Codec for DirectCodec {
+    type Protocol = StreamProtocol;
+    type Request = Vec<u8>;
+    type Response = ();
+
+    async fn read_request<T>(&mut self, _: &StreamProtocol, io: &mut T) -> io::Result<Vec<u8>>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut data = vec![];
+        io.take(MAX_DIRECT_SIZE).read_to_end(&mut data).await?;
+        Ok(data)
+    }
+
+    async fn read_response<T>(&mut self, _: &StreamProtocol, _: &mut T) -> io::Result<()>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        Ok(())
+    }
+
+    async fn write_request<T>(&mut self, _: &StreamProtocol, io: &mut T, data: Vec<u8>) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        io.write_all(&data).await?;
+        io.close().await
+    }
+
+    async fn write_response<T>(&mut self, _: &StreamProtocol, io: &mut T, _: ()) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        io.close().await
+    }
+}
+
 #[derive(NetworkBehaviour)]
 struct GossipdBehaviour {
     gossipsub: gossipsub::Behaviour,
     mdns: mdns::tokio::Behaviour,
+    direct: request_response::Behaviour<DirectCodec>,
 }
 
 #[derive(Clone, Debug)]
@@ -47,11 +100,16 @@ impl GossipdOptions {
     }
 }
 
+/// A message and the peer it is sent to.
+pub type Direct<T> = (PeerId, T);
+
 pub struct Gossipd<T> {
     // todo: refactor transport.
     // Connection Manager with mdns and gossipsub.
     transport: Swarm<GossipdBehaviour>,
     channel: (Sender<T>, Receiver<T>),
+    // messages to a single peer
+    direct: (Sender<Direct<T>>, Receiver<Direct<T>>),
     handler: Option<fn(PeerId, Message)>,
     options: GossipdOptions,
     distributor: Option<std::sync::mpsc::Sender<(PeerId,Message)>>,
@@ -94,7 +152,11 @@ impl<T: Into<Vec<u8>>> Gossipd<T> {
                     mdns::Config::default(),
                     key.public().to_peer_id(),
                 )?;
-                Ok(GossipdBehaviour { gossipsub, mdns })
+                let direct = request_response::Behaviour::new(
+                    iter::once((DIRECT_PROTOCOL, ProtocolSupport::Full)),
+                    request_response::Config::default(),
+                );
+                Ok(GossipdBehaviour { gossipsub, mdns, direct })
             })
             .expect("failed to initialize behaviour")
             .build();
@@ -108,10 +170,12 @@ impl<T: Into<Vec<u8>>> Gossipd<T> {
             .expect("failed to subscript topic");
 
         let channel = tokio::sync::mpsc::channel(4096);
+        let direct = tokio::sync::mpsc::channel(4096);
 
         Gossipd {
             transport,
             channel,
+            direct,
             handler: None,
             options,
             distributor: None,
@@ -148,6 +212,9 @@ impl<T: Into<Vec<u8>>> Gossipd<T> {
                     },
                     None => println!("none"),
                 },
+                op = self.direct.1.recv() => if let Some((peer_id, message)) = op {
+                    self.transport.behaviour_mut().direct.send_request(&peer_id, message.into());
+                },
                 event = self.transport.select_next_some() => match event {
                     SwarmEvent::Behaviour(GossipdBehaviourEvent::Mdns(mdns::Event::Discovered(list))) => {
                         for (peer_id, _) in list {
@@ -173,6 +240,26 @@ impl<T: Into<Vec<u8>>> Gossipd<T> {
                         //     handler(peer_id, message);
                         // }
                     }
+                    SwarmEvent::Behaviour(GossipdBehaviourEvent::Direct(request_response::Event::Message {
+                        peer: peer_id,
+                        message: request_response::Message::Request { request, channel, .. },
+                    })) => {
+                        let _ = self.transport.behaviour_mut().direct.send_response(channel, ());
+                        if let Some(distribute) = self.distributor.clone() {
+                            let message = Message {
+                                source: Some(peer_id),
+                                data: request,
+                                sequence_number: None,
+                                topic: TopicHash::from_raw(DIRECT_TOPIC),
+                            };
+                            distribute.send((peer_id, message)).expect("failed to send")
+                        }
+                    }
+                    SwarmEvent::Behaviour(GossipdBehaviourEvent::Direct(request_response::Event::OutboundFailure {
+                        peer, error, ..
+                    })) => {
+                        println!("failed to send to {peer}: {error}");
+                    }
                     SwarmEvent::NewListenAddr { address, .. } => {
                         println!("Chronosd is listening on {address}");
                     }
@@ -186,6 +273,11 @@ impl<T: Into<Vec<u8>>> Gossipd<T> {
         self.channel.0.clone()
     }
 
+    /// Sender of messages to a single peer, they are not gossiped further.
+    pub fn create_direct_sender(&self) -> Sender<(PeerId, T)> {
+        self.direct.0.clone()
+    }
+
     pub fn with_handler(&mut self, h: fn(PeerId, Message)) -> &mut Gossipd<T> {
         self.handler = Some(h);
 
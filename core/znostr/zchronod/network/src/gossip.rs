@@ -29,6 +29,8 @@ use chronod::Clock;
 
 pub struct GossipServer<T> {
     pub send: Sender<T>,
+    // messages to a single peer
+    pub direct: Sender<(PeerId, T)>,
     pub gossip: Gossipd<T>,
     pub ip: String,
 }
@@ -45,7 +47,7 @@ impl <T: Into<Vec<u8>>> GossipServer<T> {
         // gossip.with_handler(|peer_id, message| {
         //     println!("{peer_id}: {}", String::from_utf8_lossy(&message.data))
         // });
-        GossipServer { send: gossip.create_sender(), gossip, ip: listen_address.to_string() }
+        GossipServer { send: gossip.create_sender(), direct: gossip.create_direct_sender(), gossip, ip: listen_address.to_string() }
     }
 
 
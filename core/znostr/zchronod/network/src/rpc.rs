@@ -1,3 +1,4 @@
+use std::cmp::Ordering;
 use std::sync::{Arc, RwLock};
 use std::thread;
 use tonic::{transport::Server, Request, Response, Status, IntoRequest};
@@ -7,10 +8,45 @@ use tokio::runtime::Runtime;
 use tokio::sync::mpsc::Sender;
 use api::{CONTEXT, RT};
 use proto::zchronod::zchronod_server::{Zchronod, ZchronodServer};
-use proto::zchronod::{Empty, Event, EventMeta, PollEventState, PollItem, PollListResponse, QueryEventRequest, QueryPollEventRequest, ZchronodRequest, ZchronodResp};
+use proto::zchronod::{AttributionRequest, AttributionResponse, CausalityKey, Contribution, CausalOrder, CompareEventRequest, CompareEventResponse, Empty, Event, EventMeta, KindQueryRequest, KindQueryResponse, PollEventState, PollItem, PollListRequest, PollListResponse, PollStatus, QueryEventRequest, QueryPollEventRequest, StreamedEvent, StreamEventsRequest, Subspace, SubspaceListResponse, SubspaceMember, SubspaceMembersResponse, SubspaceOpsResponse, SubspaceKeys, SubspaceRequest, TokenAllowanceRequest, TokenAmount, TokenBalanceRequest, TokenMetadata, TokenRequest, ZchronodRequest, ZchronodResp};
 use chronod::Clock;
+use chronod::event::{verify_event, EventError};
 use chronod::clock::ZMessage;
+use prost::Message;
 use storage::ZchronodDb;
+use storage::attribution::{self, Weights};
+use storage::handler::{op, poll, subspace, token, KindRegistry};
+use tokio_stream::wrappers::ReceiverStream;
+
+// events read from the causal order per db read while streaming
+const STREAM_PAGE: usize = 256;
+// how often a stream that caught up looks for new events
+const STREAM_POLL: std::time::Duration = std::time::Duration::from_millis(500);
+
+// error of the service helpers, a Status is too large to return unboxed,
+// `?` turns one into the other both ways
+#[derive(Debug)]
+struct RpcError(Box<Status>);
+
+impl From<Status> for RpcError {
+    fn from(status: Status) -> Self {
+        RpcError(Box::new(status))
+    }
+}
+
+impl From<RpcError> for Status {
+    fn from(err: RpcError) -> Self {
+        *err.0
+    }
+}
+
+impl std::ops::Deref for RpcError {
+    type Target = Status;
+
+    fn deref(&self) -> &Status {
+        &self.0
+    }
+}
 
 #[derive(Clone)]
 pub struct RpcServer {
@@ -24,14 +60,14 @@ impl RpcServer {
         }
     }
 
-    pub fn run(&self, zc: Sender<ZMessage>, event_handle: std::sync::mpsc::Sender<Event>, db: Arc<RwLock<ZchronodDb>>) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn run(&self, zc: Sender<ZMessage>, event_handle: std::sync::mpsc::Sender<Event>, db: Arc<RwLock<ZchronodDb>>, registry: Arc<KindRegistry>) -> Result<(), Box<dyn std::error::Error>> {
         println!("rpc run");
         info!("[{}] start rpc listen on {}",module_path!(),self.port);
         println!("[{}] start rpc listen on {}", module_path!(), self.port);
         let addr = self.port.parse()?;
         //  let addr = "127.0.0.1:10020";
         let server = Server::builder()
-            .add_service(ZchronodServer::new(init(zc, event_handle, db)))
+            .add_service(ZchronodServer::new(init(zc, event_handle, db, registry)))
             .serve(addr);
 
         tokio::spawn(server);
@@ -46,17 +82,115 @@ pub struct ZchronodService {
     send: Sender<ZMessage>,
     cons: std::sync::mpsc::Sender<Event>,
     db: Arc<RwLock<ZchronodDb>>,
+    registry: Arc<KindRegistry>,
 }
 
 
-pub fn init(zc: Sender<ZMessage>, consensus_clone: std::sync::mpsc::Sender<Event>, db: Arc<RwLock<ZchronodDb>>) -> ZchronodService {
+pub fn init(zc: Sender<ZMessage>, consensus_clone: std::sync::mpsc::Sender<Event>, db: Arc<RwLock<ZchronodDb>>, registry: Arc<KindRegistry>) -> ZchronodService {
     ZchronodService {
         send: zc,
         cons: consensus_clone,
         db: db,
+        registry,
     }
 }
 
+impl ZchronodService {
+    // clock an event was accepted at, none if the event is unknown
+    fn event_clock(&self, event_id: String) -> Result<Option<Clock>, RpcError> {
+        stored_clock(&self.db.read().unwrap(), event_id)
+    }
+
+    fn query_handler(&self, handler: &str, method: &str, params: &serde_json::Value) -> Result<serde_json::Value, RpcError> {
+        self.registry.query(&self.db.read().unwrap(), handler, method, params)
+            .map_err(|e| Status::invalid_argument(e.to_string()).into())
+    }
+}
+
+fn stored_clock(db: &ZchronodDb, event_id: String) -> Result<Option<Clock>, RpcError> {
+    let stored = db.query_clock_by_event_id(event_id)
+        .map_err(|e| Status::internal(e.to_string()))?;
+    match stored {
+        Some(bytes) => {
+            let mut clock = Clock::decode(bytes.as_slice())
+                .map_err(|e| Status::internal(e.to_string()))?;
+            clock.migrate();
+            Ok(Some(clock))
+        }
+        None => Ok(None),
+    }
+}
+
+// counter a subspace operation advanced, none for other events
+fn event_keys(registry: &KindRegistry, db: &ZchronodDb, event_id: &str) -> Result<Option<SubspaceKeys>, RpcError> {
+    let handler = match registry.by_name("op") {
+        Some(handler) => handler,
+        None => return Ok(None),
+    };
+    let counter = handler.query(db, "event", &serde_json::json!({ "event_id": event_id }))
+        .map_err(|e| Status::internal(e.to_string()))?;
+    let counter: Option<op::KeyCounter> = serde_json::from_value(counter)
+        .map_err(|e| Status::internal(e.to_string()))?;
+    Ok(counter.map(|c| SubspaceKeys {
+        sid: c.sid,
+        keys: vec![CausalityKey { key: c.key, counter: c.counter }],
+    }))
+}
+
+// next events of the causal order after `cursor`
+fn stream_page(registry: &KindRegistry, db: &ZchronodDb, cursor: u64) -> Result<Vec<StreamedEvent>, RpcError> {
+    let page = db.query_seq_after(cursor, STREAM_PAGE)
+        .map_err(|e| Status::internal(e.to_string()))?;
+    let mut result = vec![];
+    for (seq, event_id) in page {
+        let event = db.query_by_event_id(event_id.clone())
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let subspace_keys = event_keys(registry, db, &event_id)?;
+        let clock = stored_clock(db, event_id)?;
+        result.push(StreamedEvent {
+            cursor: seq,
+            // events the db refused, like invalid votes, keep their place in the order
+            event: if event.id.is_empty() { None } else { Some(event) },
+            clock: clock.map(|c| to_proto_clock(&c)),
+            subspace_keys,
+        });
+    }
+    Ok(result)
+}
+
+// chronod and proto clocks share field numbers
+fn to_proto_clock(clock: &Clock) -> proto::zchronod::Clock {
+    proto::zchronod::Clock::decode(clock.encode_to_vec().as_slice()).unwrap()
+}
+
+fn causal_order(first: &Clock, second: &Clock) -> CausalOrder {
+    match first.partial_cmp(second) {
+        Some(Ordering::Less) => CausalOrder::Before,
+        Some(Ordering::Greater) => CausalOrder::After,
+        Some(Ordering::Equal) => CausalOrder::Equal,
+        None => CausalOrder::Concurrent,
+    }
+}
+
+// only events with a valid NIP-01 id and signature are ordered
+fn verified_event(msg: Option<Event>) -> Result<Event, RpcError> {
+    let event = msg.ok_or_else(|| Status::invalid_argument("invalid: request has no event"))?;
+    match verify_event(&event) {
+        Ok(()) => Ok(event),
+        Err(err) => {
+            error!("reject event [{}]: {}", hex_id(&event.id), err);
+            Err(match err {
+                EventError::BadSignature => Status::unauthenticated(format!("invalid: {}", err)),
+                _ => Status::invalid_argument(format!("invalid: {}", err)),
+            }.into())
+        }
+    }
+}
+
+fn hex_id(id: &[u8]) -> String {
+    id.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
 #[tonic::async_trait]
 impl Zchronod for ZchronodService {
     async fn send(&self, request: Request<ZchronodRequest>) -> Result<Response<ZchronodResp>, Status> {
@@ -82,8 +216,13 @@ impl Zchronod for ZchronodService {
             //     sig: vec![],
             // });
         }
-        //todo verify
-        self.cons.send(request.into_inner().msg.unwrap()).expect("failed to send cons");
+        let event = verified_event(request.into_inner().msg)?;
+        // refused by its kind handler, e.g. a subspace op without permission
+        if let Err(err) = self.registry.validate(&self.db.read().unwrap(), &event) {
+            info!("reject kind {} event [{}]: {}", event.kind, hex_id(&event.id), err);
+            return Err(Status::permission_denied(format!("restricted: {}", err)));
+        }
+        self.cons.send(event).map_err(|e| Status::unavailable(e.to_string()))?;
         // if let Some(mut ctx) = unsafe { CONTEXT.as_ref() } {
         //     println!("send msg");
         //     ctx.get_network().send(Event{
@@ -107,24 +246,38 @@ impl Zchronod for ZchronodService {
         }))
     }
 
-    async fn query_poll_list(&self, request: Request<Empty>) -> Result<Response<PollListResponse>, Status> {
-        println!("query_poll_list here");
-        let poll_list = self.db.read().unwrap().query_all_event_id().unwrap();
-        let mut poll_items: Vec<PollItem> = Vec::new();
-        for inner_vec in poll_list {
-            let poll_item = PollItem {
-                poll_item: inner_vec,
-            };
-            poll_items.push(poll_item);
-        }
+    async fn query_poll_list(&self, request: Request<PollListRequest>) -> Result<Response<PollListResponse>, Status> {
+        let req = request.into_inner();
+        let query = poll::PollListQuery {
+            cursor: Some(req.cursor).filter(|cursor| !cursor.is_empty()),
+            limit: Some(req.limit as usize).filter(|limit| *limit > 0),
+            author: Some(req.author).filter(|author| !author.is_empty()),
+            status: match PollStatus::try_from(req.status).unwrap_or(PollStatus::Any) {
+                PollStatus::Any => None,
+                PollStatus::Open => Some(poll::PollStatus::Open),
+                PollStatus::Closed => Some(poll::PollStatus::Closed),
+            },
+        };
+        let page: poll::PollPage = serde_json::from_value(self.query_handler("poll", "list", &serde_json::json!(query))?)
+            .map_err(|e| Status::internal(e.to_string()))?;
         Ok(Response::new(PollListResponse {
-            item: poll_items,
+            item: page.polls.into_iter().map(|item| PollItem {
+                poll_item: vec![item.poll.id, item.poll.title, item.poll.info],
+                author: item.poll.pubkey,
+                created_at: item.poll.created_at,
+                closed: item.closed,
+            }).collect(),
+            cursor: page.cursor.unwrap_or_default(),
         }))
     }
 
     async fn query_poll_event_state(&self, request: Request<QueryPollEventRequest>) -> Result<Response<PollEventState>, Status> {
         println!("query_poll_event_stat here");
-        let state = self.db.read().unwrap().query_poll_event_state(request.into_inner().eventid).unwrap();
+        let params = serde_json::json!({ "event_id": request.into_inner().eventid });
+        let poll: Option<poll::PollListItem> = serde_json::from_value(self.query_handler("poll", "poll", &params)?)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let state: Vec<(String, i32)> = serde_json::from_value(self.query_handler("poll", "state", &params)?)
+            .map_err(|e| Status::internal(e.to_string()))?;
         let mut string_vec: Vec<String> = Vec::new();
         for (string_val, int_val) in state {
             let int_as_string = int_val.to_string();
@@ -133,17 +286,24 @@ impl Zchronod for ZchronodService {
         }
         Ok(Response::new(PollEventState {
             state: string_vec,
+            end: poll.as_ref().and_then(|item| item.poll.end),
+            closed: poll.is_some_and(|item| item.closed),
         }))
     }
 
     async fn query_by_event_id(&self, request: Request<QueryEventRequest>) -> Result<Response<EventMeta>, Status> {
         println!("query_by_event_id here");
         info!("query_by_event_id here");
-        return match self.db.read().unwrap().query_by_event_id(request.into_inner().eventid.clone()) {
+        let event_id = request.into_inner().eventid;
+        let clock = self.event_clock(event_id.clone())?.map(|c| to_proto_clock(&c));
+        let subspace_keys = event_keys(&self.registry, &self.db.read().unwrap(), &event_id)?;
+        return match self.db.read().unwrap().query_by_event_id(event_id) {
             Ok(e) => {
                 println!("event is [{:?}]", e.clone());
                 Ok(Response::new(EventMeta {
                     event: Some(e),
+                    clock,
+                    subspace_keys,
                 }))
             }
             Err(_) => {
@@ -153,4 +313,278 @@ impl Zchronod for ZchronodService {
             }
         }
     }
+
+    async fn compare_event(&self, request: Request<CompareEventRequest>) -> Result<Response<CompareEventResponse>, Status> {
+        let req = request.into_inner();
+        info!("compare_event [{}] [{}]", req.first, req.second);
+        let first = self.event_clock(req.first.clone())?
+            .ok_or_else(|| Status::not_found(format!("no clock for event {}", req.first)))?;
+        let second = self.event_clock(req.second.clone())?
+            .ok_or_else(|| Status::not_found(format!("no clock for event {}", req.second)))?;
+        Ok(Response::new(CompareEventResponse {
+            order: causal_order(&first, &second) as i32,
+            first: Some(to_proto_clock(&first)),
+            second: Some(to_proto_clock(&second)),
+        }))
+    }
+
+    type stream_eventsStream = ReceiverStream<Result<StreamedEvent, Status>>;
+
+    async fn stream_events(&self, request: Request<StreamEventsRequest>) -> Result<Response<Self::stream_eventsStream>, Status> {
+        let mut cursor = request.into_inner().cursor;
+        info!("stream_events from cursor {}", cursor);
+        let db = Arc::clone(&self.db);
+        let registry = Arc::clone(&self.registry);
+        let (tx, rx) = tokio::sync::mpsc::channel(STREAM_PAGE);
+        tokio::spawn(async move {
+            loop {
+                let page = stream_page(&registry, &db.read().unwrap(), cursor);
+                match page {
+                    Ok(page) if page.is_empty() => {
+                        if tx.is_closed() {
+                            return;
+                        }
+                        tokio::time::sleep(STREAM_POLL).await;
+                    }
+                    Ok(page) => {
+                        for item in page {
+                            let seq = item.cursor;
+                            if tx.send(Ok(item)).await.is_err() {
+                                return;
+                            }
+                            cursor = seq;
+                        }
+                    }
+                    Err(status) => {
+                        error!("stream_events stopped: {}", *status);
+                        let _ = tx.send(Err(status.into())).await;
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn query_kind(&self, request: Request<KindQueryRequest>) -> Result<Response<KindQueryResponse>, Status> {
+        let req = request.into_inner();
+        info!("query_kind {} {}", req.handler, req.method);
+        let params = if req.params.is_empty() {
+            serde_json::Value::Null
+        } else {
+            serde_json::from_str(&req.params).map_err(|e| Status::invalid_argument(e.to_string()))?
+        };
+        let result = self.query_handler(&req.handler, &req.method, &params)?;
+        Ok(Response::new(KindQueryResponse {
+            result: result.to_string(),
+        }))
+    }
+
+    async fn query_subspace_list(&self, _request: Request<Empty>) -> Result<Response<SubspaceListResponse>, Status> {
+        let subspaces: Vec<subspace::Subspace> = serde_json::from_value(self.query_handler("subspace", "list", &serde_json::Value::Null)?)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(SubspaceListResponse {
+            subspaces: subspaces.into_iter().map(|s| Subspace {
+                sid: s.sid,
+                name: s.name,
+                creator: s.creator,
+                ops: s.ops,
+                rules: s.rules,
+                content: s.content,
+                event_id: s.event_id,
+                created_at: s.created_at,
+            }).collect(),
+        }))
+    }
+
+    async fn query_subspace_members(&self, request: Request<SubspaceRequest>) -> Result<Response<SubspaceMembersResponse>, Status> {
+        let sid = request.into_inner().sid;
+        let members = self.query_handler("subspace", "members", &serde_json::json!({ "sid": sid }))?;
+        if members.is_null() {
+            return Err(Status::not_found(format!("subspace {} not found", sid)));
+        }
+        let members: Vec<subspace::Member> = serde_json::from_value(members)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(SubspaceMembersResponse {
+            members: members.into_iter().map(|m| SubspaceMember {
+                sid: m.sid,
+                pubkey: m.pubkey,
+                rules: m.rules,
+                event_id: m.event_id,
+                created_at: m.created_at,
+                actions: m.actions,
+            }).collect(),
+        }))
+    }
+
+    async fn query_subspace_ops(&self, request: Request<SubspaceRequest>) -> Result<Response<SubspaceOpsResponse>, Status> {
+        let sid = request.into_inner().sid;
+        let ops = self.query_handler("subspace", "ops", &serde_json::json!({ "sid": sid }))?;
+        if ops.is_null() {
+            return Err(Status::not_found(format!("subspace {} not found", sid)));
+        }
+        Ok(Response::new(SubspaceOpsResponse {
+            ops: serde_json::from_value(ops).map_err(|e| Status::internal(e.to_string()))?,
+        }))
+    }
+
+    async fn query_subspace_keys(&self, request: Request<SubspaceRequest>) -> Result<Response<SubspaceKeys>, Status> {
+        let sid = request.into_inner().sid;
+        let counters = self.query_handler("op", "counters", &serde_json::json!({ "sid": sid }))?;
+        if counters.is_null() {
+            return Err(Status::not_found(format!("subspace {} not found", sid)));
+        }
+        let counters: std::collections::BTreeMap<u32, u64> = serde_json::from_value(counters)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(SubspaceKeys {
+            sid,
+            keys: counters.into_iter().map(|(key, counter)| CausalityKey { key, counter }).collect(),
+        }))
+    }
+
+    async fn query_token_metadata(&self, request: Request<TokenRequest>) -> Result<Response<TokenMetadata>, Status> {
+        let symbol = request.into_inner().symbol;
+        let token = self.query_handler("token", "token", &serde_json::json!({ "symbol": symbol }))?;
+        if token.is_null() {
+            return Err(Status::not_found(format!("token {} not found", symbol)));
+        }
+        let token: token::Token = serde_json::from_value(token).map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(TokenMetadata {
+            symbol: token.symbol,
+            name: token.name,
+            decimals: token.decimals,
+            issuer: token.issuer,
+            supply: token.supply.to_string(),
+            description: token.description,
+            event_id: token.event_id,
+            created_at: token.created_at,
+        }))
+    }
+
+    async fn query_token_supply(&self, request: Request<TokenRequest>) -> Result<Response<TokenAmount>, Status> {
+        let symbol = request.into_inner().symbol;
+        let token = self.query_handler("token", "token", &serde_json::json!({ "symbol": symbol }))?;
+        match token["supply"].as_str() {
+            Some(supply) => Ok(Response::new(TokenAmount { amount: supply.to_string(), symbol })),
+            None => Err(Status::not_found(format!("token {} not found", symbol))),
+        }
+    }
+
+    async fn query_token_balance(&self, request: Request<TokenBalanceRequest>) -> Result<Response<TokenAmount>, Status> {
+        let req = request.into_inner();
+        let balance = self.query_handler("token", "balance", &serde_json::json!({ "symbol": req.symbol, "owner": req.owner }))?;
+        match balance.as_str() {
+            Some(amount) => Ok(Response::new(TokenAmount { amount: amount.to_string(), symbol: req.symbol })),
+            None => Err(Status::not_found(format!("token {} not found", req.symbol))),
+        }
+    }
+
+    async fn query_attribution(&self, request: Request<AttributionRequest>) -> Result<Response<AttributionResponse>, Status> {
+        let req = request.into_inner();
+        let mut weights = Weights::default();
+        weights.kinds.extend(req.weights);
+        if let Some(default) = req.default_weight {
+            weights.default = default;
+        }
+        if let Some(parent) = req.parent_weight {
+            weights.parent = parent;
+        }
+        let result = self.query_handler("op", "attribution", &serde_json::json!({ "sid": req.sid, "weights": weights }))?;
+        if result.is_null() {
+            return Err(Status::not_found(format!("subspace {} not found", req.sid)));
+        }
+        let contributions: Vec<attribution::Contribution> = serde_json::from_value(result)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(AttributionResponse {
+            sid: req.sid,
+            contributions: contributions.into_iter().map(|c| Contribution {
+                pubkey: c.pubkey,
+                score: c.score,
+                share: c.share,
+                ops: c.ops,
+                referenced: c.referenced,
+            }).collect(),
+        }))
+    }
+
+    async fn query_token_allowance(&self, request: Request<TokenAllowanceRequest>) -> Result<Response<TokenAmount>, Status> {
+        let req = request.into_inner();
+        let params = serde_json::json!({ "symbol": req.symbol, "owner": req.owner, "spender": req.spender });
+        match self.query_handler("token", "allowance", &params)?.as_str() {
+            Some(amount) => Ok(Response::new(TokenAmount { amount: amount.to_string(), symbol: req.symbol })),
+            None => Err(Status::not_found(format!("token {} not found", req.symbol))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_to_proto() {
+        let mut a = Clock::new("1".to_string());
+        let mut b = Clock::new("2".to_string());
+        a.inc();
+        b.merge(&vec![&a]);
+        b.inc();
+        let proto_b = to_proto_clock(&b);
+        assert_eq!(proto_b.id, "2");
+        assert_eq!(proto_b.value, 1);
+        assert_eq!(proto_b.values, b.values);
+
+        assert_eq!(causal_order(&a, &b), CausalOrder::Before);
+        assert_eq!(causal_order(&b, &a), CausalOrder::After);
+        assert_eq!(causal_order(&a, &a), CausalOrder::Equal);
+        a.inc();
+        assert_eq!(causal_order(&a, &b), CausalOrder::Concurrent);
+    }
+
+    #[test]
+    fn reject_unverified_event() {
+        let key_pair = secp256k1::KeyPair::new_global(&mut secp256k1::rand::thread_rng());
+        let mut event = Event { kind: 1, content: "hi".to_string(), ..Default::default() };
+        chronod::event::sign_event(&mut event, &key_pair);
+        assert!(verified_event(Some(event.clone())).is_ok());
+
+        assert_eq!(verified_event(None).unwrap_err().code(), tonic::Code::InvalidArgument);
+        let mut fake = event.clone();
+        fake.id = vec![1];
+        assert_eq!(verified_event(Some(fake)).unwrap_err().code(), tonic::Code::InvalidArgument);
+        let mut forged = event;
+        forged.sig[0] ^= 1;
+        let status = verified_event(Some(forged)).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        assert!(status.message().starts_with("invalid:"));
+    }
+
+    #[test]
+    fn stream_page_resumes() {
+        let dir = tempfile::Builder::new().prefix("zchronod-rpc").tempdir().unwrap();
+        let db = ZchronodDb::new(dir.path().to_str().unwrap().to_string()).unwrap();
+        let mut clock = Clock::new("1".to_string());
+        let mut ids = vec![];
+        for i in 0..3u8 {
+            clock.inc();
+            let event = Event { id: vec![i; 32], kind: 1, ..Default::default() };
+            let id = format!("{:02x}", i).repeat(32);
+            db.event_write(event).unwrap();
+            db.clock_write(clock.encode_to_vec(), Some((id.clone(), clock.encode_to_vec()))).unwrap();
+            ids.push(id);
+        }
+        // the last one was refused by the db but keeps its place
+        ids.push("ff".repeat(32));
+        db.seq_append(ids.clone()).unwrap();
+
+        let page = stream_page(&KindRegistry::new(), &db, 0).unwrap();
+        assert_eq!(page.iter().map(|e| e.cursor).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert_eq!(page[1].event.as_ref().unwrap().id, vec![1; 32]);
+        assert_eq!(page[1].clock.as_ref().unwrap().value, 2);
+        assert!(page[3].event.is_none());
+
+        let resumed = stream_page(&KindRegistry::new(), &db, 2).unwrap();
+        assert_eq!(resumed, page[2..].to_vec());
+        assert!(stream_page(&KindRegistry::new(), &db, 4).unwrap().is_empty());
+    }
 }
+
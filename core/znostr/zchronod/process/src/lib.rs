@@ -1,9 +1,11 @@
 use std::{fs, thread};
+use std::collections::HashMap;
 use std::cmp::Ordering;
 use std::fs::File;
 use std::io::Read;
 use std::sync::{Arc, mpsc, Mutex, RwLock};
 use std::sync::mpsc::{channel, Sender};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use async_std::io::Write;
 use async_std::task as task1;
@@ -22,18 +24,36 @@ use tokio::runtime::Runtime;
 use tokio::task;
 
 use api::{CONTEXT, NetworkInterface, Node};
+use chronod::{ClockSigner, ClockVerifier};
+use chronod::delivery::CausalLog;
+use chronod::event::verify_event;
+use chronod::sync::events_after;
 use chronod::clock::{Clock, VlcMeta, VlcMsg, ZMessage};
 use network::{GossipServer, RpcServer};
 use proto::zchronod::Event;
 use proto::zchronod::zchronod_server::Zchronod;
 use storage::ZchronodDb;
+use storage::attribution::{attribute, Weights};
+use storage::handler::{KindRegistry, OpHandler};
+
+// most events sent in answer to one sync, the rest follows on the next sync
+const SYNC_PAGE: usize = 256;
+// stored events read at once while filling a sync answer
+const SYNC_SCAN: usize = 1024;
+// seconds a signed sync request is answered after it was sent
+const SYNC_MAX_AGE: u64 = 60;
+// seconds between two answered sync requests of the same clock id
+const SYNC_MIN_GAP: u64 = 10;
 
 pub struct ZchronodServer {
     gossip_send: tokio::sync::mpsc::Sender<ZMessage>,
+    // messages to a single peer
+    direct_send: tokio::sync::mpsc::Sender<(PeerId, ZMessage)>,
     node_address: String,
     // todo add config as node_config
     inner: Arc<RwLock<CoreZchronod>>,
     z_db: Arc<RwLock<ZchronodDb>>,
+    registry: Arc<KindRegistry>,
 }
 
 impl ZchronodServer {
@@ -41,27 +61,36 @@ impl ZchronodServer {
     fn handle_rpc_msg(&self, x: Event) {
         println!("receive from rpc {:?}", x);
 
+        let event_id = hex::encode(&x.id);
+        // persisted before it is sent, a restarted node never reuses a value.
+        // A rejected event takes no clock value, is not gossiped and not ordered
+        let clock_msg = match self.tick_clock(event_id.clone(), |next| self.distribute_event_msg_to_db(x.clone(), next, true)) {
+            Some(clock) => clock,
+            None => return,
+        };
+
         // construct publish event to gossip
         self.inner.write().unwrap().count += 1;
         println!("current inner count is  {}", self.inner.read().unwrap().count);
-        println!("current clock value is  {}， should+1", self.inner.read().unwrap().clock.get_value());
-        self.inner.write().unwrap().clock.inc();
+        info!("current clock value is {}", self.inner.read().unwrap().clock.get_value());
 
         // construct z_message
         let mut event_bytes = Vec::new();
         x.encode(&mut event_bytes).unwrap();
 
-        let clock_msg = Clock {
-            id: self.inner.read().unwrap().clock.id.clone(),
-            value: self.inner.read().unwrap().clock.value,
-            ancestors: vec![self.inner.read().unwrap().clock.clone()],
-        };
-        let vlc_request_message = VlcMeta {
-            clock_state: Some(clock_msg),
+        // the whole vector is sent, ancestors are no longer filled
+        let mut vlc_request_message = VlcMeta {
+            clock_state: Some(clock_msg.clone()),
             event_meta: event_bytes,
+            signature: vec![],
         };
+        self.inner.read().unwrap().signer.sign(&mut vlc_request_message);
         let mut vlc_bytes = Vec::new();
         vlc_request_message.encode(&mut vlc_bytes).unwrap();
+        // keep the signed clock state to serve it to lagging nodes
+        if let Err(err) = self.z_db.read().unwrap().vlc_write(event_id.clone(), clock_time(&clock_msg), vlc_bytes.clone()) {
+            error!("failed to save vlc meta: {}", err);
+        }
 
         let vlc_msg = VlcMsg {
             r#type: "request".to_string(),
@@ -79,93 +108,343 @@ impl ZchronodServer {
         println!("z-message construct ok, save to db, send to gossip");
         //   if x.kind == 301 {}
 
-        self.distribute_event_msg_to_db(x);
+        self.deliver(event_id, clock_msg);
         let rt = Runtime::new();
         rt.unwrap().block_on(self.gossip_send.send(
             z_message)).expect("failed to send to gossip");
     }
 
-    fn distribute_event_msg_to_db(&self, e: Event) {
-        println!("distribute rpc msg here");
-        if e.kind == 301 {
-            println!("receive kind 301 poll");
-            info!("receive kind 301 poll");
-            match self.z_db.write().unwrap().poll_write(self.construct_poll_event_key(e.clone()), e.clone()) {
-                Ok(_) => {}
-                Err(_) => { return; }
+    // check, apply at `clock` and store an event, returns whether it was accepted.
+    // A `local` event is validated for its submitter first
+    fn distribute_event_msg_to_db(&self, e: Event, clock: &Clock, local: bool) -> bool {
+        // gossiped events are checked here as well, a peer signing the clock
+        // does not vouch for the event
+        if let Err(err) = verify_event(&e) {
+            error!("reject event [{}]: {}", hex::encode(&e.id), err);
+            return false;
+        }
+        let db = self.z_db.write().unwrap();
+        match self.registry.accept(&db, &e, &handler_clock(clock), local) {
+            Ok(()) => true,
+            Err(err) => {
+                error!("kind {} event [{}] rejected: {}", e.kind, hex::encode(&e.id), err);
+                false
             }
         }
+    }
 
-        if e.kind == 309 {
-            println!("receive kind 309 vote");
-            info!("receive kind 309 vote");
-            match self.z_db.write().unwrap().vote_write(e.clone()) {
-                Ok(_) => {}
-                Err(_) => { return; }
+
+    pub fn handle_vlc_request(&self, vlc_meta: Vec<u8>) {
+        let vlc_meta_instance = match VlcMeta::decode(Bytes::from(vlc_meta.clone())) {
+            Ok(meta) => meta,
+            Err(err) => {
+                error!("invalid vlc meta: {}", err);
+                return;
             }
+        };
+        let e = match Event::decode(Bytes::from(vlc_meta_instance.event_meta.clone())) {
+            Ok(e) => e,
+            Err(err) => {
+                error!("invalid gossiped event: {}", err);
+                return;
+            }
+        };
+        let event_id = hex::encode(&e.id);
+        // only merge clock states signed by the node owning the clock id,
+        // the write guard is dropped before the read below
+        let verified = self.inner.write().unwrap().verifier.verify(&vlc_meta_instance);
+        if let Err(err) = verified {
+            let inner = self.inner.read().unwrap();
+            error!("reject clock state: {}, forged {}, replayed {}", err, inner.verifier.forged(), inner.verifier.replayed());
+            return;
         }
+        self.save_vlc_meta(&vlc_meta_instance, vlc_meta);
+        // the signature covers the clock as sent, migrate legacy ancestors only after verifying
+        let Some(mut clock_state) = vlc_meta_instance.clock_state.clone() else {
+            return;
+        };
+        clock_state.migrate();
+        info!("receive from gossip_clock_state_id [{}]", clock_state.id);
+        // a rejected event is neither merged nor ordered, only its place is kept
+        if !self.distribute_event_msg_to_db(e, &clock_state, false) {
+            self.skip(clock_state);
+            return;
+        }
+        // an older clock still belongs to a new event, it is merged and stored as well
+        // or the event would be missing from the causal order
+        self.merge_clock(event_id.clone(), &clock_state);
+        info!("current clock value is {}", self.inner.read().unwrap().clock.get_value());
 
-        match self.z_db.write().unwrap().event_write(e.clone()) {
-            Ok(_) => {}
-            Err(_) => {
-                error!("event id duplicated");
-                println!("event id duplicated");
+        self.deliver(event_id, clock_state);
+        // self.inner.write().unwrap().clock.inc();
+    }
+
+    // a lagging node advertised its clock, answer it alone with a page of the events
+    // it has not seen. Only signed and recent requests of known nodes are answered,
+    // at most one every SYNC_MIN_GAP seconds per node
+    pub fn handle_vlc_sync(&self, source: Option<PeerId>, vlc_meta: Vec<u8>) {
+        let Some(peer) = source else {
+            return;
+        };
+        let vlc_meta_instance = match VlcMeta::decode(Bytes::from(vlc_meta)) {
+            Ok(meta) => meta,
+            Err(err) => {
+                error!("invalid sync vlc meta: {}", err);
+                return;
+            }
+        };
+        let verified = self.inner.write().unwrap().verifier.verify_signature(&vlc_meta_instance);
+        if let Err(err) = verified {
+            error!("reject sync: {}", err);
+            return;
+        }
+        let Some(sent_at) = sync_sent_at(&vlc_meta_instance.event_meta) else {
+            error!("reject sync without send time");
+            return;
+        };
+        // a verified signature implies a clock state
+        let Some(mut known) = vlc_meta_instance.clock_state else {
+            return;
+        };
+        known.migrate();
+        if unix_now().abs_diff(sent_at) > SYNC_MAX_AGE {
+            info!("ignore stale sync from clock_id [{}]", known.id);
+            return;
+        }
+        {
+            let mut inner = self.inner.write().unwrap();
+            if inner.synced.get(&known.id).is_some_and(|last| sent_at < last + SYNC_MIN_GAP) {
+                info!("ignore repeated sync from clock_id [{}]", known.id);
+                return;
+            }
+            inner.synced.insert(known.id.clone(), sent_at);
+            match inner.clock.partial_cmp(&known) {
+                Some(Ordering::Less) | Some(Ordering::Equal) => return,
+                _ => {}
+            }
+        }
+        info!("receive sync from clock_id [{}]", known.id);
+
+        let missing = match self.sync_page(&known) {
+            Ok(missing) => missing,
+            Err(err) => {
+                error!("failed to read vlc meta: {}", err);
+                return;
+            }
+        };
+        info!("answer sync of [{}] with {} events", known.id, missing.len());
+
+        for meta in missing {
+            let vlc_msg = VlcMsg {
+                r#type: "sync_reply".to_string(),
+                vlc_meta: meta.encode_to_vec(),
+            };
+            let z_message = ZMessage {
+                r#type: "vlc".to_string(),
+                msg_meta: vlc_msg.encode_to_vec(),
+            };
+            if let Err(err) = self.direct_send.blocking_send((peer, z_message)) {
+                error!("failed to answer sync: {}", err);
                 return;
             }
         }
     }
 
-    fn construct_poll_event_key(&self, e: Event) -> String {
-        // key is 3041_event-id_state
-        let event_id: Vec<u8> = e.id.clone();
-        println!("{:?}", event_id);
-        let hex_string_back: String = hex::encode(e.id.clone());
-        println!("{:?}", &hex_string_back);
-        let constructed_string = format!("3041_{}_state", hex_string_back);
-        // let result: String = event_id.iter()
-        //     .map(|&num| num.to_string())
-        //     .collect();
-        constructed_string
+    // the first SYNC_PAGE stored events after `known` by logical time, an event
+    // never comes before one of its causal predecessors
+    fn sync_page(&self, known: &Clock) -> storage::Result<Vec<VlcMeta>> {
+        let db = self.z_db.read().unwrap();
+        let mut missing = vec![];
+        let mut cursor = None;
+        while missing.len() < SYNC_PAGE {
+            let page = db.query_vlc_after(cursor.as_deref(), SYNC_SCAN)?;
+            let Some((last, _)) = page.last() else {
+                break;
+            };
+            cursor = Some(last.clone());
+            let metas = page.into_iter()
+                .filter_map(|(_, bytes)| VlcMeta::decode(Bytes::from(bytes)).ok())
+                .collect();
+            missing.extend(events_after(known, metas));
+        }
+        missing.truncate(SYNC_PAGE);
+        Ok(missing)
     }
 
-    pub fn handle_vlc_request(&self, vlc_meta: Vec<u8>) {
-        let vlc_meta_instance = VlcMeta::decode(Bytes::from(vlc_meta)).unwrap();
-        let clock_state = &vlc_meta_instance.clock_state.unwrap();
-        println!("receive from gossip_clock_state_id [{}]", clock_state.id);
-        match self.inner.write().unwrap().clock.partial_cmp(clock_state) {
-            Some(Ordering::Greater) =>
-                {
-                    println!("dont need merge");
-                    return;
-                }
-            _ => {
-                println!("need merge");
+    // an event resent on sync, other nodes may already have it
+    pub fn handle_vlc_sync_reply(&self, vlc_meta: Vec<u8>) {
+        let vlc_meta_instance = match VlcMeta::decode(Bytes::from(vlc_meta.clone())) {
+            Ok(meta) => meta,
+            Err(err) => {
+                error!("invalid synced vlc meta: {}", err);
+                return;
             }
+        };
+        let e = match Event::decode(Bytes::from(vlc_meta_instance.event_meta.clone())) {
+            Ok(e) => e,
+            Err(err) => {
+                error!("invalid synced event: {}", err);
+                return;
+            }
+        };
+        let event_id = hex::encode(&e.id);
+        if self.z_db.read().unwrap().has_vlc(event_id.clone()).unwrap_or(false) {
+            return;
         }
-        self.inner.write().unwrap().clock.merge(&vec![&clock_state]);
-        //self.inner.write().unwrap().clock.inc();
-        println!("current inner count is  {}", self.inner.read().unwrap().count);
-        println!("current clock value is  {}， should+1", self.inner.read().unwrap().clock.get_value());
+        let verified = self.inner.write().unwrap().verifier.verify_signature(&vlc_meta_instance);
+        if let Err(err) = verified {
+            error!("reject synced clock state: {}", err);
+            return;
+        }
+        // a verified signature implies a clock state
+        let Some(mut clock_state) = vlc_meta_instance.clock_state.clone() else {
+            return;
+        };
+        clock_state.migrate();
+        self.save_vlc_meta(&vlc_meta_instance, vlc_meta);
+        if !self.distribute_event_msg_to_db(e, &clock_state, false) {
+            self.skip(clock_state);
+            return;
+        }
+        self.merge_clock(event_id.clone(), &clock_state);
+        info!("synced event [{}], current clock value is {}", event_id, self.inner.read().unwrap().clock.get_value());
+        self.deliver(event_id, clock_state);
+    }
 
-        let e = Event::decode(Bytes::from(vlc_meta_instance.event_meta)).unwrap();
-        self.distribute_event_msg_to_db(e);
-        // self.inner.write().unwrap().clock.inc();
+    // advertise what we have delivered so peers send the events we missed,
+    // the node clock can be ahead of a missing event
+    pub fn send_vlc_sync(&self) {
+        let known = {
+            let inner = self.inner.read().unwrap();
+            let values = inner.log.delivered().clone();
+            Clock {
+                id: inner.clock.id.clone(),
+                value: values.get(&inner.clock.id).copied().unwrap_or(0),
+                ancestors: vec![],
+                values,
+            }
+        };
+        // signed with the send time, so peers only answer recent requests of known nodes
+        let mut vlc_meta = VlcMeta {
+            clock_state: Some(known),
+            event_meta: unix_now().to_be_bytes().to_vec(),
+            signature: vec![],
+        };
+        self.inner.read().unwrap().signer.sign(&mut vlc_meta);
+        let vlc_msg = VlcMsg {
+            r#type: "sync".to_string(),
+            vlc_meta: vlc_meta.encode_to_vec(),
+        };
+        let z_message = ZMessage {
+            r#type: "vlc".to_string(),
+            msg_meta: vlc_msg.encode_to_vec(),
+        };
+        if let Err(err) = self.gossip_send.blocking_send(z_message) {
+            error!("failed to send sync: {}", err);
+        }
+    }
+
+    // increment the node clock for a new local event that `accept` takes at the
+    // new clock and persist it, returns the new clock
+    fn tick_clock(&self, event_id: String, accept: impl FnOnce(&Clock) -> bool) -> Option<Clock> {
+        // the inner lock is held while writing so a stale clock never overwrites a newer one,
+        // and no other event is accepted at the same value
+        let mut inner = self.inner.write().unwrap();
+        let mut clock = inner.clock.clone();
+        clock.inc();
+        if !accept(&clock) {
+            return None;
+        }
+        inner.clock = clock.clone();
+        self.persist_clock(&clock, event_id, &clock);
+        Some(clock)
+    }
+
+    // merge the clock of an accepted event into the node clock and persist both
+    fn merge_clock(&self, event_id: String, event_clock: &Clock) {
+        let mut inner = self.inner.write().unwrap();
+        inner.clock.merge(&vec![event_clock]);
+        self.persist_clock(&inner.clock, event_id, event_clock);
+    }
+
+    // append the events that became deliverable to the causal order
+    fn deliver(&self, event_id: String, clock: Clock) {
+        // the inner lock keeps the order of the log and the seq tree the same
+        let mut inner = self.inner.write().unwrap();
+        let ready = inner.log.offer(event_id, clock);
+        if ready.is_empty() {
+            return;
+        }
+        if let Err(err) = self.z_db.read().unwrap().seq_append(ready) {
+            error!("failed to append causal order: {}", err);
+        }
+    }
+
+    // keep the place of a rejected event, the events after it are still delivered
+    fn skip(&self, clock: Clock) {
+        let mut inner = self.inner.write().unwrap();
+        // a duplicate of an event that was accepted or skipped before
+        if inner.log.holds(&clock) {
+            return;
+        }
+        let db = self.z_db.read().unwrap();
+        if let Err(err) = db.skip_write(CausalLog::slot(&clock), clock.encode_to_vec()) {
+            error!("failed to save skipped clock: {}", err);
+        }
+        let ready = inner.log.skip(clock);
+        if ready.is_empty() {
+            return;
+        }
+        if let Err(err) = db.seq_append(ready) {
+            error!("failed to append causal order: {}", err);
+        }
+    }
+
+    fn persist_clock(&self, node_clock: &Clock, event_id: String, event_clock: &Clock) {
+        let event = Some((event_id, event_clock.encode_to_vec()));
+        if let Err(err) = self.z_db.read().unwrap().clock_write(node_clock.encode_to_vec(), event) {
+            error!("failed to persist clock: {}", err);
+        }
+    }
+
+    fn save_vlc_meta(&self, vlc_meta: &VlcMeta, bytes: Vec<u8>) {
+        let e = match Event::decode(Bytes::from(vlc_meta.event_meta.clone())) {
+            Ok(e) => e,
+            Err(_) => return,
+        };
+        let time = vlc_meta_time(vlc_meta).unwrap_or(0);
+        if let Err(err) = self.z_db.read().unwrap().vlc_write(hex::encode(&e.id), time, bytes) {
+            error!("failed to save vlc meta: {}", err);
+        }
     }
-    pub fn handle_gossip_msg(&self, z_msg_bytes: Vec<u8>) {
+
+    // `source` is the node that sent the message, sync is answered to it
+    pub fn handle_gossip_msg(&self, source: Option<PeerId>, z_msg_bytes: Vec<u8>) {
         println!("handle gossip msg");
-        let z_message = ZMessage::decode(Bytes::from(z_msg_bytes)).unwrap();
+        let z_message = match ZMessage::decode(Bytes::from(z_msg_bytes)) {
+            Ok(z_message) => z_message,
+            Err(err) => {
+                error!("invalid gossip message: {}", err);
+                return;
+            }
+        };
         match z_message.r#type.as_str() {
             "vlc" => {
                 println!("handle vlc msg");
-                let vlc_msg = VlcMsg::decode(Bytes::from(z_message.msg_meta)).unwrap();
+                let vlc_msg = match VlcMsg::decode(Bytes::from(z_message.msg_meta)) {
+                    Ok(vlc_msg) => vlc_msg,
+                    Err(err) => {
+                        error!("invalid vlc message: {}", err);
+                        return;
+                    }
+                };
                 match vlc_msg.r#type.as_str() {
                     "request" => {
                         println!("handle vlc_request msg");
                         self.handle_vlc_request(vlc_msg.vlc_meta);
                     }
-                    "sync" => {
-                        //self.handle_vlc_sync(vlc_msg.vlc_meta);
-                    }
+                    "sync" => self.handle_vlc_sync(source, vlc_msg.vlc_meta),
+                    "sync_reply" => self.handle_vlc_sync_reply(vlc_msg.vlc_meta),
                     _ => {
                         println!("unknown vlc type");
                     }
@@ -192,20 +471,68 @@ impl ZchronodServer {
     }
 }
 
+// clock of an event as kind handlers see it
+fn handler_clock(clock: &Clock) -> proto::zchronod::Clock {
+    proto::zchronod::Clock {
+        id: clock.id.clone(),
+        value: clock.value,
+        values: clock.values.clone(),
+    }
+}
+
+// logical time of a clock, the sum of its values
+fn clock_time(clock: &Clock) -> u64 {
+    clock.values.values().sum()
+}
+
+// logical time of the clock a vlc meta was signed with
+fn vlc_meta_time(meta: &VlcMeta) -> Option<u64> {
+    let mut clock = meta.clock_state.clone()?;
+    clock.migrate();
+    Some(clock_time(&clock))
+}
+
+// send time of a sync request, big endian unix seconds
+fn sync_sent_at(event_meta: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(event_meta.try_into().ok()?))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
 struct CoreZchronod {
     count: u8,
     clock: Clock,
+    signer: ClockSigner,
+    verifier: ClockVerifier,
+    log: CausalLog,
+    // clock id -> send time of the last answered sync request
+    synced: HashMap<String, u64>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 struct Config {
     id: String,
+    // hex secret key used to sign clock updates, a random one is used if empty
+    #[serde(default)]
+    key: String,
+    // clock id -> hex x-only public key of every node in the cluster
+    #[serde(default)]
+    peer_keys: HashMap<String, String>,
     peers: Vec<String>,
+    // seconds between two sync requests, 0 disables sync
+    #[serde(default = "default_sync_interval")]
+    sync_interval: u64,
     rpc: RpcConfig,
     gossip: GossipConfig,
     db: String,
 }
 
+fn default_sync_interval() -> u64 {
+    30
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct RpcConfig {
     port: String,
@@ -216,12 +543,108 @@ struct GossipConfig {
     port: String,
 }
 
+// node clock of the last run, folded with every indexed event clock in case the
+// node clock write was lost
+fn restore_clock(id: String, db: &ZchronodDb) -> Clock {
+    let mut clock = Clock::new(id);
+    let mut stored = vec![];
+    match db.query_node_clock() {
+        Ok(Some(bytes)) => stored.extend(Clock::decode(Bytes::from(bytes)).ok()),
+        Ok(None) => {}
+        Err(err) => error!("failed to read node clock: {}", err),
+    }
+    match db.query_all_event_clock() {
+        Ok(events) => {
+            stored.extend(events.into_iter().filter_map(|(_, bytes)| Clock::decode(Bytes::from(bytes)).ok()))
+        }
+        Err(err) => error!("failed to read event clock index: {}", err),
+    }
+    for c in stored.iter_mut() {
+        c.migrate();
+    }
+    clock.merge(&stored.iter().collect());
+    clock
+}
+
+// events in the seq tree were delivered, every other indexed event is offered again
+// and rejected events take their places again
+fn restore_log(db: &ZchronodDb) -> CausalLog {
+    let mut log = CausalLog::new();
+    let event_clock = |event_id: String| -> Option<Clock> {
+        let bytes = db.query_clock_by_event_id(event_id).ok()??;
+        let mut clock = Clock::decode(Bytes::from(bytes)).ok()?;
+        clock.migrate();
+        Some(clock)
+    };
+    let mut cursor = 0;
+    loop {
+        let page = match db.query_seq_after(cursor, 1024) {
+            Ok(page) => page,
+            Err(err) => {
+                error!("failed to read causal order: {}", err);
+                break;
+            }
+        };
+        if page.is_empty() {
+            break;
+        }
+        for (seq, event_id) in page {
+            cursor = seq;
+            if let Some(clock) = event_clock(event_id) {
+                log.restore(&clock);
+            }
+        }
+    }
+
+    let mut ready = vec![];
+    for bytes in db.query_all_skip().unwrap_or_default() {
+        if let Ok(mut clock) = Clock::decode(Bytes::from(bytes)) {
+            clock.migrate();
+            ready.extend(log.skip(clock));
+        }
+    }
+    for (event_id, bytes) in db.query_all_event_clock().unwrap_or_default() {
+        if let Ok(mut clock) = Clock::decode(Bytes::from(bytes)) {
+            clock.migrate();
+            ready.extend(log.offer(event_id, clock));
+        }
+    }
+    if let Err(err) = db.seq_append(ready) {
+        error!("failed to append causal order: {}", err);
+    }
+    log
+}
+
 fn parse_config_file(file_path: &str) -> Result<Config, Box<dyn std::error::Error>> {
     let contents = fs::read_to_string(file_path)?;  // start with src/../..
     let config: Config = serde_yaml::from_str(&contents)?;
     Ok(config)
 }
 
+/// Contributions to subspace `sid` in the db of the node config, as csv or json.
+/// `weights` is a yaml or json file of `attribution::Weights`, defaults if none.
+pub fn export_attribution(config: &str, sid: &str, weights: Option<&str>, format: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let conf = parse_config_file(config)?;
+    let weights: Weights = match weights {
+        Some(path) => serde_yaml::from_reader(File::open(path)?)?,
+        None => Weights::default(),
+    };
+    let db = ZchronodDb::new(conf.db)?;
+    let ops = OpHandler::new(&db)?;
+    let contributions = attribute(&db, &ops, sid, &weights)?.ok_or_else(|| format!("subspace {} not found", sid))?;
+    match format {
+        "json" => Ok(serde_json::to_string_pretty(&contributions)?),
+        "csv" => {
+            let mut out = "pubkey,score,share,ops,referenced\n".to_string();
+            for c in contributions {
+                out.push_str(&format!("{},{},{},{},{}\n", c.pubkey, c.score, c.share, c.ops, c.referenced));
+            }
+            Ok(out)
+        }
+        _ => Err(format!("unknown format {}", format).into()),
+    }
+}
+
 pub fn init_chrono_node(config: &str) {
     println!("{} init_chrono_node", config);
     let conf = parse_config_file(config).unwrap();
@@ -233,39 +656,84 @@ pub fn init_chrono_node(config: &str) {
     // CONTEXT = Some(api::Node::new(Box::new(network)));
     let db = Arc::new(RwLock::new(ZchronodDb::new(conf.db).unwrap()));
 
-    run(gossip, db, rpc, conf.id);
+    let signer = if conf.key.is_empty() {
+        ClockSigner::generate()
+    } else {
+        ClockSigner::from_hex(&conf.key).expect("invalid node key")
+    };
+    info!("clock key of [{}] is {}", &conf.id, signer.public_key_hex());
+    let mut verifier = ClockVerifier::new();
+    for (id, pubkey) in &conf.peer_keys {
+        verifier.register(id.clone(), pubkey).expect("invalid peer key");
+    }
+
+    let registry = Arc::new(KindRegistry::with_default_handlers(&db.read().unwrap()).expect("failed to register kind handlers"));
+
+    run(gossip, db, registry, rpc, conf.id, signer, verifier, conf.sync_interval);
     info!("[{}] zchronod service started",module_path!())
     // network::set().expect("TODO: panic message");
 }
 
-fn run(mut gossip: GossipServer<ZMessage>, db: Arc<RwLock<ZchronodDb>>, rpc: RpcServer, id: String) {
+fn run(mut gossip: GossipServer<ZMessage>, db: Arc<RwLock<ZchronodDb>>, registry: Arc<KindRegistry>, rpc: RpcServer, id: String, signer: ClockSigner, verifier: ClockVerifier, sync_interval: u64) {
     println!("run");
 
     let sender = gossip.send.clone();
+    let direct = gossip.direct.clone();
     let consensus = Arc::new(chronod::init());
     let db_rpc_service = Arc::clone(&db);
     //let consensus_clone = Arc::clone(&consensus);
-    rpc.run(gossip.send.clone(), consensus.receive(), db_rpc_service).expect("failed to run rpc");
+    rpc.run(gossip.send.clone(), consensus.receive(), db_rpc_service, registry.clone()).expect("failed to run rpc");
     let (gossip_send, gossip_recv) = mpsc::channel::<(PeerId, Message)>();
     gossip.register_receive(gossip_send);
 
-    let inner = Arc::new(RwLock::new(CoreZchronod { count: 0, clock: chronod::Clock::new(id) }));
+    let clock = restore_clock(id, &db.read().unwrap());
+    info!("restored clock [{}] at value {}", clock.id, clock.value);
+    let log = restore_log(&db.read().unwrap());
+    info!("restored causal order, {} events pending", log.pending());
+    match db.read().unwrap().index_vlc(|bytes| vlc_meta_time(&VlcMeta::decode(bytes).ok()?)) {
+        Ok(0) => {}
+        Ok(indexed) => info!("indexed {} stored vlc metas for sync", indexed),
+        Err(err) => error!("failed to index vlc metas: {}", err),
+    }
+    let inner = Arc::new(RwLock::new(CoreZchronod { count: 0, clock, signer, verifier, log, synced: HashMap::new() }));
     let sender_copy = gossip.send.clone();
     let inner_c = Arc::clone(&inner);
 
 
     let db_c = Arc::clone(&db);
+    let registry_c = Arc::clone(&registry);
+    if sync_interval > 0 {
+        // the first sync waits one interval so the gossip mesh can form
+        let sync = ZchronodServer {
+            gossip_send: gossip.send.clone(),
+            direct_send: gossip.direct.clone(),
+            node_address: "".to_string(),
+            inner: inner.clone(),
+            z_db: db.clone(),
+            registry: registry.clone(),
+        };
+        thread::spawn(move || {
+            loop {
+                thread::sleep(Duration::from_secs(sync_interval));
+                sync.send_vlc_sync();
+            }
+        });
+    }
+
+    let direct_copy = gossip.direct.clone();
     thread::spawn(move || {
         loop {
             gossip_recv.iter().for_each(|(peer_id, message)| {
                 println!("gossip receive here from {}", peer_id);
                 let handle2 = ZchronodServer {
                     gossip_send: sender_copy.clone(),
+                    direct_send: direct_copy.clone(),
                     node_address: "".to_string(),
                     inner: inner.clone(),
                     z_db: db.clone(),
+                    registry: registry.clone(),
                 };
-                thread::spawn(move || handle2.handle_gossip_msg(message.data));
+                thread::spawn(move || handle2.handle_gossip_msg(message.source, message.data));
             })
         }
     });
@@ -282,12 +750,360 @@ fn run(mut gossip: GossipServer<ZMessage>, db: Arc<RwLock<ZchronodDb>>, rpc: Rpc
             println!("rpc receive here which is {:?}", x);
             let handle1 = ZchronodServer {
                 gossip_send: sender.clone(),
+                direct_send: direct.clone(),
                 node_address: "".to_string(),
                 inner: inner_c.clone(),
                 z_db: db_c.clone(),
+                registry: registry_c.clone(),
             };
 
             thread::spawn(move || handle1.handle_rpc_msg(x));
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+    use std::time::Duration;
+
+    use nostr_kv::lmdb::Writer;
+    use storage::handler::KindHandler;
+
+    use super::*;
+
+    const CRASH_DB_ENV: &str = "ZCHRONOD_CRASH_DB";
+
+    fn server(db: ZchronodDb, clock: Clock) -> ZchronodServer {
+        let (gossip_send, _) = tokio::sync::mpsc::channel(1);
+        let (direct_send, _) = tokio::sync::mpsc::channel(1);
+        ZchronodServer {
+            gossip_send,
+            direct_send,
+            node_address: "".to_string(),
+            inner: Arc::new(RwLock::new(CoreZchronod {
+                count: 0,
+                clock,
+                signer: ClockSigner::generate(),
+                verifier: ClockVerifier::new(),
+                log: CausalLog::new(),
+                synced: HashMap::new(),
+            })),
+            z_db: Arc::new(RwLock::new(db)),
+            registry: Arc::new(KindRegistry::new()),
+        }
+    }
+
+    // a signed clock state of node2 for an event, tampered after signing
+    fn tampered_vlc_meta(signer: &ClockSigner) -> Vec<u8> {
+        let mut clock = Clock::new("node2".to_string());
+        clock.inc();
+        let event = Event { id: vec![1; 32], kind: 1, ..Default::default() };
+        let mut meta = VlcMeta {
+            clock_state: Some(clock),
+            event_meta: event.encode_to_vec(),
+            signature: vec![],
+        };
+        signer.sign(&mut meta);
+        meta.clock_state.as_mut().unwrap().value = 100;
+        meta.encode_to_vec()
+    }
+
+    #[test]
+    fn reject_tampered_clock() {
+        let dir = tempfile::Builder::new().prefix("zchronod-tampered").tempdir().unwrap();
+        let node = server(ZchronodDb::new(dir.path().to_str().unwrap().to_string()).unwrap(), Clock::new("node1".to_string()));
+        let signer = ClockSigner::generate();
+        node.inner.write().unwrap().verifier.register("node2".to_string(), &signer.public_key_hex()).unwrap();
+        let meta = tampered_vlc_meta(&signer);
+
+        // a rejected clock state must not leave the node locked
+        let (done, finished) = mpsc::channel();
+        let inner = node.inner.clone();
+        thread::spawn(move || {
+            node.handle_vlc_request(meta.clone());
+            node.handle_vlc_request(meta.clone());
+            node.handle_vlc_sync_reply(meta);
+            done.send(()).unwrap();
+        });
+        finished.recv_timeout(Duration::from_secs(5)).expect("node deadlocked on a tampered clock");
+        let inner = inner.read().unwrap();
+        assert_eq!(inner.verifier.forged(), 3);
+        assert_eq!(inner.clock.values.get("node2"), None);
+    }
+
+    #[test]
+    fn malformed_gossip_is_dropped() {
+        let dir = tempfile::Builder::new().prefix("zchronod-malformed").tempdir().unwrap();
+        let node = server(ZchronodDb::new(dir.path().to_str().unwrap().to_string()).unwrap(), Clock::new("node1".to_string()));
+        let garbage = vec![0xff; 7];
+        let peer = PeerId::random();
+        let vlc = |r#type: &str, vlc_meta: Vec<u8>| {
+            ZMessage {
+                r#type: "vlc".to_string(),
+                msg_meta: VlcMsg { r#type: r#type.to_string(), vlc_meta }.encode_to_vec(),
+            }
+            .encode_to_vec()
+        };
+        // a meta without an event
+        let no_event = VlcMeta { event_meta: garbage.clone(), ..Default::default() }.encode_to_vec();
+
+        node.handle_gossip_msg(Some(peer), garbage.clone());
+        node.handle_gossip_msg(Some(peer), ZMessage { r#type: "vlc".to_string(), msg_meta: garbage.clone() }.encode_to_vec());
+        for r#type in ["request", "sync", "sync_reply"] {
+            node.handle_gossip_msg(Some(peer), vlc(r#type, garbage.clone()));
+            node.handle_gossip_msg(Some(peer), vlc(r#type, no_event.clone()));
+        }
+        assert_eq!(node.inner.read().unwrap().clock.value, 0);
+    }
+
+    // a sync request of node3 sent at `sent_at`, signed by `signer` if any
+    fn sync_request(signer: Option<&ClockSigner>, sent_at: u64) -> Vec<u8> {
+        let mut meta = VlcMeta {
+            clock_state: Some(Clock::new("node3".to_string())),
+            event_meta: sent_at.to_be_bytes().to_vec(),
+            signature: vec![],
+        };
+        if let Some(signer) = signer {
+            signer.sign(&mut meta);
+        }
+        meta.encode_to_vec()
+    }
+
+    #[test]
+    fn sync_is_signed_and_paged() {
+        let dir = tempfile::Builder::new().prefix("zchronod-sync").tempdir().unwrap();
+        let mut node = server(ZchronodDb::new(dir.path().to_str().unwrap().to_string()).unwrap(), Clock::new("node1".to_string()));
+        let (direct_send, mut direct_recv) = tokio::sync::mpsc::channel(SYNC_PAGE * 2);
+        node.direct_send = direct_send;
+        let remote = ClockSigner::generate();
+        let requester = ClockSigner::generate();
+        node.inner.write().unwrap().verifier.register("node3".to_string(), &requester.public_key_hex()).unwrap();
+
+        let mut clock = Clock::new("node2".to_string());
+        for i in 0..SYNC_PAGE + 10 {
+            clock.inc();
+            let e = Event { id: (i as u64).to_be_bytes().repeat(4), kind: 1, ..Default::default() };
+            let meta = signed_vlc_meta(&remote, &clock, &e);
+            node.z_db.read().unwrap().vlc_write(hex::encode(&e.id), clock_time(&clock), meta).unwrap();
+        }
+        node.merge_clock("00".to_string(), &clock);
+
+        let peer = PeerId::random();
+        let mut answered = || {
+            let mut metas = vec![];
+            while let Ok((to, message)) = direct_recv.try_recv() {
+                assert_eq!(to, peer);
+                let vlc_msg = VlcMsg::decode(Bytes::from(message.msg_meta)).unwrap();
+                assert_eq!(vlc_msg.r#type, "sync_reply");
+                metas.push(VlcMeta::decode(Bytes::from(vlc_msg.vlc_meta)).unwrap());
+            }
+            metas
+        };
+        // unsigned, stale or without a sender
+        node.handle_vlc_sync(Some(peer), sync_request(None, unix_now()));
+        node.handle_vlc_sync(Some(peer), sync_request(Some(&requester), unix_now() - SYNC_MAX_AGE - 10));
+        node.handle_vlc_sync(None, sync_request(Some(&requester), unix_now()));
+        assert!(answered().is_empty());
+
+        node.handle_vlc_sync(Some(peer), sync_request(Some(&requester), unix_now()));
+        let metas = answered();
+        assert_eq!(metas.len(), SYNC_PAGE);
+        let times: Vec<u64> = metas.iter().map(|meta| vlc_meta_time(meta).unwrap()).collect();
+        assert_eq!(times, (1..=SYNC_PAGE as u64).collect::<Vec<_>>());
+
+        // resent within the gap
+        node.handle_vlc_sync(Some(peer), sync_request(Some(&requester), unix_now()));
+        assert!(answered().is_empty());
+    }
+
+    // rejects events with content "bad"
+    struct RejectBad;
+
+    impl KindHandler for RejectBad {
+        fn name(&self) -> &'static str {
+            "reject_bad"
+        }
+
+        fn validate(&self, _db: &ZchronodDb, _txn: &Writer, e: &Event) -> storage::Result<()> {
+            if e.content == "bad" {
+                return Err(nostr_kv::Error::Message("bad".to_string()));
+            }
+            Ok(())
+        }
+
+        fn apply(&self, _db: &ZchronodDb, _txn: &mut Writer, e: &Event, _clock: &proto::zchronod::Clock) -> storage::Result<()> {
+            if e.content == "bad" {
+                return Err(nostr_kv::Error::Message("bad".to_string()));
+            }
+            Ok(())
+        }
+
+        fn query(&self, _db: &ZchronodDb, _method: &str, _params: &serde_json::Value) -> storage::Result<serde_json::Value> {
+            Ok(serde_json::Value::Null)
+        }
+    }
+
+    fn reject_bad_server(dir: &tempfile::TempDir) -> ZchronodServer {
+        let mut node = server(ZchronodDb::new(dir.path().to_str().unwrap().to_string()).unwrap(), Clock::new("node1".to_string()));
+        let mut registry = KindRegistry::new();
+        registry.register_kind(1, Arc::new(RejectBad)).unwrap();
+        node.registry = Arc::new(registry);
+        node
+    }
+
+    fn signed_event(content: &str) -> Event {
+        let key_pair = secp256k1::KeyPair::new_global(&mut secp256k1::rand::thread_rng());
+        let mut e = Event { kind: 1, content: content.to_string(), ..Default::default() };
+        chronod::event::sign_event(&mut e, &key_pair);
+        e
+    }
+
+    fn signed_vlc_meta(signer: &ClockSigner, clock: &Clock, e: &Event) -> Vec<u8> {
+        let mut meta = VlcMeta {
+            clock_state: Some(clock.clone()),
+            event_meta: e.encode_to_vec(),
+            signature: vec![],
+        };
+        signer.sign(&mut meta);
+        meta.encode_to_vec()
+    }
+
+    #[test]
+    fn rejected_local_event_takes_no_clock() {
+        let dir = tempfile::Builder::new().prefix("zchronod-reject").tempdir().unwrap();
+        let node = reject_bad_server(&dir);
+        let bad = signed_event("bad");
+        let mut unsigned = signed_event("ok");
+        unsigned.sig[0] ^= 1;
+
+        // sending to gossip would panic, the receiver is dropped
+        node.handle_rpc_msg(bad.clone());
+        node.handle_rpc_msg(unsigned.clone());
+        assert_eq!(node.inner.read().unwrap().clock.value, 0);
+        let db = node.z_db.read().unwrap();
+        for e in [bad, unsigned] {
+            assert!(!db.has_event(hex::encode(&e.id)).unwrap());
+            assert!(!db.has_vlc(hex::encode(&e.id)).unwrap());
+            assert_eq!(db.query_clock_by_event_id(hex::encode(&e.id)).unwrap(), None);
+        }
+        assert!(db.query_seq_after(0, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejected_gossip_event_keeps_its_place() {
+        let dir = tempfile::Builder::new().prefix("zchronod-reject").tempdir().unwrap();
+        let node = reject_bad_server(&dir);
+        let signer = ClockSigner::generate();
+        node.inner.write().unwrap().verifier.register("node2".to_string(), &signer.public_key_hex()).unwrap();
+
+        let mut remote = Clock::new("node2".to_string());
+        let mut unsigned = signed_event("ok");
+        unsigned.sig[0] ^= 1;
+        let events = [signed_event("bad"), signed_event("ok"), unsigned, signed_event("ok")];
+        let metas: Vec<Vec<u8>> = events
+            .iter()
+            .map(|e| {
+                remote.inc();
+                signed_vlc_meta(&signer, &remote, e)
+            })
+            .collect();
+        let id = |i: usize| hex::encode(&events[i].id);
+
+        // each accepted event waits for the rejected one before it
+        node.handle_vlc_request(metas[1].clone());
+        assert!(node.z_db.read().unwrap().query_seq_after(0, 10).unwrap().is_empty());
+        node.handle_vlc_request(metas[0].clone());
+        node.handle_vlc_request(metas[3].clone());
+        node.handle_vlc_sync_reply(metas[2].clone());
+
+        let db = node.z_db.read().unwrap();
+        let ordered: Vec<String> = db.query_seq_after(0, 10).unwrap().into_iter().map(|(_, id)| id).collect();
+        assert_eq!(ordered, vec![id(1), id(3)]);
+        for i in [0, 2] {
+            assert!(!db.has_event(id(i)).unwrap());
+            assert_eq!(db.query_clock_by_event_id(id(i)).unwrap(), None);
+        }
+        assert_eq!(db.query_all_skip().unwrap().len(), 2);
+        assert_eq!(node.inner.read().unwrap().clock.values.get("node2"), Some(&4));
+
+        // the places are kept after a restart
+        let log = restore_log(&db);
+        assert_eq!(log.pending(), 0);
+        assert_eq!(log.delivered().get("node2"), Some(&4));
+        assert_eq!(db.query_seq_after(0, 10).unwrap().len(), 2);
+    }
+
+    // accept local events and merge remote ones until the process is killed
+    fn crash_child(path: String) {
+        let db = ZchronodDb::new(path).unwrap();
+        let clock = restore_clock("node1".to_string(), &db);
+        let log = restore_log(&db);
+        let node = server(db, clock);
+        node.inner.write().unwrap().log = log;
+        // remote events continue after the restored ones, ids are unique per run
+        let mut remote = Clock::new("node2".to_string());
+        remote.value = node.inner.read().unwrap().clock.values.get("node2").copied().unwrap_or(0);
+        remote.values.insert("node2".to_string(), remote.value);
+        let event_id = |n: u64| format!("{:08x}{:056x}", std::process::id(), n);
+        for i in 0u64.. {
+            let local = node.tick_clock(event_id(i * 2), |_| true).unwrap();
+            node.deliver(event_id(i * 2), local);
+            remote.inc();
+            node.merge_clock(event_id(i * 2 + 1), &remote);
+            node.deliver(event_id(i * 2 + 1), remote.clone());
+        }
+    }
+
+    #[test]
+    fn clock_survives_crash() {
+        if let Ok(path) = std::env::var(CRASH_DB_ENV) {
+            crash_child(path);
+            return;
+        }
+        let dir = tempfile::Builder::new().prefix("zchronod-crash").tempdir().unwrap();
+        let path = dir.path().to_str().unwrap().to_string();
+
+        let mut last_value = 0;
+        for round in 0..3 {
+            let mut child = Command::new(std::env::current_exe().unwrap())
+                .args(["tests::clock_survives_crash", "--exact", "--nocapture"])
+                .env(CRASH_DB_ENV, &path)
+                .stdout(std::process::Stdio::null())
+                .spawn()
+                .unwrap();
+            std::thread::sleep(Duration::from_millis(300 + round * 100));
+            child.kill().unwrap();
+            child.wait().unwrap();
+
+            let db = ZchronodDb::new(path.clone()).unwrap();
+            let clock = restore_clock("node1".to_string(), &db);
+            assert!(clock.value > last_value, "node clock went back after restart");
+            last_value = clock.value;
+
+            // every indexed event happened before the restored clock
+            let events = db.query_all_event_clock().unwrap();
+            assert!(!events.is_empty());
+            for (_, bytes) in events {
+                let event_clock = Clock::decode(Bytes::from(bytes)).unwrap();
+                assert_ne!(event_clock.partial_cmp(&clock), Some(Ordering::Greater));
+                assert_ne!(event_clock.partial_cmp(&clock), None);
+            }
+
+            // every indexed event is in the causal order exactly once
+            let log = restore_log(&db);
+            assert_eq!(log.pending(), 0);
+            let indexed = db.query_all_event_clock().unwrap().len();
+            let ordered = db.query_seq_after(0, usize::MAX).unwrap();
+            assert_eq!(ordered.len(), indexed);
+            let unique: std::collections::HashSet<_> = ordered.iter().map(|(_, id)| id).collect();
+            assert_eq!(unique.len(), indexed);
+
+            // the next local event gets a value never used before
+            let node = server(db, clock);
+            let next = node.tick_clock(format!("ff{:062x}", round), |_| true).unwrap();
+            assert_eq!(next.value, last_value + 1);
+            last_value = next.value;
+        }
+    }
+}
@@ -24,6 +24,9 @@ pub struct VlcMeta {
     pub clock_state: ::core::option::Option<Clock>,
     #[prost(bytes = "vec", tag = "2")]
     pub event_meta: ::prost::alloc::vec::Vec<u8>,
+    /// schnorr signature of the clock owner
+    #[prost(bytes = "vec", tag = "3")]
+    pub signature: ::prost::alloc::vec::Vec<u8>,
 }
 
 #[allow(clippy::derive_partial_eq_without_eq)]
@@ -33,6 +36,10 @@ pub struct Clock {
     pub id: ::prost::alloc::string::String,
     #[prost(uint64, tag = "2")]
     pub value: u64,
+    /// deprecated, legacy recursive format, only read
     #[prost(message, repeated, tag = "3")]
     pub ancestors: ::prost::alloc::vec::Vec<Clock>,
+    /// node id -> counter
+    #[prost(btree_map = "string, uint64", tag = "4")]
+    pub values: ::prost::alloc::collections::BTreeMap<::prost::alloc::string::String, u64>,
 }
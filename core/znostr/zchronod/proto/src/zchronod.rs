@@ -12,6 +12,293 @@ pub struct QueryEventRequest {
 pub struct EventMeta {
     #[prost(message, optional, tag = "1")]
     pub event: ::core::option::Option<Event>,
+    /// clock the event was accepted at
+    #[prost(message, optional, tag = "2")]
+    pub clock: ::core::option::Option<Clock>,
+    /// counter a subspace operation advanced
+    #[prost(message, optional, tag = "3")]
+    pub subspace_keys: ::core::option::Option<SubspaceKeys>,
+}
+/// same field numbers as msg.clock, stored clocks decode as this message
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Clock {
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub value: u64,
+    #[prost(btree_map = "string, uint64", tag = "4")]
+    pub values: ::prost::alloc::collections::BTreeMap<::prost::alloc::string::String, u64>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubspaceRequest {
+    #[prost(string, tag = "1")]
+    pub sid: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Subspace {
+    #[prost(string, tag = "1")]
+    pub sid: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub name: ::prost::alloc::string::String,
+    /// hex pubkey
+    #[prost(string, tag = "3")]
+    pub creator: ::prost::alloc::string::String,
+    /// op kind -> op name
+    #[prost(btree_map = "uint32, string", tag = "4")]
+    pub ops: ::prost::alloc::collections::BTreeMap<u32, ::prost::alloc::string::String>,
+    #[prost(string, tag = "5")]
+    pub rules: ::prost::alloc::string::String,
+    #[prost(string, tag = "6")]
+    pub content: ::prost::alloc::string::String,
+    /// creation event
+    #[prost(string, tag = "7")]
+    pub event_id: ::prost::alloc::string::String,
+    #[prost(int64, tag = "8")]
+    pub created_at: i64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubspaceListResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub subspaces: ::prost::alloc::vec::Vec<Subspace>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubspaceMember {
+    #[prost(string, tag = "1")]
+    pub sid: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub pubkey: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub rules: ::prost::alloc::string::String,
+    /// join event, the creation event for the creator
+    #[prost(string, tag = "4")]
+    pub event_id: ::prost::alloc::string::String,
+    #[prost(int64, tag = "5")]
+    pub created_at: i64,
+    /// granted auth action mask, 1=read, 2=write, 4=execute
+    #[prost(uint32, tag = "6")]
+    pub actions: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubspaceMembersResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub members: ::prost::alloc::vec::Vec<SubspaceMember>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubspaceOpsResponse {
+    /// op kind -> op name
+    #[prost(btree_map = "uint32, string", tag = "1")]
+    pub ops: ::prost::alloc::collections::BTreeMap<u32, ::prost::alloc::string::String>,
+}
+/// Lamport counter of a causality key
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CausalityKey {
+    #[prost(uint32, tag = "1")]
+    pub key: u32,
+    #[prost(uint64, tag = "2")]
+    pub counter: u64,
+}
+/// counters of the causality keys of a subspace
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubspaceKeys {
+    #[prost(string, tag = "1")]
+    pub sid: ::prost::alloc::string::String,
+    #[prost(message, repeated, tag = "2")]
+    pub keys: ::prost::alloc::vec::Vec<CausalityKey>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TokenRequest {
+    #[prost(string, tag = "1")]
+    pub symbol: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TokenBalanceRequest {
+    #[prost(string, tag = "1")]
+    pub symbol: ::prost::alloc::string::String,
+    /// hex pubkey
+    #[prost(string, tag = "2")]
+    pub owner: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TokenAllowanceRequest {
+    #[prost(string, tag = "1")]
+    pub symbol: ::prost::alloc::string::String,
+    /// hex pubkey
+    #[prost(string, tag = "2")]
+    pub owner: ::prost::alloc::string::String,
+    /// hex pubkey
+    #[prost(string, tag = "3")]
+    pub spender: ::prost::alloc::string::String,
+}
+/// amounts are decimal strings in the smallest unit
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TokenAmount {
+    #[prost(string, tag = "1")]
+    pub symbol: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub amount: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TokenMetadata {
+    #[prost(string, tag = "1")]
+    pub symbol: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "3")]
+    pub decimals: u32,
+    /// hex pubkey
+    #[prost(string, tag = "4")]
+    pub issuer: ::prost::alloc::string::String,
+    #[prost(string, tag = "5")]
+    pub supply: ::prost::alloc::string::String,
+    #[prost(string, tag = "6")]
+    pub description: ::prost::alloc::string::String,
+    /// issue event
+    #[prost(string, tag = "7")]
+    pub event_id: ::prost::alloc::string::String,
+    #[prost(int64, tag = "8")]
+    pub created_at: i64,
+}
+/// weights left out take the node defaults
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AttributionRequest {
+    #[prost(string, tag = "1")]
+    pub sid: ::prost::alloc::string::String,
+    /// op kind -> weight
+    #[prost(btree_map = "uint32, double", tag = "2")]
+    pub weights: ::prost::alloc::collections::BTreeMap<u32, f64>,
+    /// kinds not in weights
+    #[prost(double, optional, tag = "3")]
+    pub default_weight: ::core::option::Option<f64>,
+    /// part of an op's weight credited to its parents' authors
+    #[prost(double, optional, tag = "4")]
+    pub parent_weight: ::core::option::Option<f64>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Contribution {
+    #[prost(string, tag = "1")]
+    pub pubkey: ::prost::alloc::string::String,
+    #[prost(double, tag = "2")]
+    pub score: f64,
+    /// score over the subspace total
+    #[prost(double, tag = "3")]
+    pub share: f64,
+    #[prost(uint64, tag = "4")]
+    pub ops: u64,
+    /// ops of others naming its ops as parent
+    #[prost(uint64, tag = "5")]
+    pub referenced: u64,
+}
+/// highest score first
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AttributionResponse {
+    #[prost(string, tag = "1")]
+    pub sid: ::prost::alloc::string::String,
+    #[prost(message, repeated, tag = "2")]
+    pub contributions: ::prost::alloc::vec::Vec<Contribution>,
+}
+/// query routed to the kind handler registered as `handler`, params and result are json
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct KindQueryRequest {
+    #[prost(string, tag = "1")]
+    pub handler: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub method: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub params: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct KindQueryResponse {
+    #[prost(string, tag = "1")]
+    pub result: ::prost::alloc::string::String,
+}
+/// resume with the cursor of the last event received, 0 streams from the first event
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StreamEventsRequest {
+    #[prost(uint64, tag = "1")]
+    pub cursor: u64,
+}
+/// events come in causal order, an event never before one of its predecessors
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StreamedEvent {
+    #[prost(uint64, tag = "1")]
+    pub cursor: u64,
+    #[prost(message, optional, tag = "2")]
+    pub event: ::core::option::Option<Event>,
+    #[prost(message, optional, tag = "3")]
+    pub clock: ::core::option::Option<Clock>,
+    /// counter a subspace operation advanced
+    #[prost(message, optional, tag = "4")]
+    pub subspace_keys: ::core::option::Option<SubspaceKeys>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CompareEventRequest {
+    #[prost(string, tag = "1")]
+    pub first: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub second: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CompareEventResponse {
+    #[prost(enumeration = "CausalOrder", tag = "1")]
+    pub order: i32,
+    #[prost(message, optional, tag = "2")]
+    pub first: ::core::option::Option<Clock>,
+    #[prost(message, optional, tag = "3")]
+    pub second: ::core::option::Option<Clock>,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum CausalOrder {
+    Equal = 0,
+    /// first happened before second
+    Before = 1,
+    /// first happened after second
+    After = 2,
+    Concurrent = 3,
+}
+impl CausalOrder {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            CausalOrder::Equal => "EQUAL",
+            CausalOrder::Before => "BEFORE",
+            CausalOrder::After => "AFTER",
+            CausalOrder::Concurrent => "CONCURRENT",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "EQUAL" => Some(Self::Equal),
+            "BEFORE" => Some(Self::Before),
+            "AFTER" => Some(Self::After),
+            "CONCURRENT" => Some(Self::Concurrent),
+            _ => None,
+        }
+    }
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -25,21 +312,81 @@ pub struct PollEventState {
     /// option - string - option - string
     #[prost(string, repeated, tag = "1")]
     pub state: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// unix seconds of the poll end
+    #[prost(int64, optional, tag = "2")]
+    pub end: ::core::option::Option<i64>,
+    /// ended or reached its clock limit
+    #[prost(bool, tag = "3")]
+    pub closed: bool,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Empty {}
+/// newest polls first, an empty request gets the first page of every poll
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PollListRequest {
+    /// cursor of the previous page
+    #[prost(string, tag = "1")]
+    pub cursor: ::prost::alloc::string::String,
+    /// 0 for the default page size
+    #[prost(uint32, tag = "2")]
+    pub limit: u32,
+    /// hex pubkey
+    #[prost(string, tag = "3")]
+    pub author: ::prost::alloc::string::String,
+    #[prost(enumeration = "PollStatus", tag = "4")]
+    pub status: i32,
+}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct PollListResponse {
     #[prost(message, repeated, tag = "1")]
     pub item: ::prost::alloc::vec::Vec<PollItem>,
+    /// empty on the last page
+    #[prost(string, tag = "2")]
+    pub cursor: ::prost::alloc::string::String,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct PollItem {
+    /// event id - title - info
     #[prost(string, repeated, tag = "1")]
     pub poll_item: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(string, tag = "2")]
+    pub author: ::prost::alloc::string::String,
+    #[prost(int64, tag = "3")]
+    pub created_at: i64,
+    #[prost(bool, tag = "4")]
+    pub closed: bool,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum PollStatus {
+    Any = 0,
+    /// still counts votes
+    Open = 1,
+    /// ended or reached its clock limit
+    Closed = 2,
+}
+impl PollStatus {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            PollStatus::Any => "ANY",
+            PollStatus::Open => "OPEN",
+            PollStatus::Closed => "CLOSED",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "ANY" => Some(Self::Any),
+            "OPEN" => Some(Self::Open),
+            "CLOSED" => Some(Self::Closed),
+            _ => None,
+        }
+    }
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -209,7 +556,7 @@ pub mod zchronod_client {
         }
         pub async fn query_poll_list(
             &mut self,
-            request: impl tonic::IntoRequest<super::Empty>,
+            request: impl tonic::IntoRequest<super::PollListRequest>,
         ) -> std::result::Result<
             tonic::Response<super::PollListResponse>,
             tonic::Status,
@@ -276,72 +623,396 @@ pub mod zchronod_client {
                 .insert(GrpcMethod::new("zchronod.zchronod", "query_by_event_id"));
             self.inner.unary(req, path, codec).await
         }
-    }
-}
-/// Generated server implementations.
-pub mod zchronod_server {
-    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
-    use tonic::codegen::*;
-    /// Generated trait containing gRPC methods that should be implemented for use with ZchronodServer.
-    #[async_trait]
-    pub trait Zchronod: Send + Sync + 'static {
-        async fn send(
-            &self,
-            request: tonic::Request<super::ZchronodRequest>,
-        ) -> std::result::Result<tonic::Response<super::ZchronodResp>, tonic::Status>;
-        async fn query_poll_list(
-            &self,
-            request: tonic::Request<super::Empty>,
+        pub async fn compare_event(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CompareEventRequest>,
+        ) -> std::result::Result<tonic::Response<super::CompareEventResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/zchronod.zchronod/compare_event",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("zchronod.zchronod", "compare_event"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn stream_events(
+            &mut self,
+            request: impl tonic::IntoRequest<super::StreamEventsRequest>,
         ) -> std::result::Result<
-            tonic::Response<super::PollListResponse>,
+            tonic::Response<tonic::codec::Streaming<super::StreamedEvent>>,
             tonic::Status,
-        >;
-        async fn query_poll_event_state(
-            &self,
-            request: tonic::Request<super::QueryPollEventRequest>,
-        ) -> std::result::Result<tonic::Response<super::PollEventState>, tonic::Status>;
-        async fn query_by_event_id(
-            &self,
-            request: tonic::Request<super::QueryEventRequest>,
-        ) -> std::result::Result<tonic::Response<super::EventMeta>, tonic::Status>;
-    }
-    #[derive(Debug)]
-    pub struct ZchronodServer<T: Zchronod> {
-        inner: _Inner<T>,
-        accept_compression_encodings: EnabledCompressionEncodings,
-        send_compression_encodings: EnabledCompressionEncodings,
-        max_decoding_message_size: Option<usize>,
-        max_encoding_message_size: Option<usize>,
-    }
-    struct _Inner<T>(Arc<T>);
-    impl<T: Zchronod> ZchronodServer<T> {
-        pub fn new(inner: T) -> Self {
-            Self::from_arc(Arc::new(inner))
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/zchronod.zchronod/stream_events",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("zchronod.zchronod", "stream_events"));
+            self.inner.server_streaming(req, path, codec).await
         }
-        pub fn from_arc(inner: Arc<T>) -> Self {
-            let inner = _Inner(inner);
-            Self {
-                inner,
-                accept_compression_encodings: Default::default(),
-                send_compression_encodings: Default::default(),
-                max_decoding_message_size: None,
-                max_encoding_message_size: None,
-            }
+        pub async fn query_kind(
+            &mut self,
+            request: impl tonic::IntoRequest<super::KindQueryRequest>,
+        ) -> std::result::Result<tonic::Response<super::KindQueryResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/zchronod.zchronod/query_kind",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("zchronod.zchronod", "query_kind"));
+            self.inner.unary(req, path, codec).await
         }
-        pub fn with_interceptor<F>(
-            inner: T,
-            interceptor: F,
-        ) -> InterceptedService<Self, F>
-        where
-            F: tonic::service::Interceptor,
-        {
-            InterceptedService::new(Self::new(inner), interceptor)
+        pub async fn query_subspace_list(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Empty>,
+        ) -> std::result::Result<tonic::Response<super::SubspaceListResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/zchronod.zchronod/query_subspace_list",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("zchronod.zchronod", "query_subspace_list"));
+            self.inner.unary(req, path, codec).await
         }
-        /// Enable decompressing requests with the given encoding.
-        #[must_use]
-        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
-            self.accept_compression_encodings.enable(encoding);
-            self
+        pub async fn query_subspace_members(
+            &mut self,
+            request: impl tonic::IntoRequest<super::SubspaceRequest>,
+        ) -> std::result::Result<tonic::Response<super::SubspaceMembersResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/zchronod.zchronod/query_subspace_members",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("zchronod.zchronod", "query_subspace_members"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn query_subspace_ops(
+            &mut self,
+            request: impl tonic::IntoRequest<super::SubspaceRequest>,
+        ) -> std::result::Result<tonic::Response<super::SubspaceOpsResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/zchronod.zchronod/query_subspace_ops",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("zchronod.zchronod", "query_subspace_ops"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn query_subspace_keys(
+            &mut self,
+            request: impl tonic::IntoRequest<super::SubspaceRequest>,
+        ) -> std::result::Result<tonic::Response<super::SubspaceKeys>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/zchronod.zchronod/query_subspace_keys",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("zchronod.zchronod", "query_subspace_keys"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn query_token_metadata(
+            &mut self,
+            request: impl tonic::IntoRequest<super::TokenRequest>,
+        ) -> std::result::Result<tonic::Response<super::TokenMetadata>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/zchronod.zchronod/query_token_metadata",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("zchronod.zchronod", "query_token_metadata"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn query_token_supply(
+            &mut self,
+            request: impl tonic::IntoRequest<super::TokenRequest>,
+        ) -> std::result::Result<tonic::Response<super::TokenAmount>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/zchronod.zchronod/query_token_supply",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("zchronod.zchronod", "query_token_supply"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn query_token_balance(
+            &mut self,
+            request: impl tonic::IntoRequest<super::TokenBalanceRequest>,
+        ) -> std::result::Result<tonic::Response<super::TokenAmount>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/zchronod.zchronod/query_token_balance",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("zchronod.zchronod", "query_token_balance"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn query_token_allowance(
+            &mut self,
+            request: impl tonic::IntoRequest<super::TokenAllowanceRequest>,
+        ) -> std::result::Result<tonic::Response<super::TokenAmount>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/zchronod.zchronod/query_token_allowance",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("zchronod.zchronod", "query_token_allowance"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn query_attribution(
+            &mut self,
+            request: impl tonic::IntoRequest<super::AttributionRequest>,
+        ) -> std::result::Result<tonic::Response<super::AttributionResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/zchronod.zchronod/query_attribution",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("zchronod.zchronod", "query_attribution"));
+            self.inner.unary(req, path, codec).await
+        }
+    }
+}
+/// Generated server implementations.
+pub mod zchronod_server {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with ZchronodServer.
+    #[async_trait]
+    pub trait Zchronod: Send + Sync + 'static {
+        async fn send(
+            &self,
+            request: tonic::Request<super::ZchronodRequest>,
+        ) -> std::result::Result<tonic::Response<super::ZchronodResp>, tonic::Status>;
+        async fn query_poll_list(
+            &self,
+            request: tonic::Request<super::PollListRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::PollListResponse>,
+            tonic::Status,
+        >;
+        async fn query_poll_event_state(
+            &self,
+            request: tonic::Request<super::QueryPollEventRequest>,
+        ) -> std::result::Result<tonic::Response<super::PollEventState>, tonic::Status>;
+        async fn query_by_event_id(
+            &self,
+            request: tonic::Request<super::QueryEventRequest>,
+        ) -> std::result::Result<tonic::Response<super::EventMeta>, tonic::Status>;
+        async fn compare_event(
+            &self,
+            request: tonic::Request<super::CompareEventRequest>,
+        ) -> std::result::Result<tonic::Response<super::CompareEventResponse>, tonic::Status>;
+        /// Server streaming response type for the stream_events method.
+        type stream_eventsStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::StreamedEvent, tonic::Status>,
+            >
+            + Send
+            + 'static;
+        async fn stream_events(
+            &self,
+            request: tonic::Request<super::StreamEventsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<Self::stream_eventsStream>,
+            tonic::Status,
+        >;
+        async fn query_kind(
+            &self,
+            request: tonic::Request<super::KindQueryRequest>,
+        ) -> std::result::Result<tonic::Response<super::KindQueryResponse>, tonic::Status>;
+        async fn query_subspace_list(
+            &self,
+            request: tonic::Request<super::Empty>,
+        ) -> std::result::Result<tonic::Response<super::SubspaceListResponse>, tonic::Status>;
+        async fn query_subspace_members(
+            &self,
+            request: tonic::Request<super::SubspaceRequest>,
+        ) -> std::result::Result<tonic::Response<super::SubspaceMembersResponse>, tonic::Status>;
+        async fn query_subspace_ops(
+            &self,
+            request: tonic::Request<super::SubspaceRequest>,
+        ) -> std::result::Result<tonic::Response<super::SubspaceOpsResponse>, tonic::Status>;
+        async fn query_subspace_keys(
+            &self,
+            request: tonic::Request<super::SubspaceRequest>,
+        ) -> std::result::Result<tonic::Response<super::SubspaceKeys>, tonic::Status>;
+        async fn query_token_metadata(
+            &self,
+            request: tonic::Request<super::TokenRequest>,
+        ) -> std::result::Result<tonic::Response<super::TokenMetadata>, tonic::Status>;
+        async fn query_token_supply(
+            &self,
+            request: tonic::Request<super::TokenRequest>,
+        ) -> std::result::Result<tonic::Response<super::TokenAmount>, tonic::Status>;
+        async fn query_token_balance(
+            &self,
+            request: tonic::Request<super::TokenBalanceRequest>,
+        ) -> std::result::Result<tonic::Response<super::TokenAmount>, tonic::Status>;
+        async fn query_token_allowance(
+            &self,
+            request: tonic::Request<super::TokenAllowanceRequest>,
+        ) -> std::result::Result<tonic::Response<super::TokenAmount>, tonic::Status>;
+        async fn query_attribution(
+            &self,
+            request: tonic::Request<super::AttributionRequest>,
+        ) -> std::result::Result<tonic::Response<super::AttributionResponse>, tonic::Status>;
+    }
+    #[derive(Debug)]
+    pub struct ZchronodServer<T: Zchronod> {
+        inner: _Inner<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    struct _Inner<T>(Arc<T>);
+    impl<T: Zchronod> ZchronodServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            let inner = _Inner(inner);
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept_compression_encodings.enable(encoding);
+            self
         }
         /// Compress responses with the given encoding, if the client supports it.
         #[must_use]
@@ -431,7 +1102,7 @@ pub mod zchronod_server {
                 "/zchronod.zchronod/query_poll_list" => {
                     #[allow(non_camel_case_types)]
                     struct query_poll_listSvc<T: Zchronod>(pub Arc<T>);
-                    impl<T: Zchronod> tonic::server::UnaryService<super::Empty>
+                    impl<T: Zchronod> tonic::server::UnaryService<super::PollListRequest>
                     for query_poll_listSvc<T> {
                         type Response = super::PollListResponse;
                         type Future = BoxFuture<
@@ -440,7 +1111,7 @@ pub mod zchronod_server {
                         >;
                         fn call(
                             &mut self,
-                            request: tonic::Request<super::Empty>,
+                            request: tonic::Request<super::PollListRequest>,
                         ) -> Self::Future {
                             let inner = Arc::clone(&self.0);
                             let fut = async move {
@@ -565,6 +1236,559 @@ pub mod zchronod_server {
                     };
                     Box::pin(fut)
                 }
+                "/zchronod.zchronod/compare_event" => {
+                    #[allow(non_camel_case_types)]
+                    struct compare_eventSvc<T: Zchronod>(pub Arc<T>);
+                    impl<
+                        T: Zchronod,
+                    > tonic::server::UnaryService<super::CompareEventRequest>
+                    for compare_eventSvc<T> {
+                        type Response = super::CompareEventResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CompareEventRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Zchronod>::compare_event(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = compare_eventSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/zchronod.zchronod/stream_events" => {
+                    #[allow(non_camel_case_types)]
+                    struct stream_eventsSvc<T: Zchronod>(pub Arc<T>);
+                    impl<
+                        T: Zchronod,
+                    > tonic::server::ServerStreamingService<super::StreamEventsRequest>
+                    for stream_eventsSvc<T> {
+                        type Response = super::StreamedEvent;
+                        type ResponseStream = T::stream_eventsStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::StreamEventsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Zchronod>::stream_events(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = stream_eventsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/zchronod.zchronod/query_kind" => {
+                    #[allow(non_camel_case_types)]
+                    struct query_kindSvc<T: Zchronod>(pub Arc<T>);
+                    impl<
+                        T: Zchronod,
+                    > tonic::server::UnaryService<super::KindQueryRequest>
+                    for query_kindSvc<T> {
+                        type Response = super::KindQueryResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::KindQueryRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Zchronod>::query_kind(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = query_kindSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/zchronod.zchronod/query_subspace_list" => {
+                    #[allow(non_camel_case_types)]
+                    struct query_subspace_listSvc<T: Zchronod>(pub Arc<T>);
+                    impl<
+                        T: Zchronod,
+                    > tonic::server::UnaryService<super::Empty>
+                    for query_subspace_listSvc<T> {
+                        type Response = super::SubspaceListResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::Empty>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Zchronod>::query_subspace_list(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = query_subspace_listSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/zchronod.zchronod/query_subspace_members" => {
+                    #[allow(non_camel_case_types)]
+                    struct query_subspace_membersSvc<T: Zchronod>(pub Arc<T>);
+                    impl<
+                        T: Zchronod,
+                    > tonic::server::UnaryService<super::SubspaceRequest>
+                    for query_subspace_membersSvc<T> {
+                        type Response = super::SubspaceMembersResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SubspaceRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Zchronod>::query_subspace_members(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = query_subspace_membersSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/zchronod.zchronod/query_subspace_ops" => {
+                    #[allow(non_camel_case_types)]
+                    struct query_subspace_opsSvc<T: Zchronod>(pub Arc<T>);
+                    impl<
+                        T: Zchronod,
+                    > tonic::server::UnaryService<super::SubspaceRequest>
+                    for query_subspace_opsSvc<T> {
+                        type Response = super::SubspaceOpsResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SubspaceRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Zchronod>::query_subspace_ops(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = query_subspace_opsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/zchronod.zchronod/query_subspace_keys" => {
+                    #[allow(non_camel_case_types)]
+                    struct query_subspace_keysSvc<T: Zchronod>(pub Arc<T>);
+                    impl<
+                        T: Zchronod,
+                    > tonic::server::UnaryService<super::SubspaceRequest>
+                    for query_subspace_keysSvc<T> {
+                        type Response = super::SubspaceKeys;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SubspaceRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Zchronod>::query_subspace_keys(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = query_subspace_keysSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/zchronod.zchronod/query_token_metadata" => {
+                    #[allow(non_camel_case_types)]
+                    struct query_token_metadataSvc<T: Zchronod>(pub Arc<T>);
+                    impl<
+                        T: Zchronod,
+                    > tonic::server::UnaryService<super::TokenRequest>
+                    for query_token_metadataSvc<T> {
+                        type Response = super::TokenMetadata;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::TokenRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Zchronod>::query_token_metadata(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = query_token_metadataSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/zchronod.zchronod/query_token_supply" => {
+                    #[allow(non_camel_case_types)]
+                    struct query_token_supplySvc<T: Zchronod>(pub Arc<T>);
+                    impl<
+                        T: Zchronod,
+                    > tonic::server::UnaryService<super::TokenRequest>
+                    for query_token_supplySvc<T> {
+                        type Response = super::TokenAmount;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::TokenRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Zchronod>::query_token_supply(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = query_token_supplySvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/zchronod.zchronod/query_token_balance" => {
+                    #[allow(non_camel_case_types)]
+                    struct query_token_balanceSvc<T: Zchronod>(pub Arc<T>);
+                    impl<
+                        T: Zchronod,
+                    > tonic::server::UnaryService<super::TokenBalanceRequest>
+                    for query_token_balanceSvc<T> {
+                        type Response = super::TokenAmount;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::TokenBalanceRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Zchronod>::query_token_balance(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = query_token_balanceSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/zchronod.zchronod/query_token_allowance" => {
+                    #[allow(non_camel_case_types)]
+                    struct query_token_allowanceSvc<T: Zchronod>(pub Arc<T>);
+                    impl<
+                        T: Zchronod,
+                    > tonic::server::UnaryService<super::TokenAllowanceRequest>
+                    for query_token_allowanceSvc<T> {
+                        type Response = super::TokenAmount;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::TokenAllowanceRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Zchronod>::query_token_allowance(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = query_token_allowanceSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/zchronod.zchronod/query_attribution" => {
+                    #[allow(non_camel_case_types)]
+                    struct query_attributionSvc<T: Zchronod>(pub Arc<T>);
+                    impl<
+                        T: Zchronod,
+                    > tonic::server::UnaryService<super::AttributionRequest>
+                    for query_attributionSvc<T> {
+                        type Response = super::AttributionResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::AttributionRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Zchronod>::query_attribution(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = query_attributionSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         Ok(
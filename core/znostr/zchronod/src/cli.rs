@@ -1,5 +1,5 @@
 use {
-    clap::{App, Arg},
+    clap::{App, Arg, SubCommand},
 };
 
 pub fn set_clap<'a>(name: &str, about: &'a str) -> App<'a, 'a> {
@@ -23,4 +23,34 @@ pub fn set_clap<'a>(name: &str, about: &'a str) -> App<'a, 'a> {
                 .global(true)
                 .help("log path to store log file"),
         )
-}
\ No newline at end of file
+        .subcommand(
+            SubCommand::with_name("attribution")
+                .about("Export the contribution scores of a subspace from the node db")
+                .arg(
+                    Arg::with_name("sid")
+                        .long("sid")
+                        .value_name("SID")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Subspace to score"),
+                )
+                .arg(
+                    Arg::with_name("weights")
+                        .short("w")
+                        .long("weights")
+                        .value_name("FILEPATH")
+                        .takes_value(true)
+                        .help("Yaml or json file with kinds, default and parent weights"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .short("f")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .takes_value(true)
+                        .possible_values(&["csv", "json"])
+                        .default_value("csv")
+                        .help("Output format"),
+                ),
+        )
+}
@@ -18,6 +18,17 @@ async fn main() -> Result<(), Box<dyn error::Error>> {
         crate_description!(),
     ).get_matches();
 
+    if let Some(export) = matches.subcommand_matches("attribution") {
+        let out = process::export_attribution(
+            &config_path(&matches),
+            export.value_of("sid").unwrap(),
+            export.value_of("weights"),
+            export.value_of("format").unwrap(),
+        )?;
+        print!("{}", out);
+        return Ok(());
+    }
+
     process_cmd(&matches).expect("failed to process cmd");
 
     println!("init ok");
@@ -36,13 +47,18 @@ fn process_cmd(matches: &ArgMatches<'_>) -> Result<(), Box<dyn error::Error>> {
     } else {
         zchronod_logger::init_zhronod_log_with_default()
     }
-    let mut config_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    config_path = config_path.join("chronod.yaml");
-    let config = matches.value_of("config").unwrap_or(config_path.to_str().unwrap());
-    process::init_chrono_node(config);
+    process::init_chrono_node(&config_path(matches));
     Ok(())
 }
 
+// config file given with -C, chronod.yaml of the crate by default
+fn config_path(matches: &ArgMatches<'_>) -> String {
+    match matches.value_of("config_file") {
+        Some(path) => path.to_string(),
+        None => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("chronod.yaml").to_str().unwrap().to_string(),
+    }
+}
+
 fn find_env(env: &str) -> String {
     if let Ok(path_value) = env::var(env) {
         path_value
@@ -0,0 +1,218 @@
+//! Contribution attribution of a subspace.
+//!
+//! Every accepted operation of the subspace scores its author the weight of
+//! its kind. An operation naming earlier operations in `parent` tags passes
+//! `parent` times its weight on to their authors as well, split evenly, so
+//! work others build on is rewarded. Referencing one's own operations earns
+//! nothing extra. Shares are scores over the subspace total, the split of a
+//! reward.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::handler::op::OpHandler;
+use crate::handler::subspace::SubspaceHandler;
+use crate::{Result, ZchronodDb};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Weights {
+    // op kind -> weight, kinds not listed weigh `default`
+    #[serde(default = "default_kinds")]
+    pub kinds: BTreeMap<u32, f64>,
+    #[serde(default = "default_weight")]
+    pub default: f64,
+    // part of the weight of an operation credited to the authors of its parents
+    #[serde(default = "default_parent")]
+    pub parent: f64,
+}
+
+// post, propose, vote, invite
+fn default_kinds() -> BTreeMap<u32, f64> {
+    [(30300, 1.0), (30301, 3.0), (30302, 0.5), (30303, 2.0)].into()
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn default_parent() -> f64 {
+    0.5
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            kinds: default_kinds(),
+            default: default_weight(),
+            parent: default_parent(),
+        }
+    }
+}
+
+impl Weights {
+    pub fn weight(&self, kind: u32) -> f64 {
+        self.kinds.get(&kind).copied().unwrap_or(self.default)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Contribution {
+    pub pubkey: String,
+    pub score: f64,
+    // score over the total of the subspace
+    pub share: f64,
+    // accepted operations of the pubkey
+    pub ops: u64,
+    // operations of others naming one of its operations as parent
+    pub referenced: u64,
+}
+
+/// Contributions to subspace `sid`, highest score first, none for an unknown sid.
+pub fn attribute(db: &ZchronodDb, ops: &OpHandler, sid: &str, weights: &Weights) -> Result<Option<Vec<Contribution>>> {
+    if SubspaceHandler::new(db)?.subspace(db, sid)?.is_none() {
+        return Ok(None);
+    }
+    let mut events = vec![];
+    for (event_id, _) in ops.operations(db, sid)? {
+        let e = db.query_by_event_id(event_id.clone())?;
+        if !e.id.is_empty() {
+            events.push((event_id, e));
+        }
+    }
+    let authors: HashMap<&str, String> = events
+        .iter()
+        .map(|(event_id, e)| (event_id.as_str(), hex::encode(&e.pubkey)))
+        .collect();
+
+    let mut contributions: BTreeMap<String, Contribution> = BTreeMap::new();
+    for (event_id, e) in &events {
+        let author = &authors[event_id.as_str()];
+        let weight = weights.weight(e.kind);
+        let own = contribution(&mut contributions, author);
+        own.score += weight;
+        own.ops += 1;
+
+        let mut parents: Vec<&str> = e
+            .tags
+            .iter()
+            .filter(|t| t.values.first().map(|v| v.as_str()) == Some("parent"))
+            .flat_map(|t| t.values[1..].iter().map(|v| v.as_str()))
+            .collect();
+        parents.sort();
+        parents.dedup();
+        let parent_authors: Vec<&String> = parents
+            .into_iter()
+            .filter_map(|parent| authors.get(parent))
+            .filter(|parent_author| *parent_author != author)
+            .collect();
+        if parent_authors.is_empty() {
+            continue;
+        }
+        let credit = weight * weights.parent / parent_authors.len() as f64;
+        for parent_author in parent_authors {
+            let parent = contribution(&mut contributions, parent_author);
+            parent.score += credit;
+            parent.referenced += 1;
+        }
+    }
+
+    let total: f64 = contributions.values().map(|c| c.score).sum();
+    let mut result: Vec<Contribution> = contributions.into_values().collect();
+    for contribution in result.iter_mut() {
+        contribution.share = if total > 0.0 { contribution.score / total } else { 0.0 };
+    }
+    result.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.pubkey.cmp(&b.pubkey)));
+    Ok(Some(result))
+}
+
+fn contribution<'a>(contributions: &'a mut BTreeMap<String, Contribution>, pubkey: &str) -> &'a mut Contribution {
+    contributions.entry(pubkey.to_string()).or_insert_with(|| Contribution {
+        pubkey: pubkey.to_string(),
+        score: 0.0,
+        share: 0.0,
+        ops: 0,
+        referenced: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::KindHandler;
+    use crate::testing::{apply, tag, temp_db};
+    use proto::zchronod::{Clock, Event, TagArray};
+    use serde_json::json;
+
+    fn op(id: u8, kind: u32, author: u8, parents: &[u8]) -> Event {
+        let key = format!("key={}", kind);
+        let mut tags = vec![
+            tag(&["auth", "action=2", &key, "exp=100"]),
+            tag(&["d", "subspace_op"]),
+            tag(&["sid", "0xMG"]),
+        ];
+        if !parents.is_empty() {
+            let mut parent = vec!["parent".to_string()];
+            parent.extend(parents.iter().map(|p| hex::encode([*p; 32])));
+            tags.push(TagArray { values: parent });
+        }
+        Event { id: vec![id; 32], pubkey: vec![author; 32], kind, tags, ..Default::default() }
+    }
+
+    #[test]
+    fn attribute_subspace() {
+        let (_dir, db) = temp_db();
+        let subspaces = SubspaceHandler::new(&db).unwrap();
+        let ops = OpHandler::new(&db).unwrap();
+        assert_eq!(attribute(&db, &ops, "0xMG", &Weights::default()).unwrap(), None);
+
+        let create = Event {
+            id: vec![100; 32],
+            pubkey: vec![1; 32],
+            kind: 30100,
+            tags: vec![
+                tag(&["d", "subspace_create"]),
+                tag(&["sid", "0xMG"]),
+                tag(&["subspace_name", "governance"]),
+                tag(&["ops", "post=30300,propose=30301"]),
+            ],
+            ..Default::default()
+        };
+        apply(&subspaces, &db, &create, &Clock::default()).unwrap();
+        for member in [2u8, 3] {
+            let join = Event {
+                id: vec![100 + member; 32],
+                pubkey: vec![member; 32],
+                kind: 30200,
+                tags: vec![tag(&["d", "subspace_join"]), tag(&["sid", "0xMG"])],
+                ..Default::default()
+            };
+            apply(&subspaces, &db, &join, &Clock::default()).unwrap();
+        }
+        for e in [
+            op(1, 30300, 1, &[]),
+            op(2, 30300, 2, &[1]),
+            op(3, 30301, 3, &[1, 2, 2]),
+            // own parents earn nothing, unknown ones are ignored
+            op(4, 30300, 1, &[1, 99]),
+        ] {
+            apply(&ops, &db, &e, &Clock::default()).unwrap();
+            db.event_write(e).unwrap();
+        }
+
+        let result = attribute(&db, &ops, "0xMG", &Weights::default()).unwrap().unwrap();
+        let scores: Vec<(String, f64, u64, u64)> = result.iter().map(|c| (c.pubkey.clone(), c.score, c.ops, c.referenced)).collect();
+        assert_eq!(scores, vec![
+            (hex::encode([1u8; 32]), 3.25, 2, 2),
+            (hex::encode([3u8; 32]), 3.0, 1, 0),
+            (hex::encode([2u8; 32]), 1.75, 1, 1),
+        ]);
+        assert_eq!(result.iter().map(|c| c.share).sum::<f64>(), 1.0);
+
+        // weights come with the query
+        let weights = json!({"kinds": {"30301": 1.0}, "parent": 0.0});
+        let result = ops.query(&db, "attribution", &json!({"sid": "0xMG", "weights": weights})).unwrap();
+        let result: Vec<Contribution> = serde_json::from_value(result).unwrap();
+        assert_eq!(result.iter().map(|c| c.score).collect::<Vec<_>>(), vec![2.0, 1.0, 1.0]);
+    }
+}
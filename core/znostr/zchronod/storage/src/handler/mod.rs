@@ -0,0 +1,336 @@
+//! Kind handlers.
+//!
+//! Every event kind with its own state machine (polls, CausalityKey ops,
+//! tokens, ...) is a `KindHandler`. Handlers are registered in a
+//! `KindRegistry` under a kind or a range of kinds, keep their state in
+//! trees of `ZchronodDb` they open themselves and answer their own queries
+//! through `query_kind`.
+//!
+//! An event is checked, applied, observed and stored with its clock in one
+//! writer transaction, handlers read and write their state through it so
+//! nothing is kept of an event some step rejects, and every stored event has
+//! the clock it was accepted at.
+
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use nostr_kv::{Error, lmdb::{Transaction, Writer}};
+use prost::Message;
+use proto::zchronod::{Clock, Event};
+use serde_json::Value;
+
+use crate::{Result, ZchronodDb};
+
+pub mod op;
+pub mod poll;
+pub mod subspace;
+pub mod token;
+
+pub use op::OpHandler;
+pub use poll::PollHandler;
+pub use subspace::SubspaceHandler;
+pub use token::TokenHandler;
+
+pub trait KindHandler: Send + Sync {
+    /// Name queries are routed by.
+    fn name(&self) -> &'static str;
+
+    /// Check an event before it is accepted, so a submitting client learns why
+    /// it was refused. `apply` has to check again, events also come over gossip.
+    /// Nothing is written to `txn`.
+    fn validate(&self, _db: &ZchronodDb, _txn: &Writer, _e: &Event) -> Result<()> {
+        Ok(())
+    }
+
+    /// Apply an accepted event to the handler state in `txn`, the transaction
+    /// the event is stored in. `clock` is the clock the event takes in the
+    /// causal order, the same on every node. An error rejects the event.
+    fn apply(&self, db: &ZchronodDb, txn: &mut Writer, e: &Event, clock: &Clock) -> Result<()>;
+
+    /// See an accepted event of any kind in `txn`, after the handler of its kind
+    /// applied it. Only called on handlers registered as observers.
+    fn observe(&self, _db: &ZchronodDb, _txn: &mut Writer, _e: &Event, _clock: &Clock) -> Result<()> {
+        Ok(())
+    }
+
+    /// Answer a handler query, params and result are json.
+    fn query(&self, db: &ZchronodDb, method: &str, params: &Value) -> Result<Value>;
+}
+
+#[derive(Default)]
+pub struct KindRegistry {
+    kinds: Vec<(RangeInclusive<u32>, Arc<dyn KindHandler>)>,
+    names: HashMap<&'static str, Arc<dyn KindHandler>>,
+    observers: Vec<Arc<dyn KindHandler>>,
+}
+
+impl KindRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Registry with the handlers every node runs.
+    pub fn with_default_handlers(db: &ZchronodDb) -> Result<Self> {
+        let mut registry = KindRegistry::new();
+        let poll: Arc<dyn KindHandler> = Arc::new(PollHandler::new(db)?);
+        registry.register_kind(poll::POLL_KIND, poll.clone())?;
+        registry.register_kind(poll::VOTE_KIND, poll)?;
+        let subspace: Arc<dyn KindHandler> = Arc::new(SubspaceHandler::new(db)?);
+        registry.register_kind(subspace::SUBSPACE_CREATE_KIND, subspace.clone())?;
+        registry.register_kind(subspace::SUBSPACE_JOIN_KIND, subspace)?;
+        let op: Arc<dyn KindHandler> = Arc::new(OpHandler::new(db)?);
+        registry.register_range(op::OP_KINDS, op.clone())?;
+        // held ops wait for the join of their author
+        registry.register_observer(op);
+        let token: Arc<dyn KindHandler> = Arc::new(TokenHandler::new(db)?);
+        registry.register_range(token::TOKEN_KINDS, token.clone())?;
+        // MintCredit rules count events of any kind
+        registry.register_observer(token);
+        Ok(registry)
+    }
+
+    pub fn register_kind(&mut self, kind: u32, handler: Arc<dyn KindHandler>) -> Result<()> {
+        self.register_range(kind..=kind, handler)
+    }
+
+    /// Register a handler for every kind in `kinds`, ranges may not overlap.
+    pub fn register_range(&mut self, kinds: RangeInclusive<u32>, handler: Arc<dyn KindHandler>) -> Result<()> {
+        if let Some((used, other)) = self
+            .kinds
+            .iter()
+            .find(|(used, _)| used.start() <= kinds.end() && kinds.start() <= used.end())
+        {
+            return Err(Error::Message(format!(
+                "kinds {:?} of handler {} overlap {:?} of handler {}",
+                kinds,
+                handler.name(),
+                used,
+                other.name()
+            )));
+        }
+        self.names.insert(handler.name(), handler.clone());
+        self.kinds.push((kinds, handler));
+        Ok(())
+    }
+
+    /// Let `handler` observe every accepted event.
+    pub fn register_observer(&mut self, handler: Arc<dyn KindHandler>) {
+        self.observers.push(handler);
+    }
+
+    pub fn handler_for(&self, kind: u32) -> Option<&Arc<dyn KindHandler>> {
+        self.kinds
+            .iter()
+            .find(|(kinds, _)| kinds.contains(&kind))
+            .map(|(_, handler)| handler)
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&Arc<dyn KindHandler>> {
+        self.names.get(name)
+    }
+
+    /// Validate `e` with its handler against the stored state, events of kinds
+    /// without a handler pass.
+    pub fn validate(&self, db: &ZchronodDb, e: &Event) -> Result<()> {
+        self.validate_in(db, &db.writer()?, e)
+    }
+
+    /// Validate `e` against the state in `txn`.
+    pub fn validate_in(&self, db: &ZchronodDb, txn: &Writer, e: &Event) -> Result<()> {
+        match self.handler_for(e.kind) {
+            Some(handler) => handler.validate(db, txn, e),
+            None => Ok(()),
+        }
+    }
+
+    /// Apply `e` at `clock` in `txn` with its handler, events of kinds without a
+    /// handler pass. Observers see the event once it passed. The caller commits
+    /// `txn` with the event once every step passed.
+    pub fn apply(&self, db: &ZchronodDb, txn: &mut Writer, e: &Event, clock: &Clock) -> Result<()> {
+        if let Some(handler) = self.handler_for(e.kind) {
+            handler.apply(db, txn, e, clock)?;
+        }
+        for observer in &self.observers {
+            observer.observe(db, txn, e, clock)?;
+        }
+        Ok(())
+    }
+
+    /// Validate `e` if it is `local`, submitted to this node, then apply,
+    /// observe and store it with `clock` in one transaction. Nothing is kept of
+    /// an event some step rejects, an event is accepted once.
+    pub fn accept(&self, db: &ZchronodDb, e: &Event, clock: &Clock, local: bool) -> Result<()> {
+        let mut txn = db.writer()?;
+        // the same event can come again under another clock
+        if db.read_has_event(&txn, hex::encode(&e.id))? {
+            return Err(Error::Message("event id duplicated".to_string()));
+        }
+        if local {
+            self.validate_in(db, &txn, e)?;
+        }
+        self.apply(db, &mut txn, e, clock)?;
+        db.put_event(&mut txn, e)?;
+        db.put_event_clock(&mut txn, hex::encode(&e.id), clock.encode_to_vec())?;
+        txn.commit()
+    }
+
+    pub fn query(&self, db: &ZchronodDb, name: &str, method: &str, params: &Value) -> Result<Value> {
+        match self.by_name(name) {
+            Some(handler) => handler.query(db, method, params),
+            None => Err(Error::Message(format!("unknown handler {}", name))),
+        }
+    }
+}
+
+/// String param `name` of a query.
+pub(crate) fn str_param<'a>(params: &'a Value, name: &str) -> Result<&'a str> {
+    params[name]
+        .as_str()
+        .ok_or_else(|| Error::Message(format!("missing {}", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{apply_all, temp_db};
+    use nostr_kv::lmdb::Tree;
+    use serde_json::json;
+
+    struct Echo(&'static str);
+
+    impl KindHandler for Echo {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn apply(&self, _db: &ZchronodDb, _txn: &mut Writer, e: &Event, _clock: &Clock) -> Result<()> {
+            if e.content == "bad" {
+                return Err(Error::Message("bad".to_string()));
+            }
+            Ok(())
+        }
+
+        fn query(&self, _db: &ZchronodDb, method: &str, params: &Value) -> Result<Value> {
+            Ok(json!({ "handler": self.0, "method": method, "params": params }))
+        }
+    }
+
+    #[test]
+    fn registry_routes_by_kind_and_name() {
+        let (_dir, db) = temp_db();
+        let mut registry = KindRegistry::new();
+        registry.register_kind(1, Arc::new(Echo("one"))).unwrap();
+        registry.register_range(30300..=30308, Arc::new(Echo("ops"))).unwrap();
+        assert!(registry.register_range(30308..=30310, Arc::new(Echo("clash"))).is_err());
+        assert!(registry.by_name("clash").is_none());
+
+        assert_eq!(registry.handler_for(1).unwrap().name(), "one");
+        assert_eq!(registry.handler_for(30304).unwrap().name(), "ops");
+        assert!(registry.handler_for(30309).is_none());
+
+        let bad = Event { kind: 30300, content: "bad".to_string(), ..Default::default() };
+        assert!(apply_all(&registry, &db, &bad, &Clock::default()).is_err());
+        let unknown = Event { kind: 7, content: "bad".to_string(), ..Default::default() };
+        assert!(apply_all(&registry, &db, &unknown, &Clock::default()).is_ok());
+
+        let result = registry.query(&db, "ops", "list", &json!({"sid": "x"})).unwrap();
+        assert_eq!(result["params"]["sid"], "x");
+        assert!(registry.query(&db, "none", "list", &Value::Null).is_err());
+    }
+
+    #[derive(Default)]
+    struct Counter(std::sync::atomic::AtomicUsize);
+
+    impl KindHandler for Counter {
+        fn name(&self) -> &'static str {
+            "counter"
+        }
+
+        fn apply(&self, _db: &ZchronodDb, _txn: &mut Writer, _e: &Event, _clock: &Clock) -> Result<()> {
+            Ok(())
+        }
+
+        fn observe(&self, _db: &ZchronodDb, _txn: &mut Writer, _e: &Event, _clock: &Clock) -> Result<()> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn query(&self, _db: &ZchronodDb, _method: &str, _params: &Value) -> Result<Value> {
+            Ok(json!(self.0.load(std::sync::atomic::Ordering::SeqCst)))
+        }
+    }
+
+    #[test]
+    fn observers_see_accepted_events() {
+        let (_dir, db) = temp_db();
+        let mut registry = KindRegistry::new();
+        registry.register_kind(1, Arc::new(Echo("one"))).unwrap();
+        let counter = Arc::new(Counter::default());
+        registry.register_observer(counter.clone());
+
+        apply_all(&registry, &db, &Event { kind: 1, ..Default::default() }, &Clock::default()).unwrap();
+        apply_all(&registry, &db, &Event { kind: 7, ..Default::default() }, &Clock::default()).unwrap();
+        // rejected by the handler of its kind
+        assert!(apply_all(&registry, &db, &Event { kind: 1, content: "bad".to_string(), ..Default::default() }, &Clock::default()).is_err());
+        assert_eq!(counter.query(&db, "", &Value::Null).unwrap(), json!(2));
+    }
+
+    // keeps the id of every applied event, observes by rejecting content "late"
+    struct Keep(Tree);
+
+    impl KindHandler for Keep {
+        fn name(&self) -> &'static str {
+            "keep"
+        }
+
+        fn apply(&self, _db: &ZchronodDb, txn: &mut Writer, e: &Event, _clock: &Clock) -> Result<()> {
+            txn.put(&self.0, &e.id, "")
+        }
+
+        fn observe(&self, _db: &ZchronodDb, _txn: &mut Writer, e: &Event, _clock: &Clock) -> Result<()> {
+            if e.content == "late" {
+                return Err(Error::Message("late".to_string()));
+            }
+            Ok(())
+        }
+
+        fn query(&self, db: &ZchronodDb, _method: &str, params: &Value) -> Result<Value> {
+            let id = hex::decode(params.as_str().unwrap_or_default()).map_err(|e| Error::Message(e.to_string()))?;
+            Ok(json!(db.reader()?.get(&self.0, id)?.is_some()))
+        }
+    }
+
+    #[test]
+    fn rejected_event_keeps_nothing() {
+        let (_dir, db) = temp_db();
+        let mut registry = KindRegistry::new();
+        let keep = Arc::new(Keep(db.open_tree("keep").unwrap()));
+        registry.register_kind(1, keep.clone()).unwrap();
+        registry.register_observer(keep.clone());
+        let kept = |e: &Event| keep.query(&db, "", &json!(hex::encode(&e.id))).unwrap() == json!(true);
+
+        // applied by its handler, then rejected by an observer
+        let late = Event { id: vec![1; 32], kind: 1, content: "late".to_string(), ..Default::default() };
+        assert!(registry.accept(&db, &late, &Clock::default(), false).is_err());
+        assert!(!kept(&late));
+        assert!(!db.has_event(hex::encode(&late.id)).unwrap());
+
+        let ok = Event { id: vec![2; 32], kind: 1, ..Default::default() };
+        registry.accept(&db, &ok, &Clock::default(), false).unwrap();
+        assert!(kept(&ok));
+        assert!(db.has_event(hex::encode(&ok.id)).unwrap());
+        assert!(db.query_clock_by_event_id(hex::encode(&ok.id)).unwrap().is_some());
+        assert!(db.query_clock_by_event_id(hex::encode(&late.id)).unwrap().is_none());
+        assert!(registry.accept(&db, &ok, &Clock::default(), false).is_err());
+    }
+
+    #[test]
+    fn default_handlers_open() {
+        let (_dir, db) = temp_db();
+        let registry = KindRegistry::with_default_handlers(&db).unwrap();
+        for name in ["poll", "subspace", "op", "token"] {
+            assert!(registry.by_name(name).is_some());
+        }
+    }
+}
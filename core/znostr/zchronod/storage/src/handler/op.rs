@@ -0,0 +1,762 @@
+//! CausalityKey subspace operations (kinds 30300-30308).
+//!
+//! Every operation carries `["auth", "action=<mask>", "key=<id>", "exp=<clock>"]`.
+//! An operation is accepted when
+//! - its sid names a known subspace that defines the event kind as an op,
+//! - the author is a member whose granted actions cover the claimed mask,
+//!   and the mask includes write; the op has to causally follow the event
+//!   that made its author a member,
+//! - the key is the causality key of the op, the event kind,
+//! - exp is not behind the logical time of the event.
+//!
+//! The logical time of an event is the number of events its vector clock
+//! counts, i.e. the sum of its entries. An applied event is judged by the
+//! clock it takes in the causal order, which every node sees the same; a
+//! submitted event by the next tick of the node clock.
+//!
+//! Every causality key of a subspace has a Lamport counter. The counter of an
+//! operation is one more than the highest counter of the operations of the
+//! same key it causally follows, 1 for the first; events of other keys or
+//! subspaces do not move it. An operation that arrives after operations that
+//! follow it counts them again, so every node derives the same counters
+//! whatever order operations arrive in. The counter of a key is the highest
+//! counter of its operations.
+//!
+//! An operation that arrives before the join of its author, or before the
+//! subspace, is held: the event is stored but not counted. The handler
+//! observes joins and creations and checks the held operations again, an
+//! operation that does not follow any join of its author stays held. At most
+//! `MAX_HELD_PER_AUTHOR` operations of an author and `MAX_HELD_PER_SID` of a
+//! subspace are held, an operation past either is rejected, so pubkeys that
+//! never join cannot fill the store.
+//!
+//! k: sid_key, v: counter as a decimal string, in the "op_counter" tree
+//! k: event id, v: `KeyCounter` json the event advanced, in the "op_event" tree
+//! k: sid_key_event id, v: event id, in the "op_key" tree
+//! k: sid_pubkey_event id, v: `HeldOp` json, in the "op_held" tree
+
+use std::collections::BTreeMap;
+use std::ops::{Bound, RangeInclusive};
+
+use log::info;
+use nostr_kv::{Error, lmdb::{Transaction, Tree, Writer}};
+use prost::Message;
+use proto::zchronod::{Clock, Event};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::attribution::{attribute, Weights};
+use crate::handler::subspace::{
+    check_define, required_tag, tag_value, Member, SubspaceHandler, ACTION_WRITE, SUBSPACE_CREATE_KIND,
+    SUBSPACE_JOIN_KIND,
+};
+use crate::handler::{str_param, KindHandler};
+use crate::{Result, ZchronodDb};
+
+pub const OP_KINDS: RangeInclusive<u32> = 30300..=30308;
+
+// first causality key of the dimension list, `dim=1` is post
+const FIRST_KEY: u32 = 30300;
+
+/// Operations held for one author of a subspace.
+pub const MAX_HELD_PER_AUTHOR: usize = 16;
+/// Operations held for a subspace.
+pub const MAX_HELD_PER_SID: usize = 256;
+
+const COUNTER_TREE_NAME: &str = "op_counter";
+const OP_EVENT_TREE_NAME: &str = "op_event";
+const KEY_OP_TREE_NAME: &str = "op_key";
+const HELD_TREE_NAME: &str = "op_held";
+
+/// Counter of a causality key after an operation advanced it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct KeyCounter {
+    pub sid: String,
+    pub key: u32,
+    pub counter: u64,
+    // values of the clock the op takes in the causal order
+    #[serde(default)]
+    pub clock: BTreeMap<String, u64>,
+}
+
+/// Operation waiting for its author to join.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HeldOp {
+    pub event: Event,
+    // values of the clock the op takes in the causal order
+    pub clock: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Auth {
+    pub action: u32,
+    pub key: u32,
+    pub exp: u64,
+}
+
+impl Auth {
+    /// Parse the auth tag of `e`. `dim=<n>` is accepted for `key=`, dimension
+    /// n being the n-th causality key from 30300.
+    pub fn parse(e: &Event) -> Result<Self> {
+        let tag = e
+            .tags
+            .iter()
+            .find(|t| t.values.first().map(|v| v.as_str()) == Some("auth"))
+            .ok_or_else(|| Error::Message(format!("kind {} operation needs an auth tag", e.kind)))?;
+        let (mut action, mut key, mut exp) = (None, None, None);
+        for item in &tag.values[1..] {
+            let (name, value) = item
+                .split_once('=')
+                .ok_or_else(|| Error::Message(format!("invalid auth item {}", item)))?;
+            let invalid = || Error::Message(format!("invalid auth item {}", item));
+            match name.trim() {
+                "action" => action = Some(value.trim().parse::<u32>().map_err(|_| invalid())?),
+                "key" => key = Some(value.trim().parse::<u32>().map_err(|_| invalid())?),
+                "dim" => {
+                    let dim = value.trim().parse::<u32>().map_err(|_| invalid())?;
+                    if dim == 0 {
+                        return Err(invalid());
+                    }
+                    key = Some(FIRST_KEY + dim - 1)
+                }
+                "exp" => exp = Some(value.trim().parse::<u64>().map_err(|_| invalid())?),
+                _ => {}
+            }
+        }
+        match (action, key, exp) {
+            (Some(action), Some(key), Some(exp)) => Ok(Auth { action, key, exp }),
+            _ => Err(Error::Message("auth tag needs action, key and exp".to_string())),
+        }
+    }
+}
+
+/// Logical time of an event at `clock`, see the module doc.
+pub fn clock_time(clock: &Clock) -> u64 {
+    clock.values.values().sum()
+}
+
+// logical time of an encoded clock
+fn decode_time(bytes: &[u8]) -> Result<u64> {
+    let clock = Clock::decode(bytes).map_err(|e| Error::Message(e.to_string()))?;
+    Ok(clock_time(&clock))
+}
+
+/// Logical time of the clock `e` was stored with, none for an event not
+/// accepted yet.
+pub fn event_time<T: Transaction>(db: &ZchronodDb, txn: &T, e: &Event) -> Result<Option<u64>> {
+    match db.read_clock_by_event_id(txn, hex::encode(&e.id))? {
+        Some(bytes) => Ok(Some(decode_time(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Logical time of a stored `e`, or of the next event of the node for an
+/// event not accepted yet.
+pub fn logical_time<T: Transaction>(db: &ZchronodDb, txn: &T, e: &Event) -> Result<u64> {
+    match event_time(db, txn, e)? {
+        Some(time) => Ok(time),
+        None => Ok(node_time(db, txn)? + 1),
+    }
+}
+
+/// Logical time of the node clock, 0 before the first event.
+pub fn node_time<T: Transaction>(db: &ZchronodDb, txn: &T) -> Result<u64> {
+    match db.read_node_clock(txn)? {
+        Some(bytes) => decode_time(&bytes),
+        None => Ok(0),
+    }
+}
+
+fn counter_key(sid: &str, key: u32) -> String {
+    format!("{}_{}", sid, key)
+}
+
+fn key_op_key(sid: &str, key: u32, event_id: &str) -> String {
+    format!("{}_{}_{}", sid, key, event_id)
+}
+
+// whether the op at `a` causally precedes the op at `b`
+fn precedes(a: &BTreeMap<String, u64>, b: &BTreeMap<String, u64>) -> bool {
+    a != b && a.iter().all(|(id, value)| b.get(id).copied().unwrap_or(0) >= *value)
+}
+
+// hex pubkeys and event ids have no '_'
+fn held_key(sid: &str, pubkey: &str, event_id: &str) -> String {
+    format!("{}_{}_{}", sid, pubkey, event_id)
+}
+
+pub struct OpHandler {
+    subspaces: SubspaceHandler,
+    counters: Tree,
+    events: Tree,
+    keys: Tree,
+    held: Tree,
+}
+
+impl OpHandler {
+    pub fn new(db: &ZchronodDb) -> Result<Self> {
+        Ok(OpHandler {
+            subspaces: SubspaceHandler::new(db)?,
+            counters: db.open_tree(COUNTER_TREE_NAME)?,
+            events: db.open_tree(OP_EVENT_TREE_NAME)?,
+            keys: db.open_tree(KEY_OP_TREE_NAME)?,
+            held: db.open_tree(HELD_TREE_NAME)?,
+        })
+    }
+
+    fn read_counter<T: Transaction>(&self, txn: &T, sid: &str, key: u32) -> Result<u64> {
+        match txn.get(&self.counters, counter_key(sid, key))? {
+            Some(v) => std::str::from_utf8(v)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| Error::Message(format!("bad counter of {} {}", sid, key))),
+            None => Ok(0),
+        }
+    }
+
+    /// Counter of every op key a subspace defines, none for an unknown sid.
+    pub fn counters(&self, db: &ZchronodDb, sid: &str) -> Result<Option<BTreeMap<u32, u64>>> {
+        let subspace = match self.subspaces.subspace(db, sid)? {
+            Some(subspace) => subspace,
+            None => return Ok(None),
+        };
+        let reader = db.reader()?;
+        let mut result = BTreeMap::new();
+        for key in subspace.ops.keys() {
+            result.insert(*key, self.read_counter(&reader, sid, *key)?);
+        }
+        Ok(Some(result))
+    }
+
+    /// Accepted operations of a subspace as (event id, counter), by event id.
+    pub fn operations(&self, db: &ZchronodDb, sid: &str) -> Result<Vec<(String, KeyCounter)>> {
+        let reader = db.reader()?;
+        let mut result = vec![];
+        for item in reader.iter(&self.events) {
+            let (k, v) = item?;
+            let counter: KeyCounter = serde_json::from_slice(v).map_err(|e| Error::Message(e.to_string()))?;
+            if counter.sid == sid {
+                result.push((String::from_utf8_lossy(k).into_owned(), counter));
+            }
+        }
+        Ok(result)
+    }
+
+    /// Counter an accepted operation advanced.
+    pub fn event_counter(&self, db: &ZchronodDb, event_id: &str) -> Result<Option<KeyCounter>> {
+        self.read_event_counter(&db.reader()?, event_id)
+    }
+
+    fn read_event_counter<T: Transaction>(&self, txn: &T, event_id: &str) -> Result<Option<KeyCounter>> {
+        match txn.get(&self.events, event_id)? {
+            Some(v) => Ok(Some(serde_json::from_slice(v).map_err(|e| Error::Message(e.to_string()))?)),
+            None => Ok(None),
+        }
+    }
+
+    // accepted operations of a key as (event id, counter)
+    fn read_key_ops<T: Transaction>(&self, txn: &T, sid: &str, key: u32) -> Result<Vec<(String, KeyCounter)>> {
+        let prefix = key_op_key(sid, key, "");
+        let mut result = vec![];
+        for item in txn.iter_from(&self.keys, Bound::Included(prefix.as_bytes()), false) {
+            let (k, v) = item?;
+            if !k.starts_with(prefix.as_bytes()) {
+                break;
+            }
+            let event_id = String::from_utf8_lossy(v).into_owned();
+            let counter = self
+                .read_event_counter(txn, &event_id)?
+                .ok_or_else(|| Error::Message(format!("op {} has no counter", event_id)))?;
+            // a longer sid can share the prefix
+            if counter.sid == sid && counter.key == key {
+                result.push((event_id, counter));
+            }
+        }
+        Ok(result)
+    }
+
+    // count an op at `clock` once and count again the ops of its key that
+    // arrived before it but follow it, the key keeps the highest counter
+    fn advance(&self, writer: &mut Writer, e: &Event, clock: &Clock) -> Result<()> {
+        let event_id = hex::encode(&e.id);
+        if writer.get(&self.events, &event_id)?.is_some() {
+            return Ok(());
+        }
+        let sid = required_tag(e, "sid")?;
+        let mut ops = self.read_key_ops(writer, sid, e.kind)?;
+        writer.put(&self.keys, key_op_key(sid, e.kind, &event_id), &event_id)?;
+        let counter = KeyCounter {
+            sid: sid.to_string(),
+            key: e.kind,
+            counter: 0,
+            clock: clock.values.clone(),
+        };
+        ops.push((event_id.clone(), counter));
+        // an op has a higher logical time than the ops it follows
+        ops.sort_by_key(|(_, op)| op.clock.values().sum::<u64>());
+
+        let mut highest = self.read_counter(writer, sid, e.kind)?;
+        for i in 0..ops.len() {
+            if ops[i].0 != event_id && !precedes(&clock.values, &ops[i].1.clock) {
+                continue;
+            }
+            let counter = 1 + ops[..i]
+                .iter()
+                .filter(|(_, op)| precedes(&op.clock, &ops[i].1.clock))
+                .map(|(_, op)| op.counter)
+                .max()
+                .unwrap_or(0);
+            if counter == ops[i].1.counter {
+                continue;
+            }
+            let (id, op) = &mut ops[i];
+            op.counter = counter;
+            info!("subspace [{}] key {} op [{}] counter {}", op.sid, op.key, id, op.counter);
+            writer.put(&self.events, id.as_str(), json!(op).to_string())?;
+            highest = highest.max(counter);
+        }
+        writer.put(&self.counters, counter_key(sid, e.kind), highest.to_string())?;
+        Ok(())
+    }
+
+    /// Operations in `sid` waiting for their author to join, of `pubkey` if
+    /// given, by author and event id.
+    pub fn held(&self, db: &ZchronodDb, sid: &str, pubkey: Option<&str>) -> Result<Vec<HeldOp>> {
+        self.read_held(&db.reader()?, sid, pubkey)
+    }
+
+    fn read_held<T: Transaction>(&self, txn: &T, sid: &str, pubkey: Option<&str>) -> Result<Vec<HeldOp>> {
+        let prefix = match pubkey {
+            Some(pubkey) => held_key(sid, pubkey, ""),
+            None => format!("{}_", sid),
+        };
+        let mut result = vec![];
+        for item in txn.iter_from(&self.held, Bound::Included(prefix.as_bytes()), false) {
+            let (k, v) = item?;
+            if !k.starts_with(prefix.as_bytes()) {
+                break;
+            }
+            let op: HeldOp = serde_json::from_slice(v).map_err(|e| Error::Message(e.to_string()))?;
+            // a longer sid can share the prefix
+            if required_tag(&op.event, "sid")? == sid
+                && pubkey.is_none_or(|pubkey| hex::encode(&op.event.pubkey) == pubkey)
+            {
+                result.push(op);
+            }
+        }
+        Ok(result)
+    }
+
+    fn hold(&self, writer: &mut Writer, sid: &str, e: &Event, clock: &Clock) -> Result<()> {
+        let pubkey = hex::encode(&e.pubkey);
+        if self.read_held(writer, sid, Some(&pubkey))?.len() >= MAX_HELD_PER_AUTHOR {
+            return Err(Error::Message(format!("too many held ops of {} in subspace {}", pubkey, sid)));
+        }
+        if self.read_held(writer, sid, None)?.len() >= MAX_HELD_PER_SID {
+            return Err(Error::Message(format!("too many held ops in subspace {}", sid)));
+        }
+        info!("hold op [{}] until [{}] joins subspace [{}]", hex::encode(&e.id), pubkey, sid);
+        let op = HeldOp { event: e.clone(), clock: clock.values.clone() };
+        writer.put(&self.held, held_key(sid, &pubkey, &hex::encode(&e.id)), json!(op).to_string())?;
+        Ok(())
+    }
+
+    // apply the held operations that follow a join of their author now
+    fn release(&self, writer: &mut Writer, sid: &str, pubkey: Option<&str>) -> Result<()> {
+        if self.subspaces.read_subspace(writer, sid)?.is_none() {
+            return Ok(());
+        }
+        for op in self.read_held(writer, sid, pubkey)? {
+            let author = hex::encode(&op.event.pubkey);
+            let clock = Clock { values: op.clock, ..Default::default() };
+            let member = match self.subspaces.read_member(writer, sid, &author)? {
+                Some(member) if member.joined_before(&clock) => member,
+                _ => continue,
+            };
+            let event_id = hex::encode(&op.event.id);
+            match self.check(writer, &op.event, &member, clock_time(&clock)) {
+                Ok(()) => self.advance(writer, &op.event, &clock)?,
+                Err(err) => info!("held op [{}] rejected: {}", event_id, err),
+            }
+            writer.del(&self.held, held_key(sid, &author, &event_id), None)?;
+        }
+        Ok(())
+    }
+
+    // `now` is the logical time `e` is judged at, `member` its author
+    fn check<T: Transaction>(&self, txn: &T, e: &Event, member: &Member, now: u64) -> Result<()> {
+        check_define(e, "subspace_op")?;
+        let sid = required_tag(e, "sid")?;
+        let subspace = self
+            .subspaces
+            .read_subspace(txn, sid)?
+            .ok_or_else(|| Error::Message(format!("unknown subspace {}", sid)))?;
+        if !subspace.ops.contains_key(&e.kind) {
+            return Err(Error::Message(format!("subspace {} defines no op of kind {}", sid, e.kind)));
+        }
+        if let Some(op) = tag_value(e, "op") {
+            if subspace.ops.get(&e.kind).map(|name| name.as_str()) != Some(op) {
+                return Err(Error::Message(format!("kind {} is not op {} in subspace {}", e.kind, op, sid)));
+            }
+        }
+
+        let auth = Auth::parse(e)?;
+        if auth.action & ACTION_WRITE == 0 {
+            return Err(Error::Message(format!("action {} does not allow write", auth.action)));
+        }
+        if auth.action & !member.actions != 0 {
+            return Err(Error::Message(format!(
+                "action {} exceeds the actions {} granted in subspace {}",
+                auth.action, member.actions, sid
+            )));
+        }
+        if auth.key != e.kind {
+            return Err(Error::Message(format!("auth key {} does not match kind {}", auth.key, e.kind)));
+        }
+        if auth.exp < now {
+            return Err(Error::Message(format!("auth expired at clock {}, now {}", auth.exp, now)));
+        }
+        Ok(())
+    }
+}
+
+impl KindHandler for OpHandler {
+    fn name(&self) -> &'static str {
+        "op"
+    }
+
+    // a submitted op is checked against the members known now
+    fn validate(&self, db: &ZchronodDb, txn: &Writer, e: &Event) -> Result<()> {
+        check_define(e, "subspace_op")?;
+        let sid = required_tag(e, "sid")?;
+        let pubkey = hex::encode(&e.pubkey);
+        let member = self
+            .subspaces
+            .read_member(txn, sid, &pubkey)?
+            .ok_or_else(|| Error::Message(format!("{} is not a member of subspace {}", pubkey, sid)))?;
+        self.check(txn, e, &member, logical_time(db, txn, e)?)
+    }
+
+    fn apply(&self, _db: &ZchronodDb, txn: &mut Writer, e: &Event, clock: &Clock) -> Result<()> {
+        check_define(e, "subspace_op")?;
+        let sid = required_tag(e, "sid")?;
+        if txn.get(&self.events, hex::encode(&e.id))?.is_some() {
+            return Ok(());
+        }
+        match self.subspaces.read_member(txn, sid, &hex::encode(&e.pubkey))? {
+            Some(member) if member.joined_before(clock) => {
+                self.check(txn, e, &member, clock_time(clock))?;
+                self.advance(txn, e, clock)
+            }
+            _ => {
+                // checked now so a malformed op is not held
+                Auth::parse(e)?;
+                self.hold(txn, sid, e, clock)
+            }
+        }
+    }
+
+    // a creation releases the held operations of the subspace, a join those of its author
+    fn observe(&self, _db: &ZchronodDb, txn: &mut Writer, e: &Event, _clock: &Clock) -> Result<()> {
+        match e.kind {
+            SUBSPACE_CREATE_KIND => self.release(txn, required_tag(e, "sid")?, None),
+            SUBSPACE_JOIN_KIND => self.release(txn, required_tag(e, "sid")?, Some(&hex::encode(&e.pubkey))),
+            _ => Ok(()),
+        }
+    }
+
+    // counters {"sid"}: key -> counter of every op key, null for an unknown sid
+    // event {"event_id"}: `KeyCounter` the event advanced, null if none
+    // attribution {"sid", "weights"}: `Contribution`s, null for an unknown sid
+    fn query(&self, db: &ZchronodDb, method: &str, params: &Value) -> Result<Value> {
+        match method {
+            "counters" => Ok(json!(self.counters(db, str_param(params, "sid")?)?)),
+            "event" => Ok(json!(self.event_counter(db, str_param(params, "event_id")?)?)),
+            "attribution" => {
+                let weights: Weights = match &params["weights"] {
+                    Value::Null => Weights::default(),
+                    weights => serde_json::from_value(weights.clone()).map_err(|e| Error::Message(e.to_string()))?,
+                };
+                Ok(json!(attribute(db, self, str_param(params, "sid")?, &weights)?))
+            }
+            _ => Err(Error::Message(format!("unknown op query {}", method))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{apply, apply_all, tag, temp_db, validate};
+    use proto::zchronod::TagArray;
+    use std::collections::BTreeMap;
+
+    fn event(kind: u32, pubkey: u8, tags: Vec<TagArray>) -> Event {
+        Event {
+            id: vec![kind as u8, pubkey],
+            pubkey: vec![pubkey; 32],
+            kind,
+            tags,
+            ..Default::default()
+        }
+    }
+
+    fn vote(pubkey: u8, auth: &[&str]) -> Event {
+        event(30302, pubkey, vec![tag(auth), tag(&["d", "subspace_op"]), tag(&["sid", "0xMG"]), tag(&["op", "vote"])])
+    }
+
+    fn clock(values: &[(&str, u64)]) -> Clock {
+        Clock {
+            id: "node".to_string(),
+            value: 0,
+            values: values.iter().map(|(k, v)| (k.to_string(), *v)).collect::<BTreeMap<_, _>>(),
+        }
+    }
+
+    fn node_clock(db: &ZchronodDb, values: &[(&str, u64)]) {
+        db.clock_write(clock(values).encode_to_vec(), None).unwrap();
+    }
+
+    // subspace 0xMG with post and vote, joined by 2 when `join`
+    fn governance(db: &ZchronodDb, join: bool) {
+        let subspaces = SubspaceHandler::new(db).unwrap();
+        let create = event(30100, 1, vec![
+            tag(&["d", "subspace_create"]),
+            tag(&["sid", "0xMG"]),
+            tag(&["subspace_name", "governance"]),
+            tag(&["ops", "post=30300,vote=30302"]),
+        ]);
+        apply(&subspaces, db, &create, &Clock::default()).unwrap();
+        if join {
+            let join = event(30200, 2, vec![tag(&["d", "subspace_join"]), tag(&["sid", "0xMG"])]);
+            apply(&subspaces, db, &join, &Clock::default()).unwrap();
+        }
+    }
+    #[test]
+    fn parse_auth() {
+        let auth = Auth::parse(&vote(1, &["auth", "action=2", "key=30302", "exp=10"])).unwrap();
+        assert_eq!(auth, Auth { action: 2, key: 30302, exp: 10 });
+        let auth = Auth::parse(&vote(1, &["auth", "action=2", "dim=3", "exp=10"])).unwrap();
+        assert_eq!(auth.key, 30302);
+        assert!(Auth::parse(&vote(1, &["auth", "action=2", "exp=10"])).is_err());
+        assert!(Auth::parse(&vote(1, &["auth", "action=x", "key=1", "exp=10"])).is_err());
+        assert!(Auth::parse(&event(30302, 1, vec![])).is_err());
+    }
+
+    #[test]
+    fn enforce_auth() {
+        let (_dir, db) = temp_db();
+        let handler = OpHandler::new(&db).unwrap();
+
+        let ok = ["auth", "action=2", "key=30302", "exp=10"];
+        // unknown subspace
+        assert!(validate(&handler, &db, &vote(1, &ok)).is_err());
+
+        governance(&db, true);
+        assert!(validate(&handler, &db, &vote(1, &ok)).is_ok());
+        assert!(apply(&handler, &db, &vote(2, &ok), &clock(&[("a", 1)])).is_ok());
+        // not a member
+        assert!(validate(&handler, &db, &vote(3, &ok)).is_err());
+        // missing write, or more than granted to a joined member
+        assert!(validate(&handler, &db, &vote(2, &["auth", "action=1", "key=30302", "exp=10"])).is_err());
+        assert!(validate(&handler, &db, &vote(2, &["auth", "action=6", "key=30302", "exp=10"])).is_err());
+        assert!(validate(&handler, &db, &vote(1, &["auth", "action=6", "key=30302", "exp=10"])).is_ok());
+        // key of another op, op not defined by the subspace
+        assert!(validate(&handler, &db, &vote(1, &["auth", "action=2", "key=30300", "exp=10"])).is_err());
+        let propose = event(30301, 1, vec![
+            tag(&["auth", "action=2", "key=30301", "exp=10"]),
+            tag(&["d", "subspace_op"]),
+            tag(&["sid", "0xMG"]),
+        ]);
+        assert!(validate(&handler, &db, &propose).is_err());
+
+        // expiry is measured in logical time
+        node_clock(&db, &[("a", 5), ("b", 4)]);
+        assert!(validate(&handler, &db, &vote(1, &ok)).is_ok());
+        node_clock(&db, &[("a", 5), ("b", 5)]);
+        assert!(validate(&handler, &db, &vote(1, &ok)).is_err());
+        // an applied event is judged by its own clock
+        node_clock(&db, &[("a", 9), ("b", 9)]);
+        assert!(apply(&handler, &db, &vote(1, &ok), &clock(&[("a", 5), ("b", 4)])).is_ok());
+        let mut late = vote(2, &ok);
+        late.id = vec![9];
+        assert!(apply(&handler, &db, &late, &clock(&[("a", 5), ("b", 6)])).is_err());
+        assert!(validate(&handler, &db, &vote(2, &ok)).is_err());
+    }
+
+    #[test]
+    fn key_counters() {
+        let (_dir, db) = temp_db();
+        let handler = OpHandler::new(&db).unwrap();
+        governance(&db, false);
+        assert_eq!(handler.query(&db, "counters", &json!({"sid": "none"})).unwrap(), Value::Null);
+        assert_eq!(handler.query(&db, "counters", &json!({"sid": "0xMG"})).unwrap(), json!({"30300": 0, "30302": 0}));
+
+        let ok = ["auth", "action=2", "key=30302", "exp=10"];
+        let first = vote(1, &ok);
+        apply(&handler, &db, &first, &clock(&[("a", 1)])).unwrap();
+        // applying an event again does not change its counter
+        apply(&handler, &db, &first, &clock(&[("a", 3)])).unwrap();
+        let mut second = vote(1, &ok);
+        second.id = vec![9];
+        apply(&handler, &db, &second, &clock(&[("a", 1), ("b", 1)])).unwrap();
+        // rejected operations do not count, nor those of authors that did not join
+        let mut read_only = vote(1, &["auth", "action=1", "key=30302", "exp=10"]);
+        read_only.id = vec![8];
+        assert!(apply(&handler, &db, &read_only, &clock(&[("a", 3)])).is_err());
+        apply(&handler, &db, &vote(3, &ok), &clock(&[("a", 3)])).unwrap();
+        assert_eq!(handler.held(&db, "0xMG", Some(&hex::encode([3u8; 32]))).unwrap().len(), 1);
+
+        assert_eq!(handler.counters(&db, "0xMG").unwrap().unwrap(), [(30300, 0), (30302, 2)].into());
+        let counter = handler.event_counter(&db, &hex::encode(&first.id)).unwrap().unwrap();
+        assert_eq!(counter.counter, 1);
+        let counter = handler.event_counter(&db, &hex::encode(&second.id)).unwrap().unwrap();
+        assert_eq!(counter, KeyCounter {
+            sid: "0xMG".to_string(),
+            key: 30302,
+            counter: 2,
+            clock: clock(&[("a", 1), ("b", 1)]).values,
+        });
+        assert_eq!(handler.query(&db, "event", &json!({"event_id": "ff"})).unwrap(), Value::Null);
+
+        // counters are persisted
+        let reopened = OpHandler::new(&db).unwrap();
+        assert_eq!(reopened.counters(&db, "0xMG").unwrap().unwrap()[&30302], 2);
+    }
+
+    #[test]
+    fn counters_ignore_arrival_order() {
+        let ok = ["auth", "action=2", "key=30302", "exp=10"];
+        // concurrent votes of two nodes, and one that follows both
+        let first = vote(1, &ok);
+        let mut second = vote(1, &ok);
+        second.id = vec![9];
+        let mut third = vote(1, &ok);
+        third.id = vec![8];
+        let at = [clock(&[("a", 2)]), clock(&[("b", 1)]), clock(&[("a", 2), ("b", 3)])];
+
+        let counters = |order: [usize; 3]| {
+            let (_dir, db) = temp_db();
+            let handler = OpHandler::new(&db).unwrap();
+            governance(&db, false);
+            let events = [&first, &second, &third];
+            for i in order {
+                apply(&handler, &db, events[i], &at[i]).unwrap();
+            }
+            let counters: Vec<u64> = events
+                .iter()
+                .map(|e| handler.event_counter(&db, &hex::encode(&e.id)).unwrap().unwrap().counter)
+                .collect();
+            (counters, handler.counters(&db, "0xMG").unwrap().unwrap()[&30302])
+        };
+
+        assert_eq!(counters([0, 1, 2]), (vec![1, 1, 2], 2));
+        assert_eq!(counters([2, 1, 0]), (vec![1, 1, 2], 2));
+        assert_eq!(counters([1, 2, 0]), (vec![1, 1, 2], 2));
+    }
+
+    #[test]
+    fn unrelated_events_keep_counters() {
+        let (_dir, db) = temp_db();
+        let handler = OpHandler::new(&db).unwrap();
+        governance(&db, false);
+
+        let ok = ["auth", "action=2", "key=30302", "exp=100"];
+        let first = vote(1, &ok);
+        apply(&handler, &db, &first, &clock(&[("a", 1)])).unwrap();
+        // an op of another key in between
+        let post = event(30300, 1, vec![
+            tag(&["auth", "action=2", "key=30300", "exp=100"]),
+            tag(&["d", "subspace_op"]),
+            tag(&["sid", "0xMG"]),
+        ]);
+        apply(&handler, &db, &post, &clock(&[("a", 2)])).unwrap();
+        // after many events of other nodes
+        let mut second = vote(1, &ok);
+        second.id = vec![9];
+        apply(&handler, &db, &second, &clock(&[("a", 40), ("b", 30)])).unwrap();
+
+        assert_eq!(handler.event_counter(&db, &hex::encode(&second.id)).unwrap().unwrap().counter, 2);
+        assert_eq!(handler.counters(&db, "0xMG").unwrap().unwrap(), [(30300, 1), (30302, 2)].into());
+    }
+
+    #[test]
+    fn ops_wait_for_join() {
+        let ok = ["auth", "action=2", "key=30302", "exp=10"];
+        let create = event(30100, 1, vec![
+            tag(&["d", "subspace_create"]),
+            tag(&["sid", "0xMG"]),
+            tag(&["subspace_name", "governance"]),
+            tag(&["ops", "post=30300,vote=30302"]),
+        ]);
+        let join = event(30200, 2, vec![tag(&["d", "subspace_join"]), tag(&["sid", "0xMG"])]);
+        let after = vote(2, &ok);
+        // concurrent with the join
+        let mut concurrent = vote(2, &ok);
+        concurrent.id = vec![9];
+        let events = [
+            (&create, clock(&[("a", 1)])),
+            (&join, clock(&[("a", 1), ("b", 1)])),
+            (&after, clock(&[("a", 1), ("b", 2)])),
+            (&concurrent, clock(&[("a", 2)])),
+        ];
+
+        let run = |order: [usize; 4]| {
+            let (_dir, db) = temp_db();
+            let registry = crate::handler::KindRegistry::with_default_handlers(&db).unwrap();
+            for i in order {
+                apply_all(&registry, &db, events[i].0, &events[i].1).unwrap();
+            }
+            let handler = OpHandler::new(&db).unwrap();
+            let counted: Vec<bool> = [&after, &concurrent]
+                .iter()
+                .map(|e| handler.event_counter(&db, &hex::encode(&e.id)).unwrap().is_some())
+                .collect();
+            let held = handler.held(&db, "0xMG", None).unwrap().len();
+            (counted, held, handler.counters(&db, "0xMG").unwrap().unwrap()[&30302])
+        };
+
+        // only the op after the join counts, whatever arrives first
+        let expected = (vec![true, false], 1, 1);
+        assert_eq!(run([0, 1, 2, 3]), expected);
+        assert_eq!(run([2, 3, 1, 0]), expected);
+        assert_eq!(run([3, 2, 0, 1]), expected);
+    }
+
+    #[test]
+    fn held_ops_are_capped() {
+        let (_dir, db) = temp_db();
+        let handler = OpHandler::new(&db).unwrap();
+        let ok = ["auth", "action=2", "key=30302", "exp=10"];
+        let op = |pubkey: u8, n: usize| {
+            let mut e = vote(pubkey, &ok);
+            e.id = vec![pubkey, n as u8];
+            e
+        };
+
+        // authors that never join
+        for n in 0..MAX_HELD_PER_AUTHOR {
+            apply(&handler, &db, &op(3, n), &clock(&[("a", 1)])).unwrap();
+        }
+        let err = apply(&handler, &db, &op(3, MAX_HELD_PER_AUTHOR), &clock(&[("a", 1)])).unwrap_err();
+        assert!(err.to_string().contains("too many held ops of"));
+        let authors = MAX_HELD_PER_SID / MAX_HELD_PER_AUTHOR;
+        for pubkey in 4..(3 + authors) as u8 {
+            for n in 0..MAX_HELD_PER_AUTHOR {
+                apply(&handler, &db, &op(pubkey, n), &clock(&[("a", 1)])).unwrap();
+            }
+        }
+        let err = apply(&handler, &db, &op(200, 0), &clock(&[("a", 1)])).unwrap_err();
+        assert!(err.to_string().contains("too many held ops in subspace"));
+        assert_eq!(handler.held(&db, "0xMG", None).unwrap().len(), MAX_HELD_PER_SID);
+        assert!(handler.held(&db, "0xMG", Some(&hex::encode([200u8; 32]))).unwrap().is_empty());
+
+        // a release makes room again
+        governance(&db, false);
+        let registry = crate::handler::KindRegistry::with_default_handlers(&db).unwrap();
+        let join = event(30200, 3, vec![tag(&["d", "subspace_join"]), tag(&["sid", "0xMG"])]);
+        apply_all(&registry, &db, &join, &clock(&[("a", 1)])).unwrap();
+        assert_eq!(handler.held(&db, "0xMG", None).unwrap().len(), MAX_HELD_PER_SID - MAX_HELD_PER_AUTHOR);
+        apply(&handler, &db, &op(200, 0), &clock(&[("a", 1)])).unwrap();
+    }
+}
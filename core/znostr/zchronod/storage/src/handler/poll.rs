@@ -0,0 +1,1053 @@
+//! NIP-3041 polls (kind 301) and votes (kind 309).
+//!
+//! k: 3041_event-id_state, v: `OptionState` json, the vote count of every option
+//! k: 3041_event-id_voter_pubkey, v: `Voter` json, the counted vote of a pubkey
+//!
+//! Polls are listed newest first from two indexes of `PollEntry` json:
+//! tree poll_list, k: created_at_event-id
+//! tree poll_author, k: pubkey_created_at_event-id
+//! created_at is 16 hex digits with the sign bit flipped, so keys sort by time.
+//! The former poll_id key, a json list of [event id, title, info] of every
+//! poll, is moved into the indexes when the handler opens.
+//!
+//! Votes count between the poll `start` and `end` timestamps and while the
+//! logical time of their VLC is at most the poll `clock`. Every pubkey has one
+//! vote per poll: a newer vote replaces the counted one, on equal `created_at`
+//! the lower id wins, any other vote is rejected. `end` is compared with the
+//! `created_at` of the vote only, never with the time it arrives, so every
+//! node counts the same votes.
+//!
+//! Events are read through `PollSpec` and `VoteSpec`, a malformed event is
+//! rejected with a `PollError`.
+
+use std::fmt;
+use std::ops::Bound;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::info;
+use nostr_kv::{Error, lmdb::{Transaction, Tree, Writer}};
+use proto::zchronod::{Clock, Event};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::cache::Cache;
+use crate::handler::op::{clock_time, logical_time, node_time};
+use crate::handler::{str_param, KindHandler};
+use crate::{Result, ZchronodDb, TREE_NAME};
+
+pub const POLL_KIND: u32 = 301;
+pub const VOTE_KIND: u32 = 309;
+/// logical time limit of polls with a `clock` of 0
+pub const MAX_POLL_CLOCK: u64 = 2_000_000;
+/// polls of a list page without a limit
+pub const DEFAULT_POLL_PAGE: usize = 100;
+pub const MAX_POLL_PAGE: usize = 500;
+
+const LIST_TREE_NAME: &str = "poll_list";
+const AUTHOR_TREE_NAME: &str = "poll_author";
+// key of the poll list before the indexes
+const LEGACY_LIST_KEY: &str = "poll_id";
+
+/// Why a poll or vote event is rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PollError {
+    /// the event has no tag of this name
+    MissingTag(&'static str),
+    /// the event has more than one tag of this name
+    DuplicateTag(&'static str),
+    /// the poll tag ends before this field
+    MissingField(&'static str),
+    /// a field of the poll tag does not parse
+    InvalidField(&'static str, String),
+    NoPollOptions,
+    EndBeforeStart,
+    /// the e tag of a vote is not a hex event id
+    InvalidPollId(String),
+    InvalidOption(String),
+    NoOption,
+    DuplicateOption(usize),
+    OptionOutOfRange(usize),
+    SingleChoice(usize),
+    UnknownPoll,
+    NotAPoll,
+    NotStarted,
+    Ended,
+    ClockLimit,
+    /// the pubkey has a counted vote, newer or equal to this one
+    AlreadyVoted(String),
+}
+
+impl fmt::Display for PollError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PollError::MissingTag(tag) => write!(f, "missing {} tag", tag),
+            PollError::DuplicateTag(tag) => write!(f, "more than one {} tag", tag),
+            PollError::MissingField(field) => write!(f, "poll tag has no {}", field),
+            PollError::InvalidField(field, value) => write!(f, "invalid poll {} {:?}", field, value),
+            PollError::NoPollOptions => write!(f, "poll has no option"),
+            PollError::EndBeforeStart => write!(f, "poll ends before it starts"),
+            PollError::InvalidPollId(id) => write!(f, "invalid poll event id {:?}", id),
+            PollError::InvalidOption(option) => write!(f, "invalid vote option {:?}", option),
+            PollError::NoOption => write!(f, "vote has no option"),
+            PollError::DuplicateOption(option) => write!(f, "vote option {} chosen twice", option),
+            PollError::OptionOutOfRange(option) => write!(f, "vote option {} out of range", option),
+            PollError::SingleChoice(len) => write!(f, "single option vote len should be 1, got {}", len),
+            PollError::UnknownPoll => write!(f, "poll event id not found"),
+            PollError::NotAPoll => write!(f, "voted event is not a poll"),
+            PollError::NotStarted => write!(f, "poll not started"),
+            PollError::Ended => write!(f, "poll ended"),
+            PollError::ClockLimit => write!(f, "poll reached its clock limit"),
+            PollError::AlreadyVoted(id) => write!(f, "pubkey already voted with {}", id),
+        }
+    }
+}
+
+impl std::error::Error for PollError {}
+
+impl From<PollError> for Error {
+    fn from(err: PollError) -> Self {
+        Error::Message(err.to_string())
+    }
+}
+
+// the only tag of this name, none if there is no such tag
+fn single_tag<'a>(e: &'a Event, name: &'static str) -> Result<Option<&'a [String]>, PollError> {
+    let mut tags = e
+        .tags
+        .iter()
+        .map(|tag| tag.values.as_slice())
+        .filter(|values| values.first().map(String::as_str) == Some(name));
+    let tag = tags.next();
+    if tags.next().is_some() {
+        return Err(PollError::DuplicateTag(name));
+    }
+    Ok(tag)
+}
+
+/// A poll, `["poll", <multi|single>, <clock>, <start>, <end>, <title>, <info>, <option>..]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PollSpec {
+    pub multi: bool,
+    /// logical time limit, a vote counts while the logical time of its VLC is at most this
+    pub clock: u64,
+    /// unix timestamps, none if empty
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    pub title: String,
+    pub info: String,
+    pub options: Vec<String>,
+}
+
+impl PollSpec {
+    pub fn parse(e: &Event) -> Result<Self, PollError> {
+        let tag = single_tag(e, "poll")?.ok_or(PollError::MissingTag("poll"))?;
+        let field = |i: usize, name: &'static str| tag.get(i).ok_or(PollError::MissingField(name));
+        let multi = match field(1, "choice")?.as_str() {
+            "multi" => true,
+            "single" => false,
+            other => return Err(PollError::InvalidField("choice", other.to_string())),
+        };
+        let clock = match field(2, "clock")?.as_str() {
+            "" | "0" => MAX_POLL_CLOCK,
+            clock => clock
+                .parse()
+                .map_err(|_| PollError::InvalidField("clock", clock.to_string()))?,
+        };
+        let start = timestamp("start", field(3, "start")?)?;
+        let end = timestamp("end", field(4, "end")?)?;
+        if let (Some(start), Some(end)) = (start, end) {
+            if end < start {
+                return Err(PollError::EndBeforeStart);
+            }
+        }
+        let title = field(5, "title")?.clone();
+        let info = field(6, "info")?.clone();
+        let options = tag.get(7..).unwrap_or_default().to_vec();
+        if options.is_empty() {
+            return Err(PollError::NoPollOptions);
+        }
+        Ok(PollSpec {
+            multi,
+            clock,
+            start,
+            end,
+            title,
+            info,
+            options,
+        })
+    }
+}
+
+// unix seconds, or `YYYY-MM-DDTHH:MM[:SS]` in UTC as datetime-local inputs send it
+fn timestamp(field: &'static str, value: &str) -> Result<Option<i64>, PollError> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    if let Ok(secs) = value.parse() {
+        return Ok(Some(secs));
+    }
+    datetime(value)
+        .map(Some)
+        .ok_or_else(|| PollError::InvalidField(field, value.to_string()))
+}
+
+fn datetime(value: &str) -> Option<i64> {
+    let (date, time) = value.split_once('T')?;
+    let number = |s: &str, max: i64| s.parse::<i64>().ok().filter(|n| (0..=max).contains(n));
+    let mut date = date.split('-');
+    let (y, m, d) = (number(date.next()?, 9999)?, number(date.next()?, 12)?, number(date.next()?, 31)?);
+    let mut time = time.split(':');
+    let (h, min) = (number(time.next()?, 23)?, number(time.next()?, 59)?);
+    let s = match time.next() {
+        Some(s) => number(s, 59)?,
+        None => 0,
+    };
+    if date.next().is_some() || time.next().is_some() || m == 0 || d == 0 {
+        return None;
+    }
+    // days from civil, http://howardhinnant.github.io/date_algorithms.html
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    Some(days * 86400 + h * 3600 + min * 60 + s)
+}
+
+/// A vote, `["e", <poll id>]` and `["poll_r", <option index>..]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoteSpec {
+    /// lowercase hex event id of the poll
+    pub poll_id: String,
+    /// indexes of the chosen options, the first option is 0
+    pub options: Vec<usize>,
+}
+
+impl VoteSpec {
+    pub fn parse(e: &Event) -> Result<Self, PollError> {
+        // the first e tag is the poll, others may reference anything
+        let poll_id = e
+            .tags
+            .iter()
+            .find(|tag| tag.values.first().map(String::as_str) == Some("e"))
+            .ok_or(PollError::MissingTag("e"))?
+            .values
+            .get(1)
+            .ok_or_else(|| PollError::InvalidPollId(String::new()))?;
+        if poll_id.len() != 64 || !poll_id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(PollError::InvalidPollId(poll_id.clone()));
+        }
+        let tag = single_tag(e, "poll_r")?.ok_or(PollError::MissingTag("poll_r"))?;
+        let mut options = vec![];
+        for option in &tag[1..] {
+            let index: usize = option
+                .parse()
+                .map_err(|_| PollError::InvalidOption(option.clone()))?;
+            if options.contains(&index) {
+                return Err(PollError::DuplicateOption(index));
+            }
+            options.push(index);
+        }
+        if options.is_empty() {
+            return Err(PollError::NoOption);
+        }
+        Ok(VoteSpec {
+            poll_id: poll_id.to_ascii_lowercase(),
+            options,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OptionState {
+    // map: HashMap<String, i32>,
+    option_vec: Vec<(String, i32)>,
+    // option_name : vote_num
+    event: Event,
+}
+
+/// The counted vote of a pubkey
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Voter {
+    /// hex id of the vote event
+    pub id: String,
+    pub created_at: i64,
+    /// indexes of the chosen options
+    pub options: Vec<usize>,
+}
+
+/// A poll in the list indexes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PollEntry {
+    /// hex event id
+    pub id: String,
+    /// hex pubkey of the author
+    pub pubkey: String,
+    pub title: String,
+    pub info: String,
+    pub created_at: i64,
+    pub end: Option<i64>,
+    pub clock: u64,
+}
+
+impl PollEntry {
+    fn new(e: &Event, spec: &PollSpec) -> Self {
+        PollEntry {
+            id: hex::encode(&e.id),
+            pubkey: hex::encode(&e.pubkey),
+            title: spec.title.clone(),
+            info: spec.info.clone(),
+            created_at: e.created_at,
+            end: spec.end,
+            clock: spec.clock,
+        }
+    }
+
+    fn list_key(&self) -> String {
+        format!("{:016x}_{}", (self.created_at as u64) ^ (1 << 63), self.id)
+    }
+
+    fn author_key(&self) -> String {
+        format!("{}_{}", self.pubkey, self.list_key())
+    }
+
+    // ended by the node system time or the node clock passed the clock limit
+    fn closed(&self, now: i64, time: u64) -> bool {
+        self.end.is_some_and(|end| now > end) || time >= self.clock
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PollStatus {
+    /// still counts votes
+    Open,
+    /// ended or reached its clock limit
+    Closed,
+}
+
+/// Params of the list query
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct PollListQuery {
+    /// cursor of the previous page, none for the newest polls
+    pub cursor: Option<String>,
+    /// `DEFAULT_POLL_PAGE` if none, at most `MAX_POLL_PAGE`
+    pub limit: Option<usize>,
+    /// hex pubkey
+    pub author: Option<String>,
+    pub status: Option<PollStatus>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PollListItem {
+    #[serde(flatten)]
+    pub poll: PollEntry,
+    pub closed: bool,
+}
+
+/// A page of polls, newest first
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PollPage {
+    pub polls: Vec<PollListItem>,
+    /// cursor of the next page, none on the last page
+    pub cursor: Option<String>,
+}
+
+/// A vote that passed the poll rules
+struct Ballot {
+    state_key: String,
+    state: OptionState,
+    voter_key: String,
+    voter: Voter,
+    /// the vote of the pubkey this one replaces
+    replaces: Option<Voter>,
+}
+
+fn voter_key(poll_id: &str, pubkey: &str) -> String {
+    format!("3041_{}_voter_{}", poll_id, pubkey)
+}
+
+fn decode<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Result<T, Error> {
+    serde_json::from_slice(bytes).map_err(|e| Error::Message(e.to_string()))
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64
+}
+
+pub struct PollHandler {
+    tree: Tree,
+    list: Tree,
+    authors: Tree,
+    cache: Mutex<Cache>,
+}
+
+impl PollHandler {
+    pub fn new(db: &ZchronodDb) -> Result<Self> {
+        let handler = PollHandler {
+            tree: db.open_tree(TREE_NAME)?,
+            list: db.open_tree(LIST_TREE_NAME)?,
+            authors: db.open_tree(AUTHOR_TREE_NAME)?,
+            cache: Mutex::new(Cache::default()),
+        };
+        handler.migrate(db)?;
+        *handler.cache.lock().unwrap() = Cache::new(&db.inner, &handler.list);
+        Ok(handler)
+    }
+
+    // move the poll_id list into the indexes, polls keep the title and info they were listed with
+    fn migrate(&self, db: &ZchronodDb) -> Result<(), Error> {
+        let mut writer = db.inner.writer()?;
+        let legacy: Vec<Vec<String>> = match writer.get(&self.tree, LEGACY_LIST_KEY)? {
+            Some(t) => decode(t)?,
+            None => return Ok(()),
+        };
+        for item in &legacy {
+            let Some(id) = item.first() else {
+                continue;
+            };
+            let state: Option<OptionState> = match writer.get(&self.tree, format!("3041_{}_state", id))? {
+                Some(t) => decode(t).ok(),
+                None => None,
+            };
+            let Some(entry) = state.and_then(|state| {
+                let spec = PollSpec::parse(&state.event).ok()?;
+                Some(PollEntry {
+                    title: item.get(1).cloned().unwrap_or_default(),
+                    info: item.get(2).cloned().unwrap_or_default(),
+                    ..PollEntry::new(&state.event, &spec)
+                })
+            }) else {
+                info!("skip poll {} without a valid state", id);
+                continue;
+            };
+            let value = json!(entry).to_string();
+            writer.put(&self.list, entry.list_key(), &value)?;
+            writer.put(&self.authors, entry.author_key(), &value)?;
+        }
+        writer.del(&self.tree, LEGACY_LIST_KEY, None)?;
+        writer.commit()?;
+        info!("indexed {} polls of the poll_id list", legacy.len());
+        Ok(())
+    }
+
+    // key is 3041_event-id_state
+    pub fn poll_event_key(e: &Event) -> String {
+        format!("3041_{}_state", hex::encode(&e.id))
+    }
+
+    fn poll_write(&self, writer: &mut Writer, key: String, e: Event) -> Result<(), Error> {
+        let spec = PollSpec::parse(&e)?;
+        if writer.get(&self.tree, key.clone())?.is_some() {
+            info!("poll write key which is {:?} has saved", key);
+            return Ok(());
+        }
+        let entry = PollEntry::new(&e, &spec);
+        let value = json!(entry).to_string();
+        let o_s = OptionState {
+            option_vec: spec.options.into_iter().map(|option| (option, 0)).collect(),
+            event: e,
+        };
+
+        writer.put(&self.tree, key.clone(), json!(o_s).to_string())?;
+        writer.put(&self.list, entry.list_key(), &value)?;
+        writer.put(&self.authors, entry.author_key(), &value)?;
+        // a key of a transaction that is not committed is a false positive of the filter
+        self.cache.lock().unwrap().set_poll_event(key);
+        Ok(())
+    }
+
+    // the vote as it would be counted at logical `time`, an error tells why it is rejected
+    fn check_vote<T: Transaction>(&self, txn: &T, e: &Event, time: u64) -> Result<Ballot, Error> {
+        let vote = VoteSpec::parse(e)?;
+        let poll_event: Event = match txn.get(&self.tree, vote.poll_id.clone())? {
+            Some(t) => decode(t)?,
+            None => return Err(PollError::UnknownPoll.into()),
+        };
+        if poll_event.kind != POLL_KIND {
+            return Err(PollError::NotAPoll.into());
+        }
+        let poll = PollSpec::parse(&poll_event)?;
+
+        if poll.start.is_some_and(|start| e.created_at < start) {
+            return Err(PollError::NotStarted.into());
+        }
+        if poll.end.is_some_and(|end| e.created_at > end) {
+            return Err(PollError::Ended.into());
+        }
+        if time > poll.clock {
+            return Err(PollError::ClockLimit.into());
+        }
+        if !poll.multi && vote.options.len() != 1 {
+            return Err(PollError::SingleChoice(vote.options.len()).into());
+        }
+        if let Some(option) = vote.options.iter().find(|option| **option >= poll.options.len()) {
+            return Err(PollError::OptionOutOfRange(*option).into());
+        }
+
+        let state_key = format!("3041_{}_state", vote.poll_id);
+        let state: OptionState = match txn.get(&self.tree, state_key.clone())? {
+            Some(t) => decode(t)?,
+            None => return Err(PollError::UnknownPoll.into()),
+        };
+
+        let voter_key = voter_key(&vote.poll_id, &hex::encode(&e.pubkey));
+        let voter = Voter {
+            id: hex::encode(&e.id),
+            created_at: e.created_at,
+            options: vote.options,
+        };
+        let replaces = match txn.get(&self.tree, voter_key.clone())? {
+            Some(t) => {
+                let counted: Voter = decode(t)?;
+                let newer = voter.created_at > counted.created_at
+                    || (voter.created_at == counted.created_at && voter.id < counted.id);
+                if !newer {
+                    return Err(PollError::AlreadyVoted(counted.id).into());
+                }
+                Some(counted)
+            }
+            None => None,
+        };
+        Ok(Ballot {
+            state_key,
+            state,
+            voter_key,
+            voter,
+            replaces,
+        })
+    }
+
+    fn vote_write(&self, writer: &mut Writer, e: Event, time: u64) -> Result<(), Error> {
+        let Ballot {
+            state_key,
+            mut state,
+            voter_key,
+            voter,
+            replaces,
+        } = self.check_vote(writer, &e, time)?;
+        if let Some(replaced) = &replaces {
+            info!("vote {} replaces {}", voter.id, replaced.id);
+            for option in &replaced.options {
+                if let Some(tuple) = state.option_vec.get_mut(*option) {
+                    tuple.1 -= 1;
+                }
+            }
+        }
+        for option in &voter.options {
+            if let Some(tuple) = state.option_vec.get_mut(*option) {
+                tuple.1 += 1;
+            }
+        }
+
+        writer.put(&self.tree, state_key, json!(state).to_string())?;
+        writer.put(&self.tree, voter_key, json!(voter).to_string())?;
+        Ok(())
+    }
+
+    /// The counted vote of `pubkey` on a poll
+    pub fn query_voter(&self, db: &ZchronodDb, event_id: &str, pubkey: &str) -> Result<Option<Voter>, Error> {
+        let reader = db.inner.reader()?;
+        match reader.get(&self.tree, voter_key(event_id, pubkey))? {
+            Some(t) => Ok(Some(decode(t)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// A poll with its parsed limits, none if `event_id` is not a stored poll
+    pub fn query_poll(&self, db: &ZchronodDb, event_id: &str) -> Result<Option<PollListItem>, Error> {
+        let reader = db.inner.reader()?;
+        let e: Event = match reader.get(&self.tree, event_id.to_ascii_lowercase())? {
+            Some(t) => decode(t)?,
+            None => return Ok(None),
+        };
+        if e.kind != POLL_KIND {
+            return Ok(None);
+        }
+        let poll = PollEntry::new(&e, &PollSpec::parse(&e)?);
+        let closed = poll.closed(now(), node_time(db, &reader)?);
+        Ok(Some(PollListItem { poll, closed }))
+    }
+
+    pub fn query_poll_event_state(&self, db: &ZchronodDb, event_id: String) -> Result<Vec<(String, i32)>, Error> {
+        let key = format!("3041_{}_state", event_id);
+
+        // bloom query
+        if !self.cache.lock().unwrap().validate_poll_event(key.clone()) {
+            info!("query_poll_event_state via bloom filter is none which id is [{:?}]", event_id);
+            return Ok(vec![]);
+        }
+        let reader = db.inner.reader()?;
+        match reader.get(&self.tree, key)? {
+            Some(t) => Ok(decode::<OptionState>(t)?.option_vec),
+            None => {
+                info!("query_poll_event_state is none which id is [{:?}]", event_id);
+                Ok(vec![])
+            }
+        }
+    }
+
+    /// A page of polls, newest first
+    pub fn query_polls(&self, db: &ZchronodDb, query: &PollListQuery) -> Result<PollPage, Error> {
+        let limit = query.limit.unwrap_or(DEFAULT_POLL_PAGE).clamp(1, MAX_POLL_PAGE);
+        let (tree, prefix) = match &query.author {
+            Some(author) => (&self.authors, format!("{}_", author.to_ascii_lowercase())),
+            None => (&self.list, String::new()),
+        };
+        let from = match &query.cursor {
+            Some(cursor) => Bound::Excluded(format!("{}{}", prefix, cursor)),
+            // '~' sorts after the keys of the author
+            None if query.author.is_some() => Bound::Excluded(format!("{}~", prefix)),
+            None => Bound::Unbounded,
+        };
+        let now = now();
+        let reader = db.inner.reader()?;
+        let time = node_time(db, &reader)?;
+        let mut polls = vec![];
+        let mut more = false;
+        for item in reader.iter_from(tree, from, true) {
+            let (k, v) = item?;
+            if !k.starts_with(prefix.as_bytes()) {
+                break;
+            }
+            let poll: PollEntry = decode(v)?;
+            let closed = poll.closed(now, time);
+            if query.status.is_some_and(|status| (status == PollStatus::Closed) != closed) {
+                continue;
+            }
+            if polls.len() == limit {
+                more = true;
+                break;
+            }
+            polls.push(PollListItem { poll, closed });
+        }
+        // the next page starts after the last poll of this one
+        let cursor = polls.last().filter(|_| more).map(|item| item.poll.list_key());
+        Ok(PollPage { polls, cursor })
+    }
+}
+
+impl KindHandler for PollHandler {
+    fn name(&self) -> &'static str {
+        "poll"
+    }
+
+    fn validate(&self, db: &ZchronodDb, txn: &Writer, e: &Event) -> Result<()> {
+        match e.kind {
+            POLL_KIND => {
+                PollSpec::parse(e)?;
+            }
+            VOTE_KIND => {
+                self.check_vote(txn, e, logical_time(db, txn, e)?)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn apply(&self, _db: &ZchronodDb, txn: &mut Writer, e: &Event, clock: &Clock) -> Result<()> {
+        match e.kind {
+            POLL_KIND => {
+                info!("receive kind 301 poll");
+                self.poll_write(txn, Self::poll_event_key(e), e.clone())
+            }
+            VOTE_KIND => {
+                info!("receive kind 309 vote");
+                self.vote_write(txn, e.clone(), clock_time(clock))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // list `PollListQuery`: `PollPage`
+    // poll {"event_id"}: `PollListItem` of the poll, null if it is not a poll
+    // state {"event_id"}: [option, votes] of a poll
+    // voter {"event_id", "pubkey"}: `Voter` of the pubkey, null if it did not vote
+    fn query(&self, db: &ZchronodDb, method: &str, params: &Value) -> Result<Value> {
+        match method {
+            "list" => {
+                let query: PollListQuery = if params.is_null() {
+                    PollListQuery::default()
+                } else {
+                    serde_json::from_value(params.clone()).map_err(|e| Error::Message(format!("invalid poll list query: {}", e)))?
+                };
+                Ok(json!(self.query_polls(db, &query)?))
+            }
+            "poll" => Ok(json!(self.query_poll(db, str_param(params, "event_id")?)?)),
+            "state" => Ok(json!(self.query_poll_event_state(db, str_param(params, "event_id")?.to_string())?)),
+            "voter" => Ok(json!(self.query_voter(db, str_param(params, "event_id")?, str_param(params, "pubkey")?)?)),
+            _ => Err(Error::Message(format!("unknown poll query {}", method))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{apply, tag, temp_db, validate};
+    use prost::Message;
+    use proto::zchronod::TagArray;
+
+    #[test]
+    fn poll_and_vote() {
+        let (_dir, db) = temp_db();
+        let handler = PollHandler::new(&db).unwrap();
+
+        let poll = Event {
+            id: vec![1; 32],
+            kind: POLL_KIND,
+            tags: vec![tag(&["poll", "single", "0", "1707294126", "1707294126", "title", "info", "a", "b"])],
+            ..Default::default()
+        };
+        apply(&handler, &db, &poll, &Clock::default()).unwrap();
+        db.event_write(poll.clone()).unwrap();
+        let poll_id = hex::encode(&poll.id);
+
+        let vote = Event {
+            id: vec![2; 32],
+            kind: VOTE_KIND,
+            created_at: 1707294126,
+            tags: vec![tag(&["e", &poll_id]), tag(&["poll_r", "1"])],
+            ..Default::default()
+        };
+        apply(&handler, &db, &vote, &Clock::default()).unwrap();
+
+        let list = handler.query(&db, "list", &Value::Null).unwrap();
+        assert_eq!(list["polls"][0]["id"], json!(poll_id));
+        assert_eq!(list["polls"][0]["title"], json!("title"));
+        assert_eq!(list["cursor"], Value::Null);
+        let state = handler.query(&db, "state", &json!({ "event_id": poll_id })).unwrap();
+        assert_eq!(state, json!([["a", 0], ["b", 1]]));
+        assert!(handler.query(&db, "state", &Value::Null).is_err());
+
+        // a second handler on the same db sees the same state
+        let reopened = PollHandler::new(&db).unwrap();
+        assert_eq!(reopened.query(&db, "state", &json!({ "event_id": poll_id })).unwrap(), state);
+    }
+
+    // a clock at logical `time`
+    fn at(time: u64) -> Clock {
+        Clock {
+            values: [("node".to_string(), time)].into(),
+            ..Default::default()
+        }
+    }
+
+    fn vote(id: u8, pubkey: u8, created_at: i64, poll_id: &str, options: &[&str]) -> Event {
+        let mut poll_r = vec!["poll_r"];
+        poll_r.extend(options);
+        Event {
+            id: vec![id; 32],
+            pubkey: vec![pubkey; 32],
+            kind: VOTE_KIND,
+            created_at,
+            tags: vec![tag(&["t", "lunch"]), tag(&["e", poll_id]), tag(&poll_r)],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn poll_rules() {
+        let (_dir, db) = temp_db();
+        let handler = PollHandler::new(&db).unwrap();
+        let poll = Event {
+            id: vec![1; 32],
+            kind: POLL_KIND,
+            tags: vec![tag(&["poll", "multi", "3", "100", "200", "title", "info", "a", "b", "c"])],
+            ..Default::default()
+        };
+        apply(&handler, &db, &poll, &Clock::default()).unwrap();
+        db.event_write(poll.clone()).unwrap();
+        let poll_id = hex::encode(&poll.id);
+        let state = || handler.query(&db, "state", &json!({ "event_id": poll_id })).unwrap();
+        let reject = |e: Event, reason: &str| {
+            let err = apply(&handler, &db, &e, &Clock::default()).unwrap_err().to_string();
+            assert!(err.contains(reason), "{}: {}", reason, err);
+        };
+
+        reject(vote(2, 1, 50, &poll_id, &["0"]), "poll not started");
+        reject(vote(2, 1, 250, &poll_id, &["0"]), "poll ended");
+        reject(vote(2, 1, 150, &poll_id, &["3"]), "vote option 3 out of range");
+        reject(vote(2, 1, 150, &poll_id, &["0", "0"]), "vote option 0 chosen twice");
+        reject(vote(2, 1, 150, &poll_id, &["x"]), "invalid vote option");
+        reject(vote(2, 1, 150, &poll_id, &[]), "vote has no option");
+        reject(vote(2, 1, 150, &"09".repeat(32), &["0"]), "poll event id not found");
+        assert_eq!(state(), json!([["a", 0], ["b", 0], ["c", 0]]));
+
+        // one vote per pubkey, the newest counts
+        apply(&handler, &db, &vote(2, 1, 110, &poll_id, &["0"]), &Clock::default()).unwrap();
+        reject(vote(3, 1, 105, &poll_id, &["1"]), "pubkey already voted");
+        apply(&handler, &db, &vote(6, 1, 120, &poll_id, &["1", "2"]), &Clock::default()).unwrap();
+        assert_eq!(state(), json!([["a", 0], ["b", 1], ["c", 1]]));
+        // same created_at, the lower id wins
+        reject(vote(7, 1, 120, &poll_id, &["0"]), "pubkey already voted");
+        apply(&handler, &db, &vote(5, 1, 120, &poll_id, &["0"]), &Clock::default()).unwrap();
+        assert_eq!(state(), json!([["a", 1], ["b", 0], ["c", 0]]));
+        let voter = handler
+            .query(&db, "voter", &json!({ "event_id": poll_id, "pubkey": hex::encode([1; 32]) }))
+            .unwrap();
+        assert_eq!(voter, json!({ "id": hex::encode([5; 32]), "created_at": 120, "options": [0] }));
+        let voter = handler
+            .query(&db, "voter", &json!({ "event_id": poll_id, "pubkey": hex::encode([2; 32]) }))
+            .unwrap();
+        assert_eq!(voter, Value::Null);
+
+        // counted up to logical time 3, whatever the votes count
+        apply(&handler, &db, &vote(8, 2, 130, &poll_id, &["1", "2"]), &at(3)).unwrap();
+        let late = vote(9, 3, 130, &poll_id, &["0"]);
+        let err = apply(&handler, &db, &late, &at(4)).unwrap_err().to_string();
+        assert!(err.contains("poll reached its clock limit"), "{}", err);
+        db.clock_write(at(3).encode_to_vec(), None).unwrap();
+        let err = validate(&handler, &db, &late).unwrap_err().to_string();
+        assert!(err.contains("poll reached its clock limit"), "{}", err);
+        assert_eq!(state(), json!([["a", 1], ["b", 1], ["c", 1]]));
+
+        // single option
+        let single = Event {
+            id: vec![10; 32],
+            kind: POLL_KIND,
+            tags: vec![tag(&["poll", "single", "0", "0", "200", "title", "info", "a", "b"])],
+            ..Default::default()
+        };
+        apply(&handler, &db, &single, &Clock::default()).unwrap();
+        db.event_write(single.clone()).unwrap();
+        let single_id = hex::encode(&single.id);
+        reject(vote(11, 1, 150, &single_id, &["0", "1"]), "single option vote len should be 1");
+        // the end is judged by created_at alone, submitted or gossiped, long after it
+        let before_end = vote(11, 1, 150, &single_id, &["1"]);
+        validate(&handler, &db, &before_end).unwrap();
+        apply(&handler, &db, &before_end, &Clock::default()).unwrap();
+        let after_end = vote(13, 3, 201, &single_id, &["1"]);
+        assert!(validate(&handler, &db, &after_end).unwrap_err().to_string().contains("poll ended"));
+        reject(after_end, "poll ended");
+        reject(vote(12, 1, 150, &hex::encode([2; 32]), &["0"]), "poll event id not found");
+    }
+
+    fn poll(id: u8, pubkey: u8, created_at: i64, clock: &str, end: &str) -> Event {
+        Event {
+            id: vec![id; 32],
+            pubkey: vec![pubkey; 32],
+            kind: POLL_KIND,
+            created_at,
+            tags: vec![tag(&["poll", "single", clock, "", end, &format!("poll {}", id), "info", "a", "b"])],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn poll_list() {
+        let (_dir, db) = temp_db();
+        let handler = PollHandler::new(&db).unwrap();
+        // created out of order, before 1970 too
+        for (id, pubkey, created_at, clock, end) in
+            [(1, 1, 30, "0", ""), (2, 2, -10, "0", "1"), (3, 1, 20, "1", ""), (4, 2, 40, "0", ""), (5, 1, 10, "0", "")]
+        {
+            let e = poll(id, pubkey, created_at, clock, end);
+            apply(&handler, &db, &e, &Clock::default()).unwrap();
+            db.event_write(e).unwrap();
+        }
+        apply(&handler, &db, &vote(9, 1, 25, &hex::encode([3; 32]), &["0"]), &at(1)).unwrap();
+        db.clock_write(at(1).encode_to_vec(), None).unwrap();
+        let list = |query: Value| -> PollPage { serde_json::from_value(handler.query(&db, "list", &query).unwrap()).unwrap() };
+        let ids = |page: &PollPage| page.polls.iter().map(|item| item.poll.id[..2].to_string()).collect::<Vec<_>>();
+
+        let page = list(json!({ "limit": 2 }));
+        assert_eq!(ids(&page), ["04", "01"]);
+        let page = list(json!({ "limit": 2, "cursor": page.cursor }));
+        assert_eq!(ids(&page), ["03", "05"]);
+        assert!(page.polls[0].closed);
+        let page = list(json!({ "limit": 2, "cursor": page.cursor }));
+        assert_eq!(ids(&page), ["02"]);
+        assert_eq!(page.cursor, None);
+
+        let page = list(json!({ "author": hex::encode([1; 32]) }));
+        assert_eq!(ids(&page), ["01", "03", "05"]);
+        let page = list(json!({ "author": hex::encode([1; 32]), "limit": 1, "cursor": page.polls[0].poll.list_key() }));
+        assert_eq!(ids(&page), ["03"]);
+        let page = list(json!({ "author": hex::encode([1; 32]), "limit": 1, "cursor": page.cursor }));
+        assert_eq!(ids(&page), ["05"]);
+        assert_eq!(page.cursor, None);
+        assert!(list(json!({ "author": hex::encode([3; 32]) })).polls.is_empty());
+
+        let page = list(json!({ "status": "open", "limit": 2 }));
+        assert_eq!(ids(&page), ["04", "01"]);
+        let page = list(json!({ "status": "open", "limit": 2, "cursor": page.cursor }));
+        assert_eq!(ids(&page), ["05"]);
+        assert_eq!(ids(&list(json!({ "status": "closed" }))), ["03", "02"]);
+        assert_eq!(ids(&list(json!({ "status": "closed", "author": hex::encode([2; 32]) }))), ["02"]);
+        assert!(handler.query(&db, "list", &json!({ "status": "ended" })).is_err());
+    }
+
+    #[test]
+    fn query_poll() {
+        let (_dir, db) = temp_db();
+        let handler = PollHandler::new(&db).unwrap();
+        for e in [poll(1, 1, 10, "2", "9999-12-31T23:59:59"), poll(2, 1, 10, "0", "2024-02-21T08:37")] {
+            apply(&handler, &db, &e, &Clock::default()).unwrap();
+            db.event_write(e).unwrap();
+        }
+        let query = |id: u8| -> Option<PollListItem> {
+            serde_json::from_value(handler.query(&db, "poll", &json!({ "event_id": hex::encode([id; 32]) })).unwrap()).unwrap()
+        };
+        let open = query(1).unwrap();
+        assert_eq!((open.poll.end, open.poll.clock, open.closed), (Some(253402300799), 2, false));
+        let ended = query(2).unwrap();
+        assert_eq!((ended.poll.end, ended.closed), (Some(1708504620), true));
+        assert_eq!(query(3), None);
+
+        // closed once the node clock reaches the poll clock
+        db.clock_write(at(2).encode_to_vec(), None).unwrap();
+        assert!(query(1).unwrap().closed);
+    }
+
+    #[test]
+    fn migrate_poll_id_list() {
+        let (_dir, db) = temp_db();
+        let handler = PollHandler::new(&db).unwrap();
+        let e = poll(1, 1, 10, "0", "");
+        apply(&handler, &db, &e, &Clock::default()).unwrap();
+        let entry = PollEntry::new(&e, &PollSpec::parse(&e).unwrap());
+        db.event_write(e).unwrap();
+        // the list as it was kept before the indexes, with a poll that has no state
+        let mut writer = db.inner.writer().unwrap();
+        writer.del(&handler.list, entry.list_key(), None).unwrap();
+        writer.del(&handler.authors, entry.author_key(), None).unwrap();
+        let legacy = json!([[hex::encode([1; 32]), "lunch", "pick one"], [hex::encode([2; 32]), "gone", ""], []]);
+        writer.put(&handler.tree, LEGACY_LIST_KEY, legacy.to_string()).unwrap();
+        writer.commit().unwrap();
+
+        let handler = PollHandler::new(&db).unwrap();
+        let page: PollPage = serde_json::from_value(handler.query(&db, "list", &Value::Null).unwrap()).unwrap();
+        assert_eq!(page.polls.len(), 1);
+        assert_eq!(page.polls[0].poll.title, "lunch");
+        assert_eq!(page.polls[0].poll.created_at, 10);
+        let page: PollPage =
+            serde_json::from_value(handler.query(&db, "list", &json!({ "author": hex::encode([1; 32]) })).unwrap()).unwrap();
+        assert_eq!(page.polls.len(), 1);
+        assert!(db.inner.reader().unwrap().get(&handler.tree, LEGACY_LIST_KEY).unwrap().is_none());
+        assert_eq!(
+            handler.query(&db, "state", &json!({ "event_id": hex::encode([1; 32]) })).unwrap(),
+            json!([["a", 0], ["b", 0]])
+        );
+    }
+
+    fn event(kind: u32, tags: &[&[&str]]) -> Event {
+        Event {
+            id: vec![1; 32],
+            kind,
+            tags: tags.iter().map(|t| tag(t)).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse_specs() {
+        let poll = event(
+            POLL_KIND,
+            &[&["t", "lunch"], &["poll", "single", "", "2024-02-21T08:37", "", "title", "info", "a"]],
+        );
+        assert_eq!(
+            PollSpec::parse(&poll),
+            Ok(PollSpec {
+                multi: false,
+                clock: MAX_POLL_CLOCK,
+                start: Some(1708504620),
+                end: None,
+                title: "title".to_string(),
+                info: "info".to_string(),
+                options: vec!["a".to_string()],
+            })
+        );
+        let err = |tags: &[&[&str]]| PollSpec::parse(&event(POLL_KIND, tags)).unwrap_err();
+        assert_eq!(err(&[&["t", "lunch"]]), PollError::MissingTag("poll"));
+        assert_eq!(
+            err(&[&["poll", "multi", "1", "", "", "t", "i", "a"], &["poll", "multi"]]),
+            PollError::DuplicateTag("poll")
+        );
+        assert_eq!(err(&[&["poll", "multi", "1", "", ""]]), PollError::MissingField("title"));
+        assert_eq!(err(&[&["poll", "multi", "1", "", "", "t", "i"]]), PollError::NoPollOptions);
+        assert_eq!(
+            err(&[&["poll", "any", "1", "", "", "t", "i", "a"]]),
+            PollError::InvalidField("choice", "any".to_string())
+        );
+        assert_eq!(
+            err(&[&["poll", "multi", "-1", "", "", "t", "i", "a"]]),
+            PollError::InvalidField("clock", "-1".to_string())
+        );
+        assert_eq!(
+            err(&[&["poll", "multi", "1", "2024-02-30T25:00", "", "t", "i", "a"]]),
+            PollError::InvalidField("start", "2024-02-30T25:00".to_string())
+        );
+        assert_eq!(err(&[&["poll", "multi", "1", "20", "10", "t", "i", "a"]]), PollError::EndBeforeStart);
+
+        let poll_id = "AB".repeat(32);
+        let vote = event(VOTE_KIND, &[&["e", &poll_id], &["e", "x"], &["poll_r", "1", "0"]]);
+        assert_eq!(
+            VoteSpec::parse(&vote),
+            Ok(VoteSpec {
+                poll_id: poll_id.to_ascii_lowercase(),
+                options: vec![1, 0],
+            })
+        );
+        let err = |tags: &[&[&str]]| VoteSpec::parse(&event(VOTE_KIND, tags)).unwrap_err();
+        assert_eq!(err(&[&["poll_r", "0"]]), PollError::MissingTag("e"));
+        assert_eq!(err(&[&["e"], &["poll_r", "0"]]), PollError::InvalidPollId(String::new()));
+        assert_eq!(err(&[&["e", "01"], &["poll_r", "0"]]), PollError::InvalidPollId("01".to_string()));
+        assert_eq!(err(&[&["e", &poll_id]]), PollError::MissingTag("poll_r"));
+        assert_eq!(
+            err(&[&["e", &poll_id], &["poll_r", "0"], &["poll_r", "1"]]),
+            PollError::DuplicateTag("poll_r")
+        );
+        assert_eq!(err(&[&["e", &poll_id], &["poll_r", "-1"]]), PollError::InvalidOption("-1".to_string()));
+    }
+
+    // random tag arrays never panic the parsers or the handler
+    #[test]
+    fn fuzz_tags() {
+        let (_dir, db) = temp_db();
+        let handler = PollHandler::new(&db).unwrap();
+        let poll = event(POLL_KIND, &[&["poll", "multi", "0", "", "", "title", "info", "a", "b"]]);
+        apply(&handler, &db, &poll, &Clock::default()).unwrap();
+        db.event_write(poll).unwrap();
+        let poll_id = hex::encode([1; 32]);
+        let words = [
+            "poll", "poll_r", "e", "t", "p", "multi", "single", "", "0", "1", "2", "-1", "18446744073709551616",
+            "9223372036854775807", "-9223372036854775808", "2024-02-21T08:37", "9999-12-31T23:59:59",
+            "0000-01-01T00:00", "2024-13-01T00:00", "T", "-", ":", "title", "é", &poll_id,
+        ];
+        // xorshift, the same cases on every run
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % n as u64) as usize
+        };
+        for i in 0..5000u32 {
+            let tags = (0..next(4))
+                .map(|_| TagArray {
+                    values: (0..next(11)).map(|_| words[next(words.len())].to_string()).collect(),
+                })
+                .collect();
+            let e = Event {
+                id: i.to_be_bytes().repeat(8),
+                pubkey: vec![next(3) as u8; 32],
+                created_at: [0, 1708504620, i64::MAX, i64::MIN][next(4)],
+                kind: [POLL_KIND, VOTE_KIND][next(2)],
+                tags,
+                ..Default::default()
+            };
+            let _ = PollSpec::parse(&e);
+            let _ = VoteSpec::parse(&e);
+            let _ = validate(&handler, &db, &e);
+            if apply(&handler, &db, &e, &Clock::default()).is_ok() && e.kind == POLL_KIND {
+                db.event_write(e.clone()).unwrap();
+            }
+        }
+        let _ = handler.query(&db, "list", &Value::Null).unwrap();
+        let _ = handler.query(&db, "state", &json!({ "event_id": poll_id })).unwrap();
+    }
+}
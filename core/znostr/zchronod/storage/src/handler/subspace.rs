@@ -0,0 +1,490 @@
+//! CausalityKey subspaces, creation (kind 30100) and join (kind 30200).
+//!
+//! k: sid, v: `Subspace` json, in the "subspace" tree
+//! k: sid_pubkey, v: `Member` json, in the "subspace_member" tree
+//!
+//! A sid is created once. Of two creations of the same sid the one first by
+//! logical time and event id wins, the order of the token log (see
+//! `op::clock_time`), so every node keeps the same creation whatever order
+//! they arrive in; the other is rejected, and a winner that arrives later
+//! takes the sid over from the creator of the loser. `created_at` is set by
+//! the author, so it cannot decide which creation wins.
+//!
+//! A join is kept whether the subspace is known or not, submitted or
+//! gossiped, so every node ends up with the same members whatever order the
+//! join and the creation it follows arrive in.
+
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use log::{debug, info};
+use nostr_kv::{Error, lmdb::{Transaction, Tree, Writer}};
+use proto::zchronod::{Clock, Event};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::handler::{str_param, KindHandler};
+use crate::{Result, ZchronodDb};
+
+pub const SUBSPACE_CREATE_KIND: u32 = 30100;
+pub const SUBSPACE_JOIN_KIND: u32 = 30200;
+
+/// Actions of the `auth` tag mask.
+pub const ACTION_READ: u32 = 1;
+pub const ACTION_WRITE: u32 = 2;
+pub const ACTION_EXECUTE: u32 = 4;
+// granted to the creator
+const CREATOR_ACTIONS: u32 = ACTION_READ | ACTION_WRITE | ACTION_EXECUTE;
+// granted to members that joined
+const MEMBER_ACTIONS: u32 = ACTION_READ | ACTION_WRITE;
+
+const SUBSPACE_TREE_NAME: &str = "subspace";
+const MEMBER_TREE_NAME: &str = "subspace_member";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Subspace {
+    pub sid: String,
+    pub name: String,
+    // hex pubkey of the creator, a member from the start
+    pub creator: String,
+    // op kind -> op name
+    pub ops: BTreeMap<u32, String>,
+    pub rules: String,
+    pub content: String,
+    pub event_id: String,
+    pub created_at: i64,
+    // values of the clock the creation takes in the causal order
+    #[serde(default)]
+    pub clock: BTreeMap<String, u64>,
+}
+
+impl Subspace {
+    // a creation with a lower key wins the sid
+    fn order(&self) -> (u64, &str) {
+        (self.clock.values().sum(), &self.event_id)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Member {
+    pub sid: String,
+    pub pubkey: String,
+    pub rules: String,
+    // event that added the member, the creation event for the creator
+    pub event_id: String,
+    pub created_at: i64,
+    // action mask the member may claim in `auth` tags
+    #[serde(default = "default_actions")]
+    pub actions: u32,
+    // clock values of every event that made the pubkey a member, an operation
+    // has to follow one of them
+    #[serde(default)]
+    pub clocks: Vec<BTreeMap<String, u64>>,
+}
+
+impl Member {
+    /// Whether an event at `clock` causally follows the member joining.
+    pub fn joined_before(&self, clock: &Clock) -> bool {
+        self.clocks.iter().any(|joined| {
+            joined
+                .iter()
+                .all(|(id, value)| clock.values.get(id).copied().unwrap_or(0) >= *value)
+        })
+    }
+}
+
+fn default_actions() -> u32 {
+    MEMBER_ACTIONS
+}
+
+pub struct SubspaceHandler {
+    subspaces: Tree,
+    members: Tree,
+}
+
+// value of the first tag named `name`
+pub(crate) fn tag_value<'a>(e: &'a Event, name: &str) -> Option<&'a str> {
+    e.tags
+        .iter()
+        .find(|t| t.values.first().map(|v| v.as_str()) == Some(name))
+        .and_then(|t| t.values.get(1))
+        .map(|v| v.as_str())
+}
+
+pub(crate) fn required_tag<'a>(e: &'a Event, name: &str) -> Result<&'a str> {
+    match tag_value(e, name) {
+        Some(v) if !v.is_empty() => Ok(v),
+        _ => Err(Error::Message(format!("kind {} event needs a {} tag", e.kind, name))),
+    }
+}
+
+pub(crate) fn check_define(e: &Event, define: &str) -> Result<()> {
+    match tag_value(e, "d") {
+        Some(d) if d == define => Ok(()),
+        d => Err(Error::Message(format!("kind {} event needs d tag {}, got {:?}", e.kind, define, d))),
+    }
+}
+
+/// Parse `post=30300,propose=30301` into op kind -> op name.
+pub fn parse_ops(ops: &str) -> Result<BTreeMap<u32, String>> {
+    let mut result = BTreeMap::new();
+    for op in ops.split(',').map(|op| op.trim()).filter(|op| !op.is_empty()) {
+        let (name, kind) = op
+            .split_once('=')
+            .ok_or_else(|| Error::Message(format!("invalid op {}", op)))?;
+        let kind: u32 = kind
+            .trim()
+            .parse()
+            .map_err(|_| Error::Message(format!("invalid op kind {}", op)))?;
+        if result.insert(kind, name.trim().to_string()).is_some() {
+            return Err(Error::Message(format!("op kind {} defined twice", kind)));
+        }
+    }
+    Ok(result)
+}
+
+fn member_key(sid: &str, pubkey: &str) -> String {
+    format!("{}_{}", sid, pubkey)
+}
+
+impl SubspaceHandler {
+    pub fn new(db: &ZchronodDb) -> Result<Self> {
+        Ok(SubspaceHandler {
+            subspaces: db.open_tree(SUBSPACE_TREE_NAME)?,
+            members: db.open_tree(MEMBER_TREE_NAME)?,
+        })
+    }
+
+    pub fn subspace(&self, db: &ZchronodDb, sid: &str) -> Result<Option<Subspace>> {
+        self.read_subspace(&db.reader()?, sid)
+    }
+
+    pub(crate) fn read_subspace<T: Transaction>(&self, txn: &T, sid: &str) -> Result<Option<Subspace>> {
+        match txn.get(&self.subspaces, sid)? {
+            Some(v) => Ok(Some(serde_json::from_slice(v).map_err(|e| Error::Message(e.to_string()))?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn member(&self, db: &ZchronodDb, sid: &str, pubkey: &str) -> Result<Option<Member>> {
+        self.read_member(&db.reader()?, sid, pubkey)
+    }
+
+    pub(crate) fn read_member<T: Transaction>(&self, txn: &T, sid: &str, pubkey: &str) -> Result<Option<Member>> {
+        match txn.get(&self.members, member_key(sid, pubkey))? {
+            Some(v) => Ok(Some(serde_json::from_slice(v).map_err(|e| Error::Message(e.to_string()))?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn subspaces(&self, db: &ZchronodDb) -> Result<Vec<Subspace>> {
+        let reader = db.reader()?;
+        let mut result = vec![];
+        for item in reader.iter(&self.subspaces) {
+            let (_, v) = item?;
+            result.push(serde_json::from_slice(v).map_err(|e| Error::Message(e.to_string()))?);
+        }
+        Ok(result)
+    }
+
+    pub fn members(&self, db: &ZchronodDb, sid: &str) -> Result<Vec<Member>> {
+        let reader = db.reader()?;
+        let prefix = member_key(sid, "");
+        let mut result = vec![];
+        for item in reader.iter_from(&self.members, Bound::Included(prefix.as_bytes()), false) {
+            let (k, v) = item?;
+            // hex pubkeys have no '_', a longer sid sharing the prefix does
+            match k.strip_prefix(prefix.as_bytes()) {
+                Some(rest) if !rest.contains(&b'_') => {}
+                Some(_) => continue,
+                None => break,
+            }
+            result.push(serde_json::from_slice(v).map_err(|e| Error::Message(e.to_string()))?);
+        }
+        Ok(result)
+    }
+
+    fn create(&self, writer: &mut Writer, e: &Event, clock: &Clock) -> Result<()> {
+        check_define(e, "subspace_create")?;
+        let subspace = Subspace {
+            sid: required_tag(e, "sid")?.to_string(),
+            name: required_tag(e, "subspace_name")?.to_string(),
+            creator: hex::encode(&e.pubkey),
+            ops: parse_ops(required_tag(e, "ops")?)?,
+            rules: tag_value(e, "rules").unwrap_or_default().to_string(),
+            content: e.content.clone(),
+            event_id: hex::encode(&e.id),
+            created_at: e.created_at,
+            clock: clock.values.clone(),
+        };
+        if let Some(existing) = self.read_subspace(writer, &subspace.sid)? {
+            if existing.order() <= subspace.order() {
+                return Err(Error::Message(format!("subspace {} exists", subspace.sid)));
+            }
+            info!("creation [{}] takes subspace [{}] over from [{}]", subspace.event_id, subspace.sid, existing.event_id);
+            self.drop_creator(writer, &existing)?;
+        }
+        info!("create subspace [{}] {}", subspace.sid, subspace.name);
+
+        let mut creator = Member {
+            sid: subspace.sid.clone(),
+            pubkey: subspace.creator.clone(),
+            rules: subspace.rules.clone(),
+            event_id: subspace.event_id.clone(),
+            created_at: subspace.created_at,
+            actions: CREATOR_ACTIONS,
+            clocks: vec![clock.values.clone()],
+        };
+        // a join of the creator that arrived first
+        if let Some(joined) = self.read_member(writer, &creator.sid, &creator.pubkey)? {
+            creator.clocks.extend(joined.clocks.into_iter().filter(|c| *c != clock.values));
+        }
+        writer.put(&self.subspaces, &subspace.sid, json!(subspace).to_string())?;
+        writer.put(&self.members, member_key(&creator.sid, &creator.pubkey), json!(creator).to_string())?;
+        Ok(())
+    }
+
+    // the creator of a creation that lost the sid stays a member by its joins only
+    fn drop_creator(&self, writer: &mut Writer, lost: &Subspace) -> Result<()> {
+        let key = member_key(&lost.sid, &lost.creator);
+        let mut creator = match self.read_member(writer, &lost.sid, &lost.creator)? {
+            Some(creator) => creator,
+            None => return Ok(()),
+        };
+        creator.clocks.retain(|c| *c != lost.clock);
+        if creator.clocks.is_empty() {
+            writer.del(&self.members, key, None)?;
+        } else {
+            creator.actions = MEMBER_ACTIONS;
+            writer.put(&self.members, key, json!(creator).to_string())?;
+        }
+        Ok(())
+    }
+
+    // a member joining again, on another node say, keeps the clock of every join
+    fn join(&self, writer: &mut Writer, e: &Event, clock: &Clock) -> Result<()> {
+        check_define(e, "subspace_join")?;
+        let sid = required_tag(e, "sid")?;
+        let pubkey = hex::encode(&e.pubkey);
+        let member = match self.read_member(writer, sid, &pubkey)? {
+            Some(mut member) => {
+                debug!("[{}] has joined subspace [{}]", pubkey, sid);
+                if member.clocks.contains(&clock.values) {
+                    return Ok(());
+                }
+                member.clocks.push(clock.values.clone());
+                member
+            }
+            None => {
+                info!("[{}] joins subspace [{}]", pubkey, sid);
+                Member {
+                    sid: sid.to_string(),
+                    pubkey,
+                    rules: tag_value(e, "rules").unwrap_or_default().to_string(),
+                    event_id: hex::encode(&e.id),
+                    created_at: e.created_at,
+                    actions: MEMBER_ACTIONS,
+                    clocks: vec![clock.values.clone()],
+                }
+            }
+        };
+        writer.put(&self.members, member_key(&member.sid, &member.pubkey), json!(member).to_string())?;
+        Ok(())
+    }
+}
+
+impl KindHandler for SubspaceHandler {
+    fn name(&self) -> &'static str {
+        "subspace"
+    }
+
+    // a submitted creation follows every creation the node knows, so it loses to them
+    fn validate(&self, _db: &ZchronodDb, txn: &Writer, e: &Event) -> Result<()> {
+        let sid = required_tag(e, "sid")?;
+        match e.kind {
+            SUBSPACE_CREATE_KIND if self.read_subspace(txn, sid)?.is_some() => {
+                Err(Error::Message(format!("subspace {} exists", sid)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn apply(&self, _db: &ZchronodDb, txn: &mut Writer, e: &Event, clock: &Clock) -> Result<()> {
+        match e.kind {
+            SUBSPACE_CREATE_KIND => self.create(txn, e, clock),
+            SUBSPACE_JOIN_KIND => self.join(txn, e, clock),
+            _ => Ok(()),
+        }
+    }
+
+    // list: every subspace
+    // members {"sid"}: members of a subspace, null for an unknown sid
+    // ops {"sid"}: op kind -> op name of a subspace, null for an unknown sid
+    fn query(&self, db: &ZchronodDb, method: &str, params: &Value) -> Result<Value> {
+        match method {
+            "list" => Ok(json!(self.subspaces(db)?)),
+            "members" => {
+                let sid = str_param(params, "sid")?;
+                match self.subspace(db, sid)? {
+                    Some(_) => Ok(json!(self.members(db, sid)?)),
+                    None => Ok(Value::Null),
+                }
+            }
+            "ops" => match self.subspace(db, str_param(params, "sid")?)? {
+                Some(subspace) => Ok(json!(subspace.ops)),
+                None => Ok(Value::Null),
+            },
+            _ => Err(Error::Message(format!("unknown subspace query {}", method))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{apply, tag, temp_db, validate};
+
+    fn create_event(id: u8, sid: &str, created_at: i64) -> Event {
+        Event {
+            id: vec![id; 32],
+            pubkey: vec![id; 32],
+            created_at,
+            kind: SUBSPACE_CREATE_KIND,
+            tags: vec![
+                tag(&["d", "subspace_create"]),
+                tag(&["sid", sid]),
+                tag(&["subspace_name", "governance"]),
+                tag(&["ops", "post=30300,propose=30301,vote=30302,invite=30303"]),
+                tag(&["rules", "energy>1000"]),
+            ],
+            content: "{}".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn clock(values: &[(&str, u64)]) -> Clock {
+        Clock {
+            id: "node".to_string(),
+            value: 0,
+            values: values.iter().map(|(k, v)| (k.to_string(), *v)).collect(),
+        }
+    }
+
+    fn join_event(id: u8, sid: &str) -> Event {
+        Event {
+            id: vec![id; 32],
+            pubkey: vec![id; 32],
+            kind: SUBSPACE_JOIN_KIND,
+            tags: vec![tag(&["d", "subspace_join"]), tag(&["sid", sid])],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse_subspace_ops() {
+        let ops = parse_ops("post=30300, vote=30302").unwrap();
+        assert_eq!(ops.get(&30300).unwrap(), "post");
+        assert_eq!(ops.get(&30302).unwrap(), "vote");
+        assert!(parse_ops("post").is_err());
+        assert!(parse_ops("post=x").is_err());
+        assert!(parse_ops("post=1,vote=1").is_err());
+    }
+
+    #[test]
+    fn subspace_create_and_join() {
+        let (_dir, db) = temp_db();
+        let handler = SubspaceHandler::new(&db).unwrap();
+
+        // a join before the subspace is kept, submitted or not
+        assert!(validate(&handler, &db, &join_event(7, "0xMG")).is_ok());
+        apply(&handler, &db, &join_event(7, "0xMG"), &clock(&[("a", 2)])).unwrap();
+
+        apply(&handler, &db, &create_event(2, "0xMG", 10), &clock(&[("a", 1)])).unwrap();
+        assert!(validate(&handler, &db, &join_event(7, "0xMG")).is_ok());
+        apply(&handler, &db, &create_event(3, "0xMG_2", 10), &Clock::default()).unwrap();
+        apply(&handler, &db, &join_event(9, "0xMG"), &clock(&[("a", 3)])).unwrap();
+        // joining twice is a no-op, joining on another node adds the clock
+        apply(&handler, &db, &join_event(9, "0xMG"), &clock(&[("a", 3)])).unwrap();
+        let member = handler.member(&db, "0xMG", &hex::encode([9u8; 32])).unwrap().unwrap();
+        assert_eq!(member.clocks.len(), 1);
+        apply(&handler, &db, &join_event(9, "0xMG"), &clock(&[("b", 1)])).unwrap();
+        let member = handler.member(&db, "0xMG", &hex::encode([9u8; 32])).unwrap().unwrap();
+        assert_eq!(member.clocks.len(), 2);
+        // an event follows the join when its clock covers one of them
+        assert!(member.joined_before(&clock(&[("a", 3)])));
+        assert!(member.joined_before(&clock(&[("a", 1), ("b", 2)])));
+        assert!(!member.joined_before(&clock(&[("a", 2)])));
+
+        let mut missing_sid = join_event(8, "");
+        missing_sid.tags.pop();
+        assert!(apply(&handler, &db, &missing_sid, &Clock::default()).is_err());
+
+        let list = handler.query(&db, "list", &Value::Null).unwrap();
+        assert_eq!(list.as_array().unwrap().len(), 2);
+        let members: Vec<Member> =
+            serde_json::from_value(handler.query(&db, "members", &json!({"sid": "0xMG"})).unwrap()).unwrap();
+        let pubkeys: Vec<String> = members.into_iter().map(|m| m.pubkey).collect();
+        assert_eq!(pubkeys, vec![hex::encode([2u8; 32]), hex::encode([7u8; 32]), hex::encode([9u8; 32])]);
+        let ops = handler.query(&db, "ops", &json!({"sid": "0xMG"})).unwrap();
+        assert_eq!(ops["30302"], "vote");
+        assert_eq!(handler.query(&db, "ops", &json!({"sid": "none"})).unwrap(), Value::Null);
+        // joins of an unknown sid are kept, it still has no members to list
+        apply(&handler, &db, &join_event(7, "none"), &clock(&[("a", 4)])).unwrap();
+        assert_eq!(handler.query(&db, "members", &json!({"sid": "none"})).unwrap(), Value::Null);
+        assert!(handler.query(&db, "ops", &Value::Null).is_err());
+
+        // a sid is created once, a backdated creation by another pubkey does not take it over
+        assert!(apply(&handler, &db, &create_event(1, "0xMG", 11), &clock(&[("a", 1), ("b", 1)])).is_err());
+        assert!(validate(&handler, &db, &create_event(4, "0xMG", 9)).is_err());
+        let err = apply(&handler, &db, &create_event(4, "0xMG", 9), &clock(&[("a", 2)])).unwrap_err();
+        assert!(err.to_string().contains("subspace 0xMG exists"));
+        let subspace = handler.subspace(&db, "0xMG").unwrap().unwrap();
+        assert_eq!(subspace.event_id, hex::encode([2u8; 32]));
+        assert_eq!(subspace.creator, hex::encode([2u8; 32]));
+        assert!(handler.member(&db, "0xMG", &hex::encode([2u8; 32])).unwrap().is_some());
+        assert!(handler.member(&db, "0xMG", &hex::encode([4u8; 32])).unwrap().is_none());
+    }
+
+    #[test]
+    fn concurrent_creations_agree() {
+        // concurrent creations, the second first by logical time
+        let first = (create_event(2, "0xMG", 10), clock(&[("a", 3)]));
+        let second = (create_event(5, "0xMG", 20), clock(&[("b", 2)]));
+        // the creator of the first joins as well
+        let mut join = (join_event(2, "0xMG"), clock(&[("a", 3), ("b", 1)]));
+        join.0.id = vec![6; 32];
+
+        let run = |order: [&(Event, Clock); 3]| {
+            let (_dir, db) = temp_db();
+            let handler = SubspaceHandler::new(&db).unwrap();
+            for (e, clock) in order {
+                // the loser is rejected when the winner is there
+                let _ = apply(&handler, &db, e, clock);
+            }
+            let subspace = handler.subspace(&db, "0xMG").unwrap().unwrap();
+            let members: Vec<(String, u32)> =
+                handler.members(&db, "0xMG").unwrap().into_iter().map(|m| (m.pubkey, m.actions)).collect();
+            (subspace.event_id, members)
+        };
+
+        let expected = (
+            hex::encode([5u8; 32]),
+            vec![(hex::encode([2u8; 32]), MEMBER_ACTIONS), (hex::encode([5u8; 32]), CREATOR_ACTIONS)],
+        );
+        assert_eq!(run([&first, &join, &second]), expected);
+        assert_eq!(run([&second, &join, &first]), expected);
+        assert_eq!(run([&join, &second, &first]), expected);
+
+        // without the join the creator of the loser is no member
+        let (_dir, db) = temp_db();
+        let handler = SubspaceHandler::new(&db).unwrap();
+        apply(&handler, &db, &first.0, &first.1).unwrap();
+        apply(&handler, &db, &second.0, &second.1).unwrap();
+        assert!(handler.member(&db, "0xMG", &hex::encode([2u8; 32])).unwrap().is_none());
+        // a tie on logical time goes to the lower event id
+        let tied = create_event(1, "0xMG", 30);
+        apply(&handler, &db, &tied, &clock(&[("c", 2)])).unwrap();
+        assert_eq!(handler.subspace(&db, "0xMG").unwrap().unwrap().event_id, hex::encode([1u8; 32]));
+        assert!(apply(&handler, &db, &create_event(3, "0xMG", 0), &clock(&[("d", 2)])).is_err());
+    }
+}
@@ -0,0 +1,1016 @@
+//! Key tokens (kinds 30320-30323): issue, transfer, approve and MintCredit.
+//!
+//! Token events are not applied in the order a node receives them. Every
+//! event is appended to the token log under its logical time (the sum of its
+//! vector clock, see `op::clock_time`) and its id. An event that happened
+//! before another counts fewer events, so the log order extends the causal
+//! order, and concurrent events are ordered the same way on every node. The
+//! ledger is the log applied in that order: of two concurrent transfers
+//! spending the same balance the one first in the log wins and the other is
+//! recorded as failed, whatever order the node received them in.
+//!
+//! An event that sorts before events already applied rolls those back with
+//! the undo record each of them left, and they are applied again after it.
+//!
+//! MintCredit rules count accepted events of the kind in `mint_if` carrying a
+//! `tag_key` tag of `tag_value` ("any" for every value), per author. Events
+//! of a kind some rule names are added to the log as well, events accepted
+//! before the first rule naming their kind when that rule arrives, under the
+//! clock they were stored with; an event stored without one, before the
+//! clock index, has no place in the log and is not counted. Counting
+//! happens when the log is applied, so a rule counts the events after it in
+//! the log, and every `threshold` events of an author credit the author
+//! `mint_amount` once; a rolled back count is taken back with its credit.
+//!
+//! k: logical time (8 bytes be) + event id, v: `LogEntry` json, in the "token_log" tree
+//! k: event id, v: log key, in the "token_event" tree
+//! k: log key, v: `Undo` json of an applied entry, in the "token_undo" tree
+//! k: symbol, v: `Token` json, in the "token" tree
+//! k: symbol_pubkey, v: balance as a decimal string, in the "token_balance" tree
+//! k: symbol_owner_spender, v: allowance as a decimal string, in the "token_allowance" tree
+//! k: symbol_event id_index, v: `MintRule` json, in the "token_rule" tree
+//! k: event id, v: `TokenResult` json, in the "token_result" tree
+//! k: rule key_pubkey, v: events counted as a decimal string, in the "token_count" tree
+//! k: kind a rule counts, v: empty, in the "token_watch" tree
+
+use std::collections::BTreeMap;
+use std::ops::{Bound, RangeInclusive};
+
+use log::{info, warn};
+use nostr_kv::{Error, lmdb::{Transaction, Tree, Writer}};
+use proto::zchronod::{Clock, Event};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::handler::op::{clock_time, event_time};
+use crate::handler::subspace::{required_tag, tag_value};
+use crate::handler::{str_param, KindHandler};
+use crate::{Result, ZchronodDb};
+
+pub const TOKEN_KINDS: RangeInclusive<u32> = 30320..=30323;
+pub const ISSUE_KIND: u32 = 30320;
+pub const TRANSFER_KIND: u32 = 30321;
+pub const APPROVE_KIND: u32 = 30322;
+pub const MINT_CREDIT_KIND: u32 = 30323;
+
+const MAX_SYMBOL_LEN: usize = 32;
+// digits of u128::MAX
+const MAX_DECIMALS: u32 = 38;
+
+const LOG_TREE_NAME: &str = "token_log";
+const EVENT_TREE_NAME: &str = "token_event";
+const UNDO_TREE_NAME: &str = "token_undo";
+const TOKEN_TREE_NAME: &str = "token";
+const BALANCE_TREE_NAME: &str = "token_balance";
+const ALLOWANCE_TREE_NAME: &str = "token_allowance";
+const RULE_TREE_NAME: &str = "token_rule";
+const RESULT_TREE_NAME: &str = "token_result";
+const COUNT_TREE_NAME: &str = "token_count";
+const WATCH_TREE_NAME: &str = "token_watch";
+
+// value a rule takes for every value of its tag key
+const ANY_VALUE: &str = "any";
+
+// amounts are json strings, they do not fit json numbers
+mod amount {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(amount: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&amount.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub symbol: String,
+    pub name: String,
+    pub decimals: u32,
+    // hex pubkey of the issuer, the only author of MintCredit rules
+    pub issuer: String,
+    #[serde(with = "amount")]
+    pub supply: u128,
+    pub description: String,
+    pub event_id: String,
+    pub created_at: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MintRule {
+    pub symbol: String,
+    // kind of the events the rule counts
+    pub mint_if: u32,
+    pub tag_key: String,
+    // "any" matches every value of tag_key
+    pub tag_value: String,
+    pub threshold: u64,
+    #[serde(with = "amount")]
+    pub mint_amount: u128,
+}
+
+impl MintRule {
+    // whether an event of `kind` with `tags` counts for the rule
+    fn matches(&self, kind: u32, tags: &[(String, String)]) -> bool {
+        self.mint_if == kind
+            && tags
+                .iter()
+                .any(|(key, value)| *key == self.tag_key && (self.tag_value == ANY_VALUE || *value == self.tag_value))
+    }
+}
+
+/// Ledger operation of a token event.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TokenOp {
+    Issue {
+        symbol: String,
+        name: String,
+        decimals: u32,
+        // credited to the issuer
+        #[serde(with = "amount")]
+        initial_supply: u128,
+        description: String,
+    },
+    Transfer {
+        from: String,
+        to: String,
+        symbol: String,
+        #[serde(with = "amount")]
+        amount: u128,
+    },
+    Approve {
+        spender: String,
+        symbol: String,
+        #[serde(with = "amount")]
+        amount: u128,
+    },
+    MintCredit {
+        rules: Vec<MintRule>,
+    },
+    // an event MintCredit rules may count, with its tags as (key, first value)
+    Count {
+        kind: u32,
+        tags: Vec<(String, String)>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub event_id: String,
+    pub pubkey: String,
+    pub created_at: i64,
+    pub op: TokenOp,
+}
+
+/// Outcome of a token event in the ledger, reason is set when it failed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TokenResult {
+    pub applied: bool,
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Token,
+    Balance,
+    Allowance,
+    Rule,
+    Result,
+    Count,
+}
+
+// a state key and the value it is set to, none deletes it
+type Change = (State, String, Option<String>);
+
+// values an applied entry replaced, restored in reverse order
+#[derive(Serialize, Deserialize, Default)]
+struct Undo {
+    changes: Vec<Change>,
+}
+
+fn invalid(e: &Event, name: &str, value: &str) -> Error {
+    Error::Message(format!("kind {} event has an invalid {} {}", e.kind, name, value))
+}
+
+fn parse_symbol(e: &Event, symbol: &str) -> Result<String> {
+    if symbol.len() > MAX_SYMBOL_LEN || !symbol.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(invalid(e, "symbol", symbol));
+    }
+    Ok(symbol.to_string())
+}
+
+// a hex pubkey, or an ETH address padded the way ETH authors are
+fn parse_pubkey(e: &Event, name: &str, pubkey: &str) -> Result<String> {
+    match hex::decode(pubkey.strip_prefix("0x").unwrap_or(pubkey)) {
+        Ok(bytes) if bytes.len() == 32 => Ok(hex::encode(bytes)),
+        Ok(bytes) if bytes.len() == 20 => Ok(format!("{}{}", "00".repeat(12), hex::encode(bytes))),
+        _ => Err(invalid(e, name, pubkey)),
+    }
+}
+
+fn parse_amount(e: &Event, name: &str, amount: &str) -> Result<u128> {
+    if amount.is_empty() || !amount.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid(e, name, amount));
+    }
+    amount.parse().map_err(|_| invalid(e, name, amount))
+}
+
+fn parse_rule<'a>(e: &Event, mut field: impl FnMut(&str) -> Option<&'a str>) -> Result<MintRule> {
+    let mut required = |name: &str| match field(name) {
+        Some(v) if !v.is_empty() => Ok(v),
+        _ => Err(Error::Message(format!("MintCredit rule needs {}", name))),
+    };
+    let symbol = parse_symbol(e, required("symbol")?)?;
+    let mint_if = required("mint_if")?;
+    let tag_key = required("tag_key")?.to_string();
+    let tag_value = required("tag_value")?.to_string();
+    let threshold = required("threshold")?;
+    let mint_amount = required("mint_amount")?;
+    let rule = MintRule {
+        symbol,
+        mint_if: mint_if.parse().map_err(|_| invalid(e, "mint_if", mint_if))?,
+        tag_key,
+        tag_value,
+        threshold: threshold.parse().map_err(|_| invalid(e, "threshold", threshold))?,
+        mint_amount: parse_amount(e, "mint_amount", mint_amount)?,
+    };
+    if rule.threshold == 0 {
+        return Err(invalid(e, "threshold", threshold));
+    }
+    // token events are in the log already
+    if TOKEN_KINDS.contains(&rule.mint_if) {
+        return Err(invalid(e, "mint_if", mint_if));
+    }
+    Ok(rule)
+}
+
+impl TokenOp {
+    /// Parse the ledger operation of a token event.
+    pub fn parse(e: &Event) -> Result<Self> {
+        match e.kind {
+            ISSUE_KIND => {
+                let decimals = required_tag(e, "decimals")?;
+                let decimals: u32 = decimals.parse().map_err(|_| invalid(e, "decimals", decimals))?;
+                if decimals > MAX_DECIMALS {
+                    return Err(invalid(e, "decimals", &decimals.to_string()));
+                }
+                Ok(TokenOp::Issue {
+                    symbol: parse_symbol(e, required_tag(e, "symbol")?)?,
+                    name: required_tag(e, "name")?.to_string(),
+                    decimals,
+                    initial_supply: match tag_value(e, "initial_supply") {
+                        Some(supply) => parse_amount(e, "initial_supply", supply)?,
+                        None => 0,
+                    },
+                    description: e.content.clone(),
+                })
+            }
+            TRANSFER_KIND => {
+                let amount = parse_amount(e, "amount", required_tag(e, "amount")?)?;
+                if amount == 0 {
+                    return Err(invalid(e, "amount", "0"));
+                }
+                Ok(TokenOp::Transfer {
+                    // the author spends its own balance by default
+                    from: match tag_value(e, "from") {
+                        Some(from) => parse_pubkey(e, "from", from)?,
+                        None => hex::encode(&e.pubkey),
+                    },
+                    to: parse_pubkey(e, "to", required_tag(e, "to")?)?,
+                    symbol: parse_symbol(e, required_tag(e, "symbol")?)?,
+                    amount,
+                })
+            }
+            APPROVE_KIND => Ok(TokenOp::Approve {
+                spender: parse_pubkey(e, "spender", required_tag(e, "spender")?)?,
+                symbol: parse_symbol(e, required_tag(e, "symbol")?)?,
+                amount: parse_amount(e, "amount", required_tag(e, "amount")?)?,
+            }),
+            MINT_CREDIT_KIND => {
+                let mut rules = vec![];
+                // a single rule in tags of its own
+                if tag_value(e, "mint_if").is_some() {
+                    rules.push(parse_rule(e, |name| tag_value(e, name))?);
+                }
+                // any number of ["rule", "symbol=..", "mint_if=..", ...]
+                for tag in e.tags.iter().filter(|t| t.values.first().map(|v| v.as_str()) == Some("rule")) {
+                    let items: Vec<(&str, &str)> = tag.values[1..].iter().filter_map(|item| item.split_once('=')).collect();
+                    rules.push(parse_rule(e, |name| items.iter().find(|(k, _)| k.trim() == name).map(|(_, v)| v.trim()))?);
+                }
+                if rules.is_empty() {
+                    return Err(Error::Message("MintCredit event defines no rule".to_string()));
+                }
+                Ok(TokenOp::MintCredit { rules })
+            }
+            kind => Err(Error::Message(format!("kind {} is not a token event", kind))),
+        }
+    }
+
+    // the op of an event of another kind counted by rules
+    fn count(e: &Event) -> Self {
+        TokenOp::Count {
+            kind: e.kind,
+            tags: e
+                .tags
+                .iter()
+                .filter_map(|t| match t.values.as_slice() {
+                    [key, value, ..] => Some((key.clone(), value.clone())),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn log_key(time: u64, event_id: &[u8]) -> Vec<u8> {
+    let mut key = time.to_be_bytes().to_vec();
+    key.extend_from_slice(event_id);
+    key
+}
+
+fn balance_key(symbol: &str, owner: &str) -> String {
+    format!("{}_{}", symbol, owner)
+}
+
+fn allowance_key(symbol: &str, owner: &str, spender: &str) -> String {
+    format!("{}_{}_{}", symbol, owner, spender)
+}
+
+fn amount_value(amount: u128) -> Option<String> {
+    (amount > 0).then(|| amount.to_string())
+}
+
+fn from_json<'a, T: Deserialize<'a>>(v: &'a [u8]) -> Result<T> {
+    serde_json::from_slice(v).map_err(|e| Error::Message(e.to_string()))
+}
+
+pub struct TokenHandler {
+    log: Tree,
+    events: Tree,
+    undo: Tree,
+    tokens: Tree,
+    balances: Tree,
+    allowances: Tree,
+    rules: Tree,
+    results: Tree,
+    counts: Tree,
+    watch: Tree,
+}
+
+impl TokenHandler {
+    pub fn new(db: &ZchronodDb) -> Result<Self> {
+        Ok(TokenHandler {
+            log: db.open_tree(LOG_TREE_NAME)?,
+            events: db.open_tree(EVENT_TREE_NAME)?,
+            undo: db.open_tree(UNDO_TREE_NAME)?,
+            tokens: db.open_tree(TOKEN_TREE_NAME)?,
+            balances: db.open_tree(BALANCE_TREE_NAME)?,
+            allowances: db.open_tree(ALLOWANCE_TREE_NAME)?,
+            rules: db.open_tree(RULE_TREE_NAME)?,
+            results: db.open_tree(RESULT_TREE_NAME)?,
+            counts: db.open_tree(COUNT_TREE_NAME)?,
+            watch: db.open_tree(WATCH_TREE_NAME)?,
+        })
+    }
+
+    fn tree(&self, state: State) -> &Tree {
+        match state {
+            State::Token => &self.tokens,
+            State::Balance => &self.balances,
+            State::Allowance => &self.allowances,
+            State::Rule => &self.rules,
+            State::Result => &self.results,
+            State::Count => &self.counts,
+        }
+    }
+
+    fn read_token<T: Transaction>(&self, txn: &T, symbol: &str) -> Result<Option<Token>> {
+        txn.get(&self.tokens, symbol)?.map(from_json).transpose()
+    }
+
+    fn read_amount<T: Transaction>(&self, txn: &T, tree: &Tree, key: &str) -> Result<u128> {
+        match txn.get(tree, key)? {
+            Some(v) => std::str::from_utf8(v)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| Error::Message(format!("bad amount of {}", key))),
+            None => Ok(0),
+        }
+    }
+
+    pub fn token(&self, db: &ZchronodDb, symbol: &str) -> Result<Option<Token>> {
+        self.read_token(&db.reader()?, symbol)
+    }
+
+    pub fn tokens(&self, db: &ZchronodDb) -> Result<Vec<Token>> {
+        let reader = db.reader()?;
+        let mut result = vec![];
+        for item in reader.iter(&self.tokens) {
+            result.push(from_json(item?.1)?);
+        }
+        Ok(result)
+    }
+
+    /// Balance of `owner`, none for an unknown symbol.
+    pub fn balance(&self, db: &ZchronodDb, symbol: &str, owner: &str) -> Result<Option<u128>> {
+        let reader = db.reader()?;
+        if self.read_token(&reader, symbol)?.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(self.read_amount(&reader, &self.balances, &balance_key(symbol, owner))?))
+    }
+
+    /// Amount `spender` may still transfer from `owner`, none for an unknown symbol.
+    pub fn allowance(&self, db: &ZchronodDb, symbol: &str, owner: &str, spender: &str) -> Result<Option<u128>> {
+        let reader = db.reader()?;
+        if self.read_token(&reader, symbol)?.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(self.read_amount(&reader, &self.allowances, &allowance_key(symbol, owner, spender))?))
+    }
+
+    /// MintCredit rules of a token, in log order.
+    pub fn rules(&self, db: &ZchronodDb, symbol: &str) -> Result<Vec<MintRule>> {
+        let reader = db.reader()?;
+        let prefix = format!("{}_", symbol);
+        let mut result = vec![];
+        for item in reader.iter_from(&self.rules, Bound::Included(prefix.as_bytes()), false) {
+            let (k, v) = item?;
+            if !k.starts_with(prefix.as_bytes()) {
+                break;
+            }
+            result.push(from_json(v)?);
+        }
+        Ok(result)
+    }
+
+    /// Events of `pubkey` counted by each MintCredit rule of a token, by rule key.
+    pub fn counts(&self, db: &ZchronodDb, symbol: &str, pubkey: &str) -> Result<BTreeMap<String, u64>> {
+        let reader = db.reader()?;
+        let prefix = format!("{}_", symbol);
+        let suffix = format!("_{}", pubkey);
+        let mut result = BTreeMap::new();
+        for item in reader.iter_from(&self.counts, Bound::Included(prefix.as_bytes()), false) {
+            let (k, v) = item?;
+            if !k.starts_with(prefix.as_bytes()) {
+                break;
+            }
+            if let Some(rule) = k.strip_suffix(suffix.as_bytes()) {
+                let count = String::from_utf8_lossy(v).parse().map_err(|_| Error::Message("bad count".to_string()))?;
+                result.insert(String::from_utf8_lossy(rule).into_owned(), count);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Outcome of a token event, none if it is not in the log.
+    pub fn result(&self, db: &ZchronodDb, event_id: &str) -> Result<Option<TokenResult>> {
+        db.reader()?.get(&self.results, event_id)?.map(from_json).transpose()
+    }
+
+    // the state changes of `entry` on top of the state in `txn`, or why it fails
+    fn plan<T: Transaction>(&self, txn: &T, entry: &LogEntry) -> Result<std::result::Result<Vec<Change>, String>> {
+        let mut changes = vec![];
+        match &entry.op {
+            TokenOp::Issue { symbol, name, decimals, initial_supply, description } => {
+                if self.read_token(txn, symbol)?.is_some() {
+                    return Ok(Err(format!("token {} is already issued", symbol)));
+                }
+                let token = Token {
+                    symbol: symbol.clone(),
+                    name: name.clone(),
+                    decimals: *decimals,
+                    issuer: entry.pubkey.clone(),
+                    supply: *initial_supply,
+                    description: description.clone(),
+                    event_id: entry.event_id.clone(),
+                    created_at: entry.created_at,
+                };
+                changes.push((State::Token, symbol.clone(), Some(json!(token).to_string())));
+                changes.push((State::Balance, balance_key(symbol, &entry.pubkey), amount_value(*initial_supply)));
+            }
+            TokenOp::Transfer { from, to, symbol, amount } => {
+                if self.read_token(txn, symbol)?.is_none() {
+                    return Ok(Err(format!("unknown token {}", symbol)));
+                }
+                let from_key = balance_key(symbol, from);
+                let balance = self.read_amount(txn, &self.balances, &from_key)?;
+                if balance < *amount {
+                    return Ok(Err(format!("insufficient {} balance of {}: {} < {}", symbol, from, balance, amount)));
+                }
+                // spending for another account uses up its allowance
+                if *from != entry.pubkey {
+                    let key = allowance_key(symbol, from, &entry.pubkey);
+                    let allowance = self.read_amount(txn, &self.allowances, &key)?;
+                    if allowance < *amount {
+                        return Ok(Err(format!(
+                            "insufficient {} allowance of {} from {}: {} < {}",
+                            symbol, entry.pubkey, from, allowance, amount
+                        )));
+                    }
+                    changes.push((State::Allowance, key, amount_value(allowance - amount)));
+                }
+                if from != to {
+                    let to_key = balance_key(symbol, to);
+                    let received = match self.read_amount(txn, &self.balances, &to_key)?.checked_add(*amount) {
+                        Some(received) => received,
+                        None => return Ok(Err(format!("{} balance of {} overflows", symbol, to))),
+                    };
+                    changes.push((State::Balance, from_key, amount_value(balance - amount)));
+                    changes.push((State::Balance, to_key, amount_value(received)));
+                }
+            }
+            TokenOp::Approve { spender, symbol, amount } => {
+                if self.read_token(txn, symbol)?.is_none() {
+                    return Ok(Err(format!("unknown token {}", symbol)));
+                }
+                changes.push((State::Allowance, allowance_key(symbol, &entry.pubkey, spender), amount_value(*amount)));
+            }
+            TokenOp::MintCredit { rules } => {
+                for (index, rule) in rules.iter().enumerate() {
+                    let token = match self.read_token(txn, &rule.symbol)? {
+                        Some(token) => token,
+                        None => return Ok(Err(format!("unknown token {}", rule.symbol))),
+                    };
+                    if token.issuer != entry.pubkey {
+                        return Ok(Err(format!("only the issuer of {} defines its MintCredit rules", rule.symbol)));
+                    }
+                    let key = format!("{}_{}_{:04}", rule.symbol, entry.event_id, index);
+                    changes.push((State::Rule, key, Some(json!(rule).to_string())));
+                }
+            }
+            TokenOp::Count { kind, tags } => {
+                let mut minted: BTreeMap<String, u128> = BTreeMap::new();
+                for item in txn.iter(&self.rules) {
+                    let (k, v) = item?;
+                    let rule: MintRule = from_json(v)?;
+                    if !rule.matches(*kind, tags) {
+                        continue;
+                    }
+                    let count_key = format!("{}_{}", String::from_utf8_lossy(k), entry.pubkey);
+                    let count = self.read_amount(txn, &self.counts, &count_key)? + 1;
+                    changes.push((State::Count, count_key, Some(count.to_string())));
+                    // a crossing of the threshold credits once
+                    if count % rule.threshold as u128 == 0 {
+                        let amount = minted.entry(rule.symbol).or_default();
+                        *amount = amount.saturating_add(rule.mint_amount);
+                    }
+                }
+                for (symbol, amount) in minted {
+                    let mut token = match self.read_token(txn, &symbol)? {
+                        Some(token) => token,
+                        None => continue,
+                    };
+                    let key = balance_key(&symbol, &entry.pubkey);
+                    let balance = self.read_amount(txn, &self.balances, &key)?;
+                    match (token.supply.checked_add(amount), balance.checked_add(amount)) {
+                        (Some(supply), Some(balance)) => {
+                            info!("token {} mints {} to {}", symbol, amount, entry.pubkey);
+                            token.supply = supply;
+                            changes.push((State::Token, symbol, Some(json!(token).to_string())));
+                            changes.push((State::Balance, key, amount_value(balance)));
+                        }
+                        _ => warn!("token {} supply overflows, {} not minted", symbol, amount),
+                    }
+                }
+            }
+        }
+        Ok(Ok(changes))
+    }
+
+    // apply a log entry and keep the values it replaced
+    fn apply_entry(&self, writer: &mut Writer, key: &[u8], entry: &LogEntry) -> Result<()> {
+        let (mut changes, result) = match self.plan(writer, entry)? {
+            Ok(changes) => (changes, TokenResult { applied: true, reason: None }),
+            Err(reason) => (vec![], TokenResult { applied: false, reason: Some(reason) }),
+        };
+        // counted events of other kinds have no outcome of their own
+        if !matches!(entry.op, TokenOp::Count { .. }) {
+            changes.push((State::Result, entry.event_id.clone(), Some(json!(result).to_string())));
+        }
+
+        let mut undo = Undo::default();
+        for (state, k, value) in changes {
+            let tree = self.tree(state);
+            let old = writer.get(tree, &k)?.map(|v| String::from_utf8_lossy(v).into_owned());
+            match &value {
+                Some(value) => writer.put(tree, &k, value)?,
+                None if old.is_some() => writer.del(tree, &k, None)?,
+                None => {}
+            }
+            undo.changes.push((state, k, old));
+        }
+        writer.put(&self.undo, key, json!(undo).to_string())?;
+        Ok(())
+    }
+
+    // restore the state before an applied entry
+    fn rollback(&self, writer: &mut Writer, key: &[u8]) -> Result<()> {
+        let undo: Undo = match writer.get(&self.undo, key)? {
+            Some(v) => from_json(v)?,
+            None => return Ok(()),
+        };
+        for (state, k, old) in undo.changes.into_iter().rev() {
+            let tree = self.tree(state);
+            match old {
+                Some(old) => writer.put(tree, &k, old)?,
+                None => {
+                    if writer.get(tree, &k)?.is_some() {
+                        writer.del(tree, &k, None)?;
+                    }
+                }
+            }
+        }
+        writer.del(&self.undo, key, None)?;
+        Ok(())
+    }
+
+    // add `e` at logical `time` to the log and bring the ledger up to date, once per event
+    fn append(&self, db: &ZchronodDb, writer: &mut Writer, e: &Event, time: u64, op: TokenOp) -> Result<()> {
+        let entry = log_entry(e, op);
+        let key = log_key(time, &e.id);
+        if writer.get(&self.events, &entry.event_id)?.is_some() {
+            return Ok(());
+        }
+        writer.put(&self.log, &key, json!(entry).to_string())?;
+        writer.put(&self.events, &entry.event_id, &key)?;
+        // kinds rules count from now on, whether the rules take effect or not
+        let mut watched = vec![];
+        if let TokenOp::MintCredit { rules } = &entry.op {
+            for rule in rules {
+                let kind = rule.mint_if.to_string();
+                if writer.get(&self.watch, &kind)?.is_none() {
+                    writer.put(&self.watch, &kind, "")?;
+                    watched.push(rule.mint_if);
+                }
+            }
+        }
+
+        let later = writer
+            .iter_from(&self.undo, Bound::Excluded(key.as_slice()), false)
+            .map(|item| item.map(|(k, _)| k.to_vec()))
+            .collect::<Result<Vec<_>>>()?;
+        if !later.is_empty() {
+            info!("token event [{}] rolls back {} later events", entry.event_id, later.len());
+        }
+        for k in later.iter().rev() {
+            self.rollback(writer, k)?;
+        }
+        let pending = writer
+            .iter_from(&self.log, Bound::Included(key.as_slice()), false)
+            .map(|item| item.map(|(k, v)| (k.to_vec(), v.to_vec())))
+            .collect::<Result<Vec<_>>>()?;
+        for (k, v) in pending {
+            self.apply_entry(writer, &k, &from_json(&v)?)?;
+        }
+
+        // events accepted before their kind was watched
+        if !watched.is_empty() {
+            let events = db.read_events_by_kinds(writer, &watched)?;
+            info!("token rules of [{}] count {} earlier events", entry.event_id, events.len());
+            for e in events {
+                match event_time(db, writer, &e)? {
+                    Some(time) => self.append(db, writer, &e, time, TokenOp::count(&e))?,
+                    None => warn!("event [{}] has no clock, token rules do not count it", hex::encode(&e.id)),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn log_entry(e: &Event, op: TokenOp) -> LogEntry {
+    LogEntry {
+        event_id: hex::encode(&e.id),
+        pubkey: hex::encode(&e.pubkey),
+        created_at: e.created_at,
+        op,
+    }
+}
+
+impl KindHandler for TokenHandler {
+    fn name(&self) -> &'static str {
+        "token"
+    }
+
+    // a submitted event is checked against the ledger as it is now
+    fn validate(&self, _db: &ZchronodDb, txn: &Writer, e: &Event) -> Result<()> {
+        let entry = log_entry(e, TokenOp::parse(e)?);
+        match self.plan(txn, &entry)? {
+            Ok(_) => Ok(()),
+            Err(reason) => Err(Error::Message(reason)),
+        }
+    }
+
+    // events are only refused when malformed, whether they take effect
+    // depends on their place in the log, see `result`
+    fn apply(&self, db: &ZchronodDb, txn: &mut Writer, e: &Event, clock: &Clock) -> Result<()> {
+        self.append(db, txn, e, clock_time(clock), TokenOp::parse(e)?)
+    }
+
+    // events of kinds MintCredit rules count
+    fn observe(&self, db: &ZchronodDb, txn: &mut Writer, e: &Event, clock: &Clock) -> Result<()> {
+        if TOKEN_KINDS.contains(&e.kind) || txn.get(&self.watch, e.kind.to_string())?.is_none() {
+            return Ok(());
+        }
+        self.append(db, txn, e, clock_time(clock), TokenOp::count(e))
+    }
+
+    // token {"symbol"}: `Token`, null if unknown
+    // tokens: every `Token`
+    // balance {"symbol", "owner"}: amount string, null for an unknown symbol
+    // allowance {"symbol", "owner", "spender"}: amount string, null for an unknown symbol
+    // rules {"symbol"}: `MintRule`s of the token
+    // counts {"symbol", "pubkey"}: rule key -> events of pubkey the rule counted
+    // result {"event_id"}: `TokenResult`, null if not in the log
+    fn query(&self, db: &ZchronodDb, method: &str, params: &Value) -> Result<Value> {
+        match method {
+            "token" => Ok(json!(self.token(db, str_param(params, "symbol")?)?)),
+            "tokens" => Ok(json!(self.tokens(db)?)),
+            "balance" => Ok(json!(self
+                .balance(db, str_param(params, "symbol")?, str_param(params, "owner")?)?
+                .map(|amount| amount.to_string()))),
+            "allowance" => Ok(json!(self
+                .allowance(
+                    db,
+                    str_param(params, "symbol")?,
+                    str_param(params, "owner")?,
+                    str_param(params, "spender")?,
+                )?
+                .map(|amount| amount.to_string()))),
+            "rules" => Ok(json!(self.rules(db, str_param(params, "symbol")?)?)),
+            "counts" => Ok(json!(self.counts(db, str_param(params, "symbol")?, str_param(params, "pubkey")?)?)),
+            "result" => Ok(json!(self.result(db, str_param(params, "event_id")?)?)),
+            _ => Err(Error::Message(format!("unknown token query {}", method))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{apply, observe, tag, temp_db, validate};
+    use prost::Message;
+    use proto::zchronod::TagArray;
+
+    fn pubkey(n: u8) -> String {
+        hex::encode([n; 32])
+    }
+
+    fn event(id: u8, kind: u32, author: u8, tags: Vec<TagArray>) -> Event {
+        Event { id: vec![id; 32], pubkey: vec![author; 32], kind, tags, ..Default::default() }
+    }
+
+    fn issue(id: u8, author: u8, symbol: &str, supply: &str) -> Event {
+        event(id, ISSUE_KIND, author, vec![
+            tag(&["symbol", symbol]),
+            tag(&["name", "New Coin"]),
+            tag(&["decimals", "6"]),
+            tag(&["initial_supply", supply]),
+        ])
+    }
+
+    fn transfer(id: u8, author: u8, from: u8, to: u8, amount: &str) -> Event {
+        event(id, TRANSFER_KIND, author, vec![
+            tag(&["from", &pubkey(from)]),
+            tag(&["to", &pubkey(to)]),
+            tag(&["symbol", "NOST"]),
+            tag(&["amount", amount]),
+        ])
+    }
+
+    // record the clock `e` was accepted at, returns it
+    fn accepted_at(db: &ZchronodDb, e: &Event, values: &[(&str, u64)]) -> Clock {
+        let clock = Clock {
+            id: "node".to_string(),
+            value: 0,
+            values: values.iter().map(|(k, v)| (k.to_string(), *v)).collect(),
+        };
+        db.clock_write(clock.encode_to_vec(), Some((hex::encode(&e.id), clock.encode_to_vec()))).unwrap();
+        clock
+    }
+
+    fn balance(handler: &TokenHandler, db: &ZchronodDb, owner: u8) -> u128 {
+        handler.balance(db, "NOST", &pubkey(owner)).unwrap().unwrap()
+    }
+
+    #[test]
+    fn parse_ops() {
+        let e = issue(1, 1, "NOST", "100");
+        assert!(matches!(TokenOp::parse(&e).unwrap(), TokenOp::Issue { initial_supply: 100, decimals: 6, .. }));
+        assert!(TokenOp::parse(&issue(1, 1, "NO_ST", "100")).is_err());
+        assert!(TokenOp::parse(&issue(1, 1, "NOST", "-1")).is_err());
+        assert!(TokenOp::parse(&transfer(1, 1, 1, 2, "0")).is_err());
+        assert!(TokenOp::parse(&transfer(1, 1, 1, 2, "1e6")).is_err());
+
+        // the author spends its own balance by default, addresses are padded
+        let e = event(1, TRANSFER_KIND, 1, vec![
+            tag(&["to", "0x2c7536e3605d9c16a7a3d7b1898e529396a65c23"]),
+            tag(&["symbol", "NOST"]),
+            tag(&["amount", "5"]),
+        ]);
+        assert_eq!(TokenOp::parse(&e).unwrap(), TokenOp::Transfer {
+            from: pubkey(1),
+            to: "0000000000000000000000002c7536e3605d9c16a7a3d7b1898e529396a65c23".to_string(),
+            symbol: "NOST".to_string(),
+            amount: 5,
+        });
+
+        let single = event(1, MINT_CREDIT_KIND, 1, vec![
+            tag(&["symbol", "NOST"]),
+            tag(&["mint_if", "30023"]),
+            tag(&["tag_key", "e"]),
+            tag(&["tag_value", "any"]),
+            tag(&["threshold", "10"]),
+            tag(&["mint_amount", "1000000"]),
+        ]);
+        let rule = MintRule {
+            symbol: "NOST".to_string(),
+            mint_if: 30023,
+            tag_key: "e".to_string(),
+            tag_value: "any".to_string(),
+            threshold: 10,
+            mint_amount: 1000000,
+        };
+        assert_eq!(TokenOp::parse(&single).unwrap(), TokenOp::MintCredit { rules: vec![rule.clone()] });
+        let multi = event(1, MINT_CREDIT_KIND, 1, vec![
+            tag(&["rule", "symbol=NOST", "mint_if=30023", "tag_key=e", "tag_value=any", "threshold=10", "mint_amount=1000000"]),
+            tag(&["rule", "symbol=NOST", "mint_if=30011", "tag_key=p", "tag_value=ab", "threshold=3", "mint_amount=500000"]),
+        ]);
+        match TokenOp::parse(&multi).unwrap() {
+            TokenOp::MintCredit { rules } => {
+                assert_eq!(rules[0], rule);
+                assert_eq!(rules[1].threshold, 3);
+            }
+            op => panic!("unexpected {:?}", op),
+        }
+        let broken = event(1, MINT_CREDIT_KIND, 1, vec![tag(&["rule", "symbol=NOST", "mint_if=30023"])]);
+        assert!(TokenOp::parse(&broken).is_err());
+        assert!(TokenOp::parse(&event(1, MINT_CREDIT_KIND, 1, vec![])).is_err());
+    }
+
+    #[test]
+    fn ledger() {
+        let (_dir, db) = temp_db();
+        let handler = TokenHandler::new(&db).unwrap();
+
+        // unknown symbol
+        assert!(validate(&handler, &db, &transfer(2, 1, 1, 2, "10")).is_err());
+        assert_eq!(handler.balance(&db, "NOST", &pubkey(1)).unwrap(), None);
+
+        apply(&handler, &db, &issue(1, 1, "NOST", "100"), &Clock::default()).unwrap();
+        assert!(validate(&handler, &db, &issue(9, 2, "NOST", "5")).is_err());
+        let token = handler.token(&db, "NOST").unwrap().unwrap();
+        assert_eq!((token.issuer, token.supply, token.decimals), (pubkey(1), 100, 6));
+        assert_eq!(handler.query(&db, "balance", &json!({"symbol": "NOST", "owner": pubkey(1)})).unwrap(), json!("100"));
+
+        apply(&handler, &db, &transfer(2, 1, 1, 2, "30"), &Clock::default()).unwrap();
+        assert_eq!((balance(&handler, &db, 1), balance(&handler, &db, 2)), (70, 30));
+        // overdraft
+        assert!(validate(&handler, &db, &transfer(3, 2, 2, 3, "31")).is_err());
+        apply(&handler, &db, &transfer(3, 2, 2, 3, "31"), &Clock::default()).unwrap();
+        let result = handler.result(&db, &hex::encode([3u8; 32])).unwrap().unwrap();
+        assert!(!result.applied);
+        assert_eq!(balance(&handler, &db, 2), 30);
+
+        // spending for another account needs an allowance
+        assert!(validate(&handler, &db, &transfer(4, 3, 1, 3, "10")).is_err());
+        let approve = event(5, APPROVE_KIND, 1, vec![
+            tag(&["spender", &pubkey(3)]),
+            tag(&["symbol", "NOST"]),
+            tag(&["amount", "15"]),
+        ]);
+        apply(&handler, &db, &approve, &Clock::default()).unwrap();
+        apply(&handler, &db, &transfer(6, 3, 1, 3, "10"), &Clock::default()).unwrap();
+        assert_eq!(handler.allowance(&db, "NOST", &pubkey(1), &pubkey(3)).unwrap(), Some(5));
+        assert_eq!((balance(&handler, &db, 1), balance(&handler, &db, 3)), (60, 10));
+        assert!(validate(&handler, &db, &transfer(7, 3, 1, 3, "6")).is_err());
+
+        // rules are defined by the issuer only
+        let rule = |id: u8, author: u8| {
+            event(id, MINT_CREDIT_KIND, author, vec![tag(&[
+                "rule", "symbol=NOST", "mint_if=1", "tag_key=t", "tag_value=any", "threshold=3", "mint_amount=5",
+            ])])
+        };
+        assert!(validate(&handler, &db, &rule(8, 2)).is_err());
+        apply(&handler, &db, &rule(8, 1), &Clock::default()).unwrap();
+        assert_eq!(handler.rules(&db, "NOST").unwrap().len(), 1);
+
+        // malformed events are refused, events are applied once
+        assert!(apply(&handler, &db, &transfer(10, 1, 1, 2, "x"), &Clock::default()).is_err());
+        apply(&handler, &db, &transfer(2, 1, 1, 2, "30"), &Clock::default()).unwrap();
+        assert_eq!(balance(&handler, &db, 2), 30);
+    }
+
+    #[test]
+    fn concurrent_double_spend() {
+        let issue = issue(1, 1, "NOST", "100");
+        // both spend 80 of the 100 without having seen each other
+        let to_two = transfer(2, 1, 1, 2, "80");
+        let to_three = transfer(3, 1, 1, 3, "80");
+        let then = transfer(4, 3, 3, 4, "50");
+
+        let ledger = |order: [&Event; 4]| {
+            let (dir, db) = temp_db();
+            let handler = TokenHandler::new(&db).unwrap();
+            let mut at = std::collections::HashMap::new();
+            at.insert(&issue.id, accepted_at(&db, &issue, &[("a", 1)]));
+            at.insert(&to_two.id, accepted_at(&db, &to_two, &[("a", 2)]));
+            at.insert(&to_three.id, accepted_at(&db, &to_three, &[("a", 1), ("b", 1)]));
+            at.insert(&then.id, accepted_at(&db, &then, &[("a", 2), ("b", 2)]));
+            for e in order {
+                apply(&handler, &db, e, &at[&e.id]).unwrap();
+            }
+            let balances: Vec<u128> = (1..=4).map(|owner| balance(&handler, &db, owner)).collect();
+            let results: Vec<bool> = [&to_two, &to_three, &then]
+                .iter()
+                .map(|e| handler.result(&db, &hex::encode(&e.id)).unwrap().unwrap().applied)
+                .collect();
+            drop(dir);
+            (balances, results)
+        };
+
+        // both count two events, the lower id goes first and 3 has nothing to pass on
+        let expected = (vec![20, 80, 0, 0], vec![true, false, false]);
+        assert_eq!(ledger([&issue, &to_two, &to_three, &then]), expected);
+        assert_eq!(ledger([&then, &to_three, &to_two, &issue]), expected);
+        assert_eq!(ledger([&to_two, &issue, &then, &to_three]), expected);
+    }
+
+    #[test]
+    fn mint_credit() {
+        let issue = issue(1, 1, "NOST", "0");
+        let rule = event(2, MINT_CREDIT_KIND, 1, vec![tag(&[
+            "rule", "symbol=NOST", "mint_if=1", "tag_key=t", "tag_value=any", "threshold=2", "mint_amount=5",
+        ])]);
+        let note = |id: u8, author: u8, t: &str| event(id, 1, author, vec![tag(&[t, "chat"])]);
+        // before the rule in the log, does not count
+        let early = note(10, 2, "t");
+        let notes = [note(11, 2, "t"), note(12, 2, "t"), note(13, 3, "t"), note(14, 2, "p"), note(15, 2, "t")];
+
+        let ledger = |rule_first: bool| {
+            let (dir, db) = temp_db();
+            let handler = TokenHandler::new(&db).unwrap();
+            let mut events = vec![(early.clone(), accepted_at(&db, &early, &[("a", 1)]))];
+            let issue_at = accepted_at(&db, &issue, &[("a", 2)]);
+            let rule_at = accepted_at(&db, &rule, &[("a", 3)]);
+            for (i, e) in notes.iter().enumerate() {
+                events.push((e.clone(), accepted_at(&db, e, &[("a", 3), ("b", i as u64 + 1)])));
+            }
+            apply(&handler, &db, &issue, &issue_at).unwrap();
+            if rule_first {
+                apply(&handler, &db, &rule, &rule_at).unwrap();
+            }
+            // accepted events reach the handler through the registry
+            for (e, at) in &events {
+                observe(&handler, &db, e, at).unwrap();
+                db.event_write(e.clone()).unwrap();
+                observe(&handler, &db, e, at).unwrap();
+            }
+            if !rule_first {
+                apply(&handler, &db, &rule, &rule_at).unwrap();
+            }
+            let token = handler.token(&db, "NOST").unwrap().unwrap();
+            let counts = handler.counts(&db, "NOST", &pubkey(2)).unwrap();
+            let result = (token.supply, balance(&handler, &db, 2), balance(&handler, &db, 3), counts.into_values().collect::<Vec<_>>());
+            drop(dir);
+            result
+        };
+
+        // three counted events of 2 cross the threshold once, one of 3 does not
+        let expected = (5, 5, 0, vec![3]);
+        assert_eq!(ledger(true), expected);
+        assert_eq!(ledger(false), expected);
+    }
+
+    #[test]
+    fn earlier_events_keep_their_clock() {
+        let (_dir, db) = temp_db();
+        let handler = TokenHandler::new(&db).unwrap();
+        let issue = issue(1, 1, "NOST", "0");
+        apply(&handler, &db, &issue, &accepted_at(&db, &issue, &[("a", 1)])).unwrap();
+        let rule = event(2, MINT_CREDIT_KIND, 1, vec![tag(&[
+            "rule", "symbol=NOST", "mint_if=1", "tag_key=t", "tag_value=any", "threshold=1", "mint_amount=5",
+        ])]);
+        let rule_at = accepted_at(&db, &rule, &[("a", 5)]);
+
+        // stored before the rule, after it in the log
+        let counted = event(10, 1, 2, vec![tag(&["t", "chat"])]);
+        accepted_at(&db, &counted, &[("a", 5), ("b", 1)]);
+        db.event_write(counted.clone()).unwrap();
+        // stored without a clock, the node clock does not place it
+        let unordered = event(11, 1, 3, vec![tag(&["t", "chat"])]);
+        db.event_write(unordered.clone()).unwrap();
+        let node = Clock { values: [("a".to_string(), 9)].into(), ..Default::default() };
+        db.clock_write(node.encode_to_vec(), None).unwrap();
+
+        apply(&handler, &db, &rule, &rule_at).unwrap();
+        assert_eq!(balance(&handler, &db, 2), 5);
+        assert_eq!(handler.balance(&db, "NOST", &pubkey(3)).unwrap(), Some(0));
+        assert_eq!(handler.counts(&db, "NOST", &pubkey(2)).unwrap().into_values().collect::<Vec<_>>(), vec![1]);
+        assert!(handler.counts(&db, "NOST", &pubkey(3)).unwrap().is_empty());
+    }
+}
@@ -8,48 +8,62 @@ use serde::{Deserialize, Serialize};
 //use log::kv::ToKey;
 use serde::de::Unexpected::Str;
 
-use cache::Cache;
 use nostr_kv::{Error, lmdb::{*, Db as Lmdb, Iter as LmdbIter}, scanner::{Group, GroupItem, MatchResult, Scanner}};
 use nostr_kv::lmdb::Db;
 use proto::zchronod::Event;
 
+pub mod attribution;
 mod bloomfilter;
 mod cache;
+pub mod handler;
+#[cfg(test)]
+mod testing;
 
 pub struct ZchronodDb {
     inner: Db,
     state: Tree,
-    cache: Arc<Mutex<Cache>>,
+    // event id -> signed vlc meta the event was gossiped with, served on sync
+    vlc: Tree,
+    // "logical time_event id" -> empty, the vlc metas in an order sync can page
+    vlc_time: Tree,
+    // "node" -> this node's clock, event id -> clock the event was accepted at
+    clock: Tree,
+    // big endian sequence number -> event id, events in causal delivery order
+    seq: Tree,
+    // "origin:value" -> clock of a rejected event, holds its place in the causal order
+    skip: Tree,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct OptionState {
-    // map: HashMap<String, i32>,
-    option_vec: Vec<(String, i32)>,
-    // option_name : vote_num
-    event: Event,
-}
-
-type Result<T, E = Error> = core::result::Result<T, E>;
+pub type Result<T, E = Error> = core::result::Result<T, E>;
 
+// events by hex id, shared with the poll handler state
 const TREE_NAME: &str = "3041";
+const VLC_TREE_NAME: &str = "vlc";
+const VLC_TIME_TREE_NAME: &str = "vlc_time";
+const CLOCK_TREE_NAME: &str = "clock";
+const NODE_CLOCK_KEY: &str = "node";
+const SEQ_TREE_NAME: &str = "seq";
+const SKIP_TREE_NAME: &str = "skip";
+const MAX_TREES: u32 = 64;
 
 impl ZchronodDb {
-    // kind_301_poll is to init kv
-    // kind_309_vote is to update value
-    // k : 3041_event-id_state
-    // v:  event_state
-    // event_state is  map[option_name]vote_num, event
     pub fn new(db_path: String) -> Result<Self> {
-        let lmdb = Db::open(db_path)?;
+        // every kind handler keeps its own trees
+        let lmdb = Db::open_with(db_path, Some(MAX_TREES), Some(100), Some(1_000_000_000_000), 0)?;
         let state = lmdb.open_tree(Some(TREE_NAME), 0)?;
-        let cache = Arc::new(Mutex::new(
-            Cache::new(&lmdb, &state)
-        ));
+        let vlc = lmdb.open_tree(Some(VLC_TREE_NAME), 0)?;
+        let vlc_time = lmdb.open_tree(Some(VLC_TIME_TREE_NAME), 0)?;
+        let clock = lmdb.open_tree(Some(CLOCK_TREE_NAME), 0)?;
+        let seq = lmdb.open_tree(Some(SEQ_TREE_NAME), 0)?;
+        let skip = lmdb.open_tree(Some(SKIP_TREE_NAME), 0)?;
         Ok(ZchronodDb {
             inner: lmdb,
             state,
-            cache,
+            vlc,
+            vlc_time,
+            clock,
+            seq,
+            skip,
         })
     }
 
@@ -61,6 +75,11 @@ impl ZchronodDb {
         Ok(self.inner.reader()?)
     }
 
+    // trees of kind handlers live in the same env so one writer covers them all
+    pub fn open_tree(&self, name: &str) -> Result<Tree> {
+        self.inner.open_tree(Some(name), 0)
+    }
+
 
     // let my_path = "./my_file_sglk";
     // let db = Db::open(my_path)?;
@@ -81,248 +100,323 @@ impl ZchronodDb {
     // fn get_vote_null_option(e: Event) -> HashMap<String, i32> {
     //
     // }
-    pub fn poll_write(&self, key: String, e: Event) -> Result<(), Error> {
-        println!("poll write key is {:?}", key.clone());
-        let reader = self.inner.reader()?;
-        if reader.get(&self.state, key.clone())?.is_none() {
-            let mut writer = self.inner.writer()?;
-            // convert option_state to json, and write as bytes
-            if e.tags.len() != 1 {
-                println!("tag len != 1, should be panic");
-                panic!()
-            }
-            let poll_tag = e.tags.get(0).unwrap().clone().values;
-            // option start with index 5
-            // let mut option_hmap: HashMap<String, i32> = HashMap::new();
-            let mut option_vec: Vec<(String, i32)> = vec![];
-            // ["poll", "single", "0","1707294126","1707294126", "I'm a title!","This a demo survey!" "Option 1", "Option 2", "Option 3"]
-            for i in 7..=poll_tag.len() - 1 {
-                //option_hmap.insert(poll_tag.get(i).unwrap().to_string(), 0);
-                option_vec.push((poll_tag.get(i).unwrap().to_string(), 0));
-                println!("insert index {} , which is {}", i, poll_tag.get(i).unwrap().to_string());
-            }
-            let title = poll_tag.get(5).unwrap().to_string();
-            let info = poll_tag.get(6).unwrap().to_string();
-            let o_s = OptionState {
-                // map: option_hmap,    // to generate option with 0
-                option_vec,
-                event: e.clone(),
-
-            };
-            let option_state = serde_json::to_string(&o_s).unwrap();
-            writer.put(&self.state, key.clone(), option_state);
-            match reader.get(&self.state, "poll_id".to_string())? {
-                Some(t) => {
-                    let mut poll_id_list: Vec<Vec<String>> = serde_json::from_str(std::str::from_utf8(t).unwrap()).unwrap();
-                    println!("get poll_id, {:?}", &poll_id_list);
-                    // transfer u8 to hex
-                    let s_poll_id: String = hex::encode(e.id.clone());
-                    let mut s_poll = vec![];
-                    s_poll.extend([s_poll_id, title, info]);
-                    poll_id_list.push(s_poll);
-                    println!("after update, get poll_id, {:?}", &poll_id_list);
-                    // poll_id_list.push(e.id.clone());
-                    writer.put(&self.state, "poll_id".to_string(), serde_json::to_string(&poll_id_list).unwrap());
-                }
-                None => {
-                    let mut poll_id_list: Vec<Vec<String>> = Vec::new();
-                    let mut s_poll = vec![];
-                    let s_poll_id: String = hex::encode(e.id.clone());
-                    s_poll.extend([s_poll_id, title, info]);
-                    poll_id_list.push(s_poll);
-                    let json_write = serde_json::to_string(&poll_id_list).unwrap();
-                    println!("write poll_id, {:?}", &json_write);
-                    writer.put(&self.state, "poll_id".to_string(), json_write);
-                }
-            }
-            self.cache.lock().unwrap().set_poll_event(key);
-            writer.commit()?;
-        } else {
-            println!("poll write key which is {:?} has saved", key);
-        }
-
-        // println!("query test here");
-        // drop(reader);
-        // let q_result = self.query_all_event_id().unwrap();
-        // println!("query poll_id, {:?}", q_result);
+    pub fn event_write(&self, e: Event) -> Result<(), Error> {
+        let mut writer = self.inner.writer()?;
+        self.put_event(&mut writer, &e)?;
+        writer.commit()?;
         Ok(())
     }
 
-    pub fn event_write(&self, e: Event) -> Result<(), Error> {
+    // store `e` in the transaction its kind handler state is written in
+    pub fn put_event(&self, writer: &mut Writer, e: &Event) -> Result<(), Error> {
         let key: String = hex::encode(e.id.clone());
-        let reader = self.inner.reader()?;
-        let mut writer = self.inner.writer()?;
-        if reader.get(&self.state, key.clone())?.is_none() {
-            println!("need to save event [{:?}]", key.clone());
-            let event_bytes = serde_json::to_vec(&e).unwrap();
-            writer.put(&self.state, key.clone(), event_bytes);
-            writer.commit()?;
-            // let serialized = serde_json::to_vec(&event_1).unwrap();
-            // let _v2 = reader.get(&t2,"k4")?.unwrap();
-            // let deserialized: Event = serde_json::from_slice(&_v2).unwrap();
-        } else {
-            println!("event id has been saved, dont need to write event id which is [{:?}]", key.clone());
+        if writer.get(&self.state, key.clone())?.is_some() {
+            info!("event id has been saved, dont need to write event id which is [{:?}]", key);
             return Err(Error::Message("event id has saved".to_string()));
         }
-
+        let event_bytes = serde_json::to_vec(e).map_err(|e| Error::Message(e.to_string()))?;
+        writer.put(&self.state, key, event_bytes)?;
         Ok(())
     }
-    pub fn vote_write(&self, e: Event) -> Result<(), Error> {
-        // construct key
-        let mut vote_tag = e.tags.clone();
-        let mut event_id = "".to_string();
-        let mut option_vote: Vec<String> = vec![];
-        let event_symbol = "e".to_string();
-        let reader = self.inner.reader()?;
-        // should be once in item
-        for item in &mut vote_tag {
-            if item.values.get(0).unwrap().to_string() == event_symbol {
-                event_id = item.values.get(1).unwrap().to_string();
-            }
-            if item.values.get(0).unwrap().to_string() == "poll_r".to_string() {
-                // option start with 1
-                for i in 1..=item.values.len() - 1 {
-                    option_vote.push(item.values.get(i).unwrap().to_string());
-                    println!("insert poll_r index {} , which is {}", i, item.values.get(i).unwrap().to_string());
-                }
-            }
-        }
 
-        // check single mutil poll, single option vote len should be 1
-        let poll_event = match reader.get(&self.state, event_id.clone())? {
+    pub fn has_event(&self, event_id: String) -> Result<bool, Error> {
+        self.read_has_event(&self.inner.reader()?, event_id)
+    }
+
+    pub fn read_has_event<T: Transaction>(&self, txn: &T, event_id: String) -> Result<bool, Error> {
+        Ok(txn.get(&self.state, event_id)?.is_some())
+    }
+
+    pub fn query_by_event_id(&self, event_id: String) -> Result<Event, Error> {
+        let reader = self.inner.reader()?;
+        return match reader.get(&self.state, event_id)? {
             Some(t) => {
                 let event: Event = serde_json::from_slice(t).unwrap();
-                event
+                Ok(event)
             }
             None => {
                 println!("find none in query_by_event_id");
                 info!("find none in query_by_event_id");
-                Event::default()
+                Ok(Default::default())
             }
         };
+    }
 
-        if poll_event.id.len() == 0 {
-            return Err(Error::Message("poll event id not found".to_string()));
+    // stored events of the given kinds, the tree keeps poll state next to the events
+    pub fn query_events_by_kinds(&self, kinds: &[u32]) -> Result<Vec<Event>, Error> {
+        self.read_events_by_kinds(&self.inner.reader()?, kinds)
+    }
+
+    pub fn read_events_by_kinds<T: Transaction>(&self, txn: &T, kinds: &[u32]) -> Result<Vec<Event>, Error> {
+        let mut result = vec![];
+        for item in txn.iter(&self.state) {
+            let (k, v) = item?;
+            if k.len() != 64 || !k.iter().all(|b| b.is_ascii_hexdigit()) {
+                continue;
+            }
+            let event: Event = serde_json::from_slice(v).map_err(|e| Error::Message(e.to_string()))?;
+            if kinds.contains(&event.kind) {
+                result.push(event);
+            }
         }
+        Ok(result)
+    }
 
-        if poll_event.tags[0].values[1].to_string() == "single".to_string() && option_vote.len() != 1 {
-            println!("vote option len should be 1 in single option vote, whose len is {:?}", option_vote.len());
-            info!("vote option len should be 1 in single option vote, whose len is {:?}", option_vote.len());
-            return Err(Error::Message("single option vote len should be 1".to_string()));
+    // k: hex event id, v: encoded VlcMeta, the first one written is kept
+    // and indexed at `time`, the logical time of its clock
+    pub fn vlc_write(&self, event_id: String, time: u64, vlc_meta: Vec<u8>) -> Result<(), Error> {
+        let reader = self.inner.reader()?;
+        if reader.get(&self.vlc, event_id.clone())?.is_some() {
+            return Ok(());
         }
+        drop(reader);
+        let mut writer = self.inner.writer()?;
+        writer.put(&self.vlc_time, vlc_time_key(time, &event_id), [])?;
+        writer.put(&self.vlc, event_id, vlc_meta)?;
+        writer.commit()?;
+        Ok(())
+    }
 
-        let key = format!("3041_{}_state", event_id.clone());
-        println!("vote write key is {:?}, should find this poll_key in db", key.clone());
-        // read state, update, write
+    // index the vlc metas stored before the time index, `time` reads the logical
+    // time of an encoded meta. Returns how many were indexed
+    pub fn index_vlc(&self, time: impl Fn(&[u8]) -> Option<u64>) -> Result<usize, Error> {
+        let reader = self.inner.reader()?;
+        if reader.iter(&self.vlc_time).next().is_some() {
+            return Ok(0);
+        }
+        let mut keys = vec![];
+        for item in reader.iter(&self.vlc) {
+            let (k, v) = item?;
+            if let Some(time) = time(v) {
+                keys.push(vlc_time_key(time, &String::from_utf8_lossy(k)));
+            }
+        }
+        drop(reader);
+        let mut writer = self.inner.writer()?;
+        for key in &keys {
+            writer.put(&self.vlc_time, key, [])?;
+        }
+        writer.commit()?;
+        Ok(keys.len())
+    }
 
-        let state = std::str::from_utf8(reader.get(&self.state, key.to_string())?.unwrap()).unwrap();
-        let mut op_read_state: OptionState = serde_json::from_str(state).unwrap();
+    pub fn has_vlc(&self, event_id: String) -> Result<bool, Error> {
+        let reader = self.inner.reader()?;
+        Ok(reader.get(&self.vlc, event_id)?.is_some())
+    }
 
-        // update
-        for vote in &option_vote {
-            let vote_index: usize = vote.parse().unwrap();
-            if let Some(mut tuple) = op_read_state.option_vec.get_mut(vote_index) {
-                tuple.1 += 1;
-                println!("{:?}", tuple);
+    // at most `limit` (cursor, vlc meta) by logical time after `cursor`, none for the first page
+    pub fn query_vlc_after(&self, cursor: Option<&str>, limit: usize) -> Result<Vec<(String, Vec<u8>)>, Error> {
+        let reader = self.inner.reader()?;
+        let from = match cursor {
+            Some(cursor) => std::ops::Bound::Excluded(cursor.as_bytes()),
+            None => std::ops::Bound::Unbounded,
+        };
+        let mut result = vec![];
+        for item in reader.iter_from(&self.vlc_time, from, false).take(limit) {
+            let (k, _) = item?;
+            let key = String::from_utf8_lossy(k).to_string();
+            // hex event ids have no '_'
+            let event_id = key.rsplit('_').next().unwrap_or_default();
+            if let Some(v) = reader.get(&self.vlc, event_id)? {
+                result.push((key.clone(), v.to_vec()));
             }
         }
+        Ok(result)
+    }
 
-        println!("after update vote {:?}", &op_read_state.option_vec);
-
-        // write
+    // node clock and the clock of the event that moved it are written in one transaction,
+    // an event clock already indexed is kept
+    pub fn clock_write(&self, node_clock: Vec<u8>, event: Option<(String, Vec<u8>)>) -> Result<(), Error> {
         let mut writer = self.inner.writer()?;
-        let wirte_json_string = serde_json::to_string(&op_read_state).unwrap();
-        writer.put(&self.state, key.to_string(), wirte_json_string).expect("failed to put vote state");
+        writer.put(&self.clock, NODE_CLOCK_KEY, node_clock)?;
+        if let Some((event_id, event_clock)) = event {
+            self.put_event_clock(&mut writer, event_id, event_clock)?;
+        }
         writer.commit()?;
+        Ok(())
+    }
 
-        // test query poll_event state
-        drop(reader);
-        let state_poll = self.query_poll_event_state(event_id).unwrap();
-        println!("vote test state_poll is [{:?}]", state_poll);
+    // index the clock of an event in `writer`, an event clock already indexed is kept
+    pub fn put_event_clock(&self, writer: &mut Writer, event_id: String, event_clock: Vec<u8>) -> Result<(), Error> {
+        if writer.get(&self.clock, event_id.clone())?.is_none() {
+            writer.put(&self.clock, event_id, event_clock)?;
+        }
         Ok(())
     }
 
-    pub fn query_poll_event_state(&self, event_id: String) -> Result<Vec<(String, i32)>, Error> {
-        // construct key
-        let key = format!("3041_{}_state", event_id);
-        println!("vote write key is {:?}", key.clone());
+    pub fn query_node_clock(&self) -> Result<Option<Vec<u8>>, Error> {
+        self.read_node_clock(&self.inner.reader()?)
+    }
 
-        // bloom query
-        if !self.cache.lock().unwrap().validate_poll_event(key.clone()) {
-            info!("query_poll_event_state via bloom filter is none which id is [{:?}]",event_id.clone());
-            return Ok(vec![]);
-        }
+    pub fn read_node_clock<T: Transaction>(&self, txn: &T) -> Result<Option<Vec<u8>>, Error> {
+        Ok(txn.get(&self.clock, NODE_CLOCK_KEY)?.map(|v| v.to_vec()))
+    }
+
+    pub fn query_clock_by_event_id(&self, event_id: String) -> Result<Option<Vec<u8>>, Error> {
+        self.read_clock_by_event_id(&self.inner.reader()?, event_id)
+    }
+
+    pub fn read_clock_by_event_id<T: Transaction>(&self, txn: &T, event_id: String) -> Result<Option<Vec<u8>>, Error> {
+        Ok(txn.get(&self.clock, event_id)?.map(|v| v.to_vec()))
+    }
+
+    // every (event id, clock) of the index
+    pub fn query_all_event_clock(&self) -> Result<Vec<(String, Vec<u8>)>, Error> {
         let reader = self.inner.reader()?;
-        if reader.get(&self.state, key.clone())?.is_none() {
-            info!("query_poll_event_state is none which id is [{:?}]",event_id);
-            println!("query_poll_event_state is none which id is [{:?}]", event_id);
-            return Ok(vec![]);
+        let mut result = vec![];
+        for item in reader.iter(&self.clock) {
+            let (k, v) = item?;
+            if k == NODE_CLOCK_KEY.as_bytes() {
+                continue;
+            }
+            result.push((String::from_utf8_lossy(k).to_string(), v.to_vec()));
         }
-        let state = std::str::from_utf8(reader.get(&self.state, key.to_string())?.unwrap()).unwrap();
+        Ok(result)
+    }
 
-        let op_state: OptionState = serde_json::from_str(state).unwrap();
-        let mut result: Vec<(String, i32)> = vec![];
-        for element in op_state.option_vec {
-            result.push(element);
+    // k: "origin:value" of a rejected event, v: its encoded clock
+    pub fn skip_write(&self, slot: String, clock: Vec<u8>) -> Result<(), Error> {
+        let mut writer = self.inner.writer()?;
+        writer.put(&self.skip, slot, clock)?;
+        writer.commit()?;
+        Ok(())
+    }
+
+    pub fn query_all_skip(&self) -> Result<Vec<Vec<u8>>, Error> {
+        let reader = self.inner.reader()?;
+        let mut result = vec![];
+        for item in reader.iter(&self.skip) {
+            let (_, v) = item?;
+            result.push(v.to_vec());
         }
         Ok(result)
     }
-    pub fn query_all_event_id(&self) -> Result<Vec<Vec<String>>, Error> {
+
+    // append event ids after the last sequence number, returns the last one written
+    pub fn seq_append(&self, event_ids: Vec<String>) -> Result<u64, Error> {
+        let mut last = self.query_last_seq()?;
+        if event_ids.is_empty() {
+            return Ok(last);
+        }
+        let mut writer = self.inner.writer()?;
+        for event_id in event_ids {
+            last += 1;
+            writer.put(&self.seq, last.to_be_bytes(), event_id)?;
+        }
+        writer.commit()?;
+        Ok(last)
+    }
+
+    pub fn query_last_seq(&self) -> Result<u64, Error> {
         let reader = self.inner.reader()?;
-        return match reader.get(&self.state, "poll_id".to_string())? {
-            Some(t) => {
-                let poll_id_list: Vec<Vec<String>> = serde_json::from_str(std::str::from_utf8(t).unwrap()).unwrap();
-                println!("query all poll_event_id, {:?}", &poll_id_list);
-                Ok(poll_id_list)
+        let mut iter = reader.iter_from(&self.seq, std::ops::Bound::Unbounded::<Vec<u8>>, true);
+        match iter.next() {
+            Some(item) => {
+                let (k, _) = item?;
+                Ok(seq_from_key(k))
             }
-            None => {
-                println!("find none in query_all_event_id");
-                info!("find none in query_all_event_id");
-                Ok(vec![vec![]])
-            }
-        };
+            None => Ok(0),
+        }
     }
 
-    fn write_3041_db(&self, key: &str, option_state: HashMap<String, i32>) -> Result<(), Error> {
+    // at most `limit` (sequence number, event id) after `cursor`
+    pub fn query_seq_after(&self, cursor: u64, limit: usize) -> Result<Vec<(u64, String)>, Error> {
         let reader = self.inner.reader()?;
-        let mut op_state = "".to_string();
-        match reader.get(&self.state, key.to_string())? {
-            None => {
-                return Err(Error::Message("failed to get state in db".to_string()));
-            }
-            Some(t) => {
-                let state_bytes = reader.get(&self.state, key.to_string());
-                match reader.get(&self.state, key.to_string()) {
-                    Ok(s) => {
-                        match std::str::from_utf8(s.unwrap()) {
-                            Ok(i) => { op_state = i.to_string() }
-                            Err(_) => {
-                                return Err(Error::Message("failed to transfer to string".to_string()));
-                            }
-                        }
-                    }
-                    Err(_) => { return Err(Error::Message("failed to get state in db".to_string())); }
-                }
-                //  op_state = std::str::from_utf8(state_bytes);
-            }
+        let from = (cursor + 1).to_be_bytes();
+        let mut result = vec![];
+        for item in reader.iter_from(&self.seq, std::ops::Bound::Included(from), false).take(limit) {
+            let (k, v) = item?;
+            result.push((seq_from_key(k), String::from_utf8_lossy(v).to_string()));
         }
+        Ok(result)
+    }
+}
+
+// 16 hex digits so keys sort by time
+fn vlc_time_key(time: u64, event_id: &str) -> String {
+    format!("{:016x}_{}", time, event_id)
+}
 
+fn seq_from_key(k: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&k[..8]);
+    u64::from_be_bytes(buf)
+}
 
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_db;
+
+    #[test]
+    fn vlc_write_keeps_first() {
+        let (_dir, db) = temp_db();
+        assert!(!db.has_vlc("aa".to_string()).unwrap());
+        db.vlc_write("aa".to_string(), 2, vec![1]).unwrap();
+        db.vlc_write("aa".to_string(), 1, vec![2]).unwrap();
+        db.vlc_write("bb".to_string(), 1, vec![3]).unwrap();
+        assert!(db.has_vlc("aa".to_string()).unwrap());
+        let metas = |page: Vec<(String, Vec<u8>)>| page.into_iter().map(|(_, meta)| meta).collect::<Vec<_>>();
+        assert_eq!(metas(db.query_vlc_after(None, 10).unwrap()), vec![vec![3], vec![1]]);
+        let page = db.query_vlc_after(None, 1).unwrap();
+        assert_eq!(metas(db.query_vlc_after(Some(&page[0].0), 10).unwrap()), vec![vec![1]]);
     }
 
-    pub fn query_by_event_id(&self, event_id: String) -> Result<Event, Error> {
-        let reader = self.inner.reader()?;
-        return match reader.get(&self.state, event_id)? {
-            Some(t) => {
-                let event: Event = serde_json::from_slice(t).unwrap();
-                Ok(event)
-            }
-            None => {
-                println!("find none in query_by_event_id");
-                info!("find none in query_by_event_id");
-                Ok(Default::default())
-            }
-        };
+    #[test]
+    fn index_stored_vlc() {
+        let (_dir, db) = temp_db();
+        // metas stored before the time index
+        let mut writer = db.writer().unwrap();
+        writer.put(&db.vlc, "aa", [5]).unwrap();
+        writer.put(&db.vlc, "bb", [4]).unwrap();
+        writer.put(&db.vlc, "cc", []).unwrap();
+        writer.commit().unwrap();
+        let time = |meta: &[u8]| meta.first().map(|t| *t as u64);
+        assert_eq!(db.index_vlc(time).unwrap(), 2);
+        assert_eq!(db.index_vlc(time).unwrap(), 0);
+        let page = db.query_vlc_after(None, 10).unwrap();
+        assert_eq!(page.into_iter().map(|(_, meta)| meta).collect::<Vec<_>>(), vec![vec![4], vec![5]]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn clock_write_index() {
+        let (_dir, db) = temp_db();
+        assert_eq!(db.query_node_clock().unwrap(), None);
+        db.clock_write(vec![1], Some(("aa".to_string(), vec![1]))).unwrap();
+        db.clock_write(vec![2], Some(("aa".to_string(), vec![2]))).unwrap();
+        db.clock_write(vec![3], None).unwrap();
+        assert_eq!(db.query_node_clock().unwrap(), Some(vec![3]));
+        assert_eq!(db.query_clock_by_event_id("aa".to_string()).unwrap(), Some(vec![1]));
+        assert_eq!(db.query_all_event_clock().unwrap(), vec![("aa".to_string(), vec![1])]);
+    }
+
+    #[test]
+    fn skip_write_slots() {
+        let (_dir, db) = temp_db();
+        assert!(db.query_all_skip().unwrap().is_empty());
+        db.skip_write("node2:1".to_string(), vec![1]).unwrap();
+        db.skip_write("node2:1".to_string(), vec![1]).unwrap();
+        db.skip_write("node2:3".to_string(), vec![3]).unwrap();
+        assert_eq!(db.query_all_skip().unwrap(), vec![vec![1], vec![3]]);
+        assert!(!db.has_event("aa".to_string()).unwrap());
+    }
+
+    #[test]
+    fn seq_cursor() {
+        let (_dir, db) = temp_db();
+        assert_eq!(db.query_last_seq().unwrap(), 0);
+        assert!(db.query_seq_after(0, 10).unwrap().is_empty());
+        let ids: Vec<String> = (0..300).map(|i| format!("{:064x}", i)).collect();
+        assert_eq!(db.seq_append(ids[..256].to_vec()).unwrap(), 256);
+        assert_eq!(db.seq_append(ids[256..].to_vec()).unwrap(), 300);
+        assert_eq!(db.query_last_seq().unwrap(), 300);
+
+        let page = db.query_seq_after(0, 2).unwrap();
+        assert_eq!(page, vec![(1, ids[0].clone()), (2, ids[1].clone())]);
+        let page = db.query_seq_after(255, 300).unwrap();
+        assert_eq!(page.len(), 45);
+        assert_eq!(page[0], (256, ids[255].clone()));
+        assert!(db.query_seq_after(300, 10).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,43 @@
+//! Helpers shared by the tests of the storage crate.
+
+use nostr_kv::lmdb::{Transaction, Writer};
+use proto::zchronod::{Clock, Event, TagArray};
+
+use crate::handler::{KindHandler, KindRegistry};
+use crate::{Result, ZchronodDb};
+
+pub(crate) fn tag(values: &[&str]) -> TagArray {
+    TagArray { values: values.iter().map(|v| v.to_string()).collect() }
+}
+
+// an empty db, removed with the returned dir
+pub(crate) fn temp_db() -> (tempfile::TempDir, ZchronodDb) {
+    let dir = tempfile::Builder::new().prefix("zchronod-db").tempdir().unwrap();
+    let db = ZchronodDb::new(dir.path().to_str().unwrap().to_string()).unwrap();
+    (dir, db)
+}
+
+// run `f` in a writer transaction, committed if it passes
+pub(crate) fn in_txn<R>(db: &ZchronodDb, f: impl FnOnce(&mut Writer) -> Result<R>) -> Result<R> {
+    let mut txn = db.writer()?;
+    let result = f(&mut txn)?;
+    txn.commit()?;
+    Ok(result)
+}
+
+pub(crate) fn apply(handler: &dyn KindHandler, db: &ZchronodDb, e: &Event, clock: &Clock) -> Result<()> {
+    in_txn(db, |txn| handler.apply(db, txn, e, clock))
+}
+
+pub(crate) fn observe(handler: &dyn KindHandler, db: &ZchronodDb, e: &Event, clock: &Clock) -> Result<()> {
+    in_txn(db, |txn| handler.observe(db, txn, e, clock))
+}
+
+pub(crate) fn validate(handler: &dyn KindHandler, db: &ZchronodDb, e: &Event) -> Result<()> {
+    handler.validate(db, &db.writer()?, e)
+}
+
+// apply with the handler of the kind and the observers, without storing the event
+pub(crate) fn apply_all(registry: &KindRegistry, db: &ZchronodDb, e: &Event, clock: &Clock) -> Result<()> {
+    in_txn(db, |txn| registry.apply(db, txn, e, clock))
+}